@@ -20,6 +20,14 @@ impl File {
     pub fn from_cap_std(file: cap_std::fs::File) -> Self {
         File(file)
     }
+
+    /// Duplicate the underlying handle.
+    ///
+    /// The returned `File` refers to the same open file description, so it
+    /// shares the file offset and status flags with `self`.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(File(self.0.try_clone()?))
+    }
 }
 
 #[async_trait::async_trait]
@@ -52,13 +60,15 @@ impl WasiFile for File {
         Ok(fdflags)
     }
     async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
-        if fdflags.intersects(
-            wasi_common::file::FdFlags::DSYNC
-                | wasi_common::file::FdFlags::SYNC
-                | wasi_common::file::FdFlags::RSYNC,
-        ) {
-            return Err(Error::invalid_argument().context("cannot set DSYNC, SYNC, or RSYNC flag"));
+        if fdflags.intersects(wasi_common::file::FdFlags::DSYNC | wasi_common::file::FdFlags::SYNC)
+        {
+            return Err(Error::invalid_argument().context("cannot set DSYNC or SYNC flag"));
         }
+        // RSYNC asks for reads to be synchronized to the same degree as DSYNC
+        // or SYNC writes. No platform lets `O_RSYNC` be changed after open, and
+        // without DSYNC or SYNC there is nothing for reads to be synchronized
+        // with, so accept it as a no-op rather than failing the whole call.
+        let fdflags = fdflags & !wasi_common::file::FdFlags::RSYNC;
         let set_fd_flags = self.0.new_set_fd_flags(to_sysif_fdflags(fdflags))?;
         self.0.set_fd_flags(set_fd_flags)?;
         Ok(())
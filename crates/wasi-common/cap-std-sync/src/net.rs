@@ -110,8 +110,9 @@ macro_rules! wasi_listen_write_impl {
                 } else if fdflags.is_empty() {
                     self.0.set_nonblocking(false)?;
                 } else {
+                    // WASI expects ENOTSUP, not EINVAL, for flags a socket can't honor.
                     return Err(
-                        Error::invalid_argument().context("cannot set anything else than NONBLOCK")
+                        Error::not_supported().context("cannot set anything else than NONBLOCK")
                     );
                 }
                 Ok(())
@@ -152,6 +153,11 @@ impl TcpListener {
     pub fn from_cap_std(cap_std: cap_std::net::TcpListener) -> Self {
         TcpListener(cap_std)
     }
+
+    /// Duplicate the underlying socket, sharing the same open file description.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(TcpListener(self.0.try_clone()?))
+    }
 }
 wasi_listen_write_impl!(TcpListener, TcpStream);
 
@@ -163,6 +169,11 @@ impl UnixListener {
     pub fn from_cap_std(cap_std: cap_std::os::unix::net::UnixListener) -> Self {
         UnixListener(cap_std)
     }
+
+    /// Duplicate the underlying socket, sharing the same open file description.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(UnixListener(self.0.try_clone()?))
+    }
 }
 
 #[cfg(unix)]
@@ -197,8 +208,9 @@ macro_rules! wasi_stream_write_impl {
                 } else if fdflags.is_empty() {
                     self.0.set_nonblocking(false)?;
                 } else {
+                    // WASI expects ENOTSUP, not EINVAL, for flags a socket can't honor.
                     return Err(
-                        Error::invalid_argument().context("cannot set anything else than NONBLOCK")
+                        Error::not_supported().context("cannot set anything else than NONBLOCK")
                     );
                 }
                 Ok(())
@@ -327,6 +339,11 @@ impl TcpStream {
     pub fn from_cap_std(socket: cap_std::net::TcpStream) -> Self {
         TcpStream(socket)
     }
+
+    /// Duplicate the underlying socket, sharing the same open file description.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(TcpStream(self.0.try_clone()?))
+    }
 }
 
 wasi_stream_write_impl!(TcpStream, std::net::TcpStream);
@@ -339,6 +356,11 @@ impl UnixStream {
     pub fn from_cap_std(socket: cap_std::os::unix::net::UnixStream) -> Self {
         UnixStream(socket)
     }
+
+    /// Duplicate the underlying socket, sharing the same open file description.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(UnixStream(self.0.try_clone()?))
+    }
 }
 
 #[cfg(unix)]
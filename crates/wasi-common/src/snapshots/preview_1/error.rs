@@ -122,6 +122,24 @@ fn from_raw_os_error(err: Option<i32>) -> Option<Error> {
         RustixErrno::PROTOTYPE => Errno::Prototype.into(),
         RustixErrno::STALE => Errno::Stale.into(),
         RustixErrno::TIMEDOUT => Errno::Timedout.into(),
+        RustixErrno::ROFS => Errno::Rofs.into(),
+        RustixErrno::XDEV => Errno::Xdev.into(),
+        RustixErrno::TXTBSY => Errno::Txtbsy.into(),
+        RustixErrno::NXIO => Errno::Nxio.into(),
+        RustixErrno::NODEV => Errno::Nodev.into(),
+        RustixErrno::SRCH => Errno::Srch.into(),
+        RustixErrno::NOSYS => Errno::Nosys.into(),
+        RustixErrno::NOLCK => Errno::Nolck.into(),
+        RustixErrno::DEADLK => Errno::Deadlk.into(),
+        RustixErrno::CHILD => Errno::Child.into(),
+        RustixErrno::DOM => Errno::Dom.into(),
+        RustixErrno::RANGE => Errno::Range.into(),
+        RustixErrno::NOTTY => Errno::Notty.into(),
+        RustixErrno::NOEXEC => Errno::Noexec.into(),
+        RustixErrno::PROTO => Errno::Proto.into(),
+        RustixErrno::BADMSG => Errno::Badmsg.into(),
+        RustixErrno::IDRM => Errno::Idrm.into(),
+        RustixErrno::NOMSG => Errno::Nomsg.into(),
 
         // On some platforms.into(), these have the same value as other errno values.
         #[allow(unreachable_patterns)]
@@ -155,6 +173,7 @@ fn from_raw_os_error(raw_os_error: Option<i32>) -> Option<Error> {
         Some(Foundation::ERROR_NOT_SUPPORTED) => return Some(Errno::Notsup.into()),
         Some(Foundation::ERROR_FILE_EXISTS) => return Some(Errno::Exist.into()),
         Some(Foundation::ERROR_BROKEN_PIPE) => return Some(Errno::Pipe.into()),
+        Some(Foundation::ERROR_NO_DATA) => return Some(Errno::Pipe.into()),
         Some(Foundation::ERROR_BUFFER_OVERFLOW) => return Some(Errno::Nametoolong.into()),
         Some(Foundation::ERROR_NOT_A_REPARSE_POINT) => return Some(Errno::Inval.into()),
         Some(Foundation::ERROR_NEGATIVE_SEEK) => return Some(Errno::Inval.into()),
@@ -162,6 +181,12 @@ fn from_raw_os_error(raw_os_error: Option<i32>) -> Option<Error> {
         Some(Foundation::ERROR_ALREADY_EXISTS) => return Some(Errno::Exist.into()),
         Some(Foundation::ERROR_STOPPED_ON_SYMLINK) => return Some(Errno::Loop.into()),
         Some(Foundation::ERROR_DIRECTORY_NOT_SUPPORTED) => return Some(Errno::Isdir.into()),
+        Some(Foundation::ERROR_DISK_FULL) => return Some(Errno::Nospc.into()),
+        Some(Foundation::ERROR_HANDLE_DISK_FULL) => return Some(Errno::Nospc.into()),
+        Some(Foundation::ERROR_FILE_TOO_LARGE) => return Some(Errno::Fbig.into()),
+        Some(Foundation::ERROR_DISK_QUOTA_EXCEEDED) => return Some(Errno::Dquot.into()),
+        Some(Foundation::ERROR_WRITE_PROTECT) => return Some(Errno::Rofs.into()),
+        Some(Foundation::ERROR_NOT_SAME_DEVICE) => return Some(Errno::Xdev.into()),
         _ => {}
     }
 
@@ -216,6 +241,20 @@ impl From<std::io::Error> for Error {
                 std::io::ErrorKind::PermissionDenied => Errno::Perm.into(),
                 std::io::ErrorKind::AlreadyExists => Errno::Exist.into(),
                 std::io::ErrorKind::InvalidInput => Errno::Inval.into(),
+                // Errors made by Rust code rather than returned by the OS
+                // have no code, but may still say what went wrong.
+                std::io::ErrorKind::WouldBlock => Errno::Again.into(),
+                std::io::ErrorKind::Interrupted => Errno::Intr.into(),
+                std::io::ErrorKind::TimedOut => Errno::Timedout.into(),
+                std::io::ErrorKind::BrokenPipe => Errno::Pipe.into(),
+                std::io::ErrorKind::ConnectionRefused => Errno::Connrefused.into(),
+                std::io::ErrorKind::ConnectionReset => Errno::Connreset.into(),
+                std::io::ErrorKind::ConnectionAborted => Errno::Connaborted.into(),
+                std::io::ErrorKind::NotConnected => Errno::Notconn.into(),
+                std::io::ErrorKind::AddrInUse => Errno::Addrinuse.into(),
+                std::io::ErrorKind::AddrNotAvailable => Errno::Addrnotavail.into(),
+                std::io::ErrorKind::Unsupported => Errno::Notsup.into(),
+                std::io::ErrorKind::OutOfMemory => Errno::Nomem.into(),
                 _ => Error::trap(anyhow::anyhow!(err).context("Unknown OS error")),
             },
         }
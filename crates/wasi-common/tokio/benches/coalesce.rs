@@ -0,0 +1,42 @@
+//! Compare reading into many tiny iovecs with `readv` against reading into
+//! one scratch buffer and copying out, as `File::with_coalesce_threshold`
+//! does.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::io::{IoSliceMut, Write};
+use wasi_common::WasiFile;
+use wasi_tokio::File;
+
+criterion_group!(benches, bench_coalesce);
+criterion_main!(benches);
+
+// Within Linux's `IOV_MAX` of 1024.
+const IOVECS: usize = 1024;
+const IOVEC_LEN: usize = 16;
+
+fn bench_coalesce(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&[0x5a; IOVECS * IOVEC_LEN]).unwrap();
+
+    let mut group = c.benchmark_group(format!("{} iovecs of {} bytes", IOVECS, IOVEC_LEN));
+    for (name, threshold) in [("readv", None), ("scratch buffer", Some(64))] {
+        let f = File::from_cap_std(cap_std::fs::File::from_std(file.try_clone().unwrap()));
+        let f = match threshold {
+            Some(count) => f.with_coalesce_threshold(count),
+            None => f,
+        };
+        let mut storage = vec![[0u8; IOVEC_LEN]; IOVECS];
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut bufs = storage
+                    .iter_mut()
+                    .map(|s| IoSliceMut::new(&mut s[..]))
+                    .collect::<Vec<_>>();
+                let n = rt.block_on(f.read_vectored_at(&mut bufs, 0)).unwrap();
+                assert_eq!(n as usize, IOVECS * IOVEC_LEN);
+            })
+        });
+    }
+    group.finish();
+}
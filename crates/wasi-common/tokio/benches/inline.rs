@@ -0,0 +1,66 @@
+//! Compare running small and large reads and writes inline on the calling
+//! task with offloading them through `block_in_place`, on either side of
+//! `File::with_inline_threshold`'s default of 64KiB.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io::{IoSlice, IoSliceMut, Write};
+use wasi_common::WasiFile;
+use wasi_tokio::File;
+
+criterion_group!(benches, bench_read, bench_write);
+criterion_main!(benches);
+
+// One op well below the default threshold, and one well above it.
+const SIZES: [usize; 2] = [4 * 1024, 1024 * 1024];
+
+// An inline threshold above every size, and one which offloads them all.
+const THRESHOLDS: [(&str, usize); 2] = [("inline", usize::MAX), ("block_in_place", 0)];
+
+fn file(threshold: usize, contents: &[u8]) -> File {
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(contents).unwrap();
+    File::from_cap_std(cap_std::fs::File::from_std(file)).with_inline_threshold(threshold)
+}
+
+fn bench_read(c: &mut Criterion) {
+    // `block_in_place` needs a multi-threaded runtime.
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("read");
+    for size in SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        for (name, threshold) in THRESHOLDS {
+            let f = file(threshold, &vec![0x5a; size]);
+            let mut buf = vec![0; size];
+            group.bench_function(BenchmarkId::new(name, size), |b| {
+                b.iter(|| {
+                    let n = rt
+                        .block_on(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0))
+                        .unwrap();
+                    assert_eq!(n as usize, size);
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_write(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("write");
+    for size in SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        for (name, threshold) in THRESHOLDS {
+            let f = file(threshold, &[]);
+            let buf = vec![0x5a; size];
+            group.bench_function(BenchmarkId::new(name, size), |b| {
+                b.iter(|| {
+                    let n = rt
+                        .block_on(f.write_vectored_at(&[IoSlice::new(&buf)], 0))
+                        .unwrap();
+                    assert_eq!(n as usize, size);
+                })
+            });
+        }
+    }
+    group.finish();
+}
@@ -0,0 +1,133 @@
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+/// A `WasiFile` wrapper for a guest's audit log: writes can only add to the
+/// end of the inner file, and each line written is also passed to a
+/// host-provided sink, such as one forwarding to syslog.
+///
+/// Every write goes to the current end of the inner file, wherever its
+/// offset is, so a guest can't overwrite earlier entries. Seeks fail with
+/// `ESPIPE`; positional writes and truncation with `EPERM`; reads with
+/// `EBADF`, as on a descriptor opened write-only. Writes through this wrapper
+/// don't interleave with one another, but other writers to the same file
+/// can still get in between them.
+///
+/// The sink is called once for each complete line, including its newline,
+/// after the inner file has accepted it. A trailing unterminated line is
+/// passed on when the guest syncs the file, or when the wrapper is dropped.
+pub struct AuditLog<F: WasiFile> {
+    inner: F,
+    sink: Box<dyn Fn(&[u8]) + Send + Sync>,
+    // The start of a line which hasn't been passed to the sink yet. Writes
+    // hold this for their duration, so they stay in order.
+    partial: Mutex<Vec<u8>>,
+}
+
+impl<F: WasiFile> AuditLog<F> {
+    pub fn new(inner: F, sink: impl Fn(&[u8]) + Send + Sync + 'static) -> Self {
+        AuditLog {
+            inner,
+            sink: Box::new(sink),
+            partial: Mutex::new(Vec::new()),
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    // Pass every complete line in `partial` to the sink.
+    fn mirror_lines(&self, partial: &mut Vec<u8>) {
+        let mut start = 0;
+        while let Some(end) = partial[start..].iter().position(|b| *b == b'\n') {
+            (self.sink)(&partial[start..start + end + 1]);
+            start += end + 1;
+        }
+        partial.drain(..start);
+    }
+
+    fn mirror_rest(&self, partial: &mut Vec<u8>) {
+        if !partial.is_empty() {
+            (self.sink)(partial);
+            partial.clear();
+        }
+    }
+}
+
+impl<F: WasiFile> Drop for AuditLog<F> {
+    fn drop(&mut self) {
+        let mut partial = std::mem::take(self.partial.get_mut());
+        self.mirror_rest(&mut partial);
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for AuditLog<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.mirror_rest(&mut *self.partial.lock().await);
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.mirror_rest(&mut *self.partial.lock().await);
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(self.inner.get_fdflags().await? | FdFlags::APPEND)
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::perm().context("audit log is append-only"))
+    }
+    async fn read_vectored<'a>(&self, _bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        Err(Error::badf().context("audit log is write-only"))
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::badf().context("audit log is write-only"))
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let mut data = Vec::with_capacity(iovec_len(bufs.iter().map(|b| b.len()))?);
+        for buf in bufs {
+            data.extend_from_slice(buf);
+        }
+        let mut partial = self.partial.lock().await;
+        let end = self.inner.get_filestat().await?.size;
+        let n = self
+            .inner
+            .write_vectored_at(&[io::IoSlice::new(&data)], end)
+            .await?;
+        // Only what reached the file is mirrored.
+        partial.extend_from_slice(&data[..n as usize]);
+        self.mirror_lines(&mut partial);
+        Ok(n)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::perm().context("audit log is append-only"))
+    }
+    async fn seek(&self, _pos: io::SeekFrom) -> Result<u64, Error> {
+        Err(Error::seek_pipe().context("audit log is append-only"))
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
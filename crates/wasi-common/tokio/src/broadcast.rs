@@ -0,0 +1,105 @@
+use crate::iovec::iovec_len;
+use bytes::Bytes;
+use std::any::Any;
+use std::io;
+use tokio::sync::broadcast;
+use wasi_common::{
+    file::{FileType, WasiFile},
+    Error,
+};
+
+/// A `WasiFile` which publishes each write on a `tokio::sync::broadcast`
+/// channel, so that one guest's output, such as its stdout, reaches several
+/// independent consumers, such as a live log viewer and a file.
+///
+/// Each `write_vectored` goes out as one message, to every receiver from
+/// [`BroadcastSink::subscribe`] at the time. Writes always succeed in full
+/// without waiting, even with no receivers, in which case the output is
+/// dropped. The channel holds the last `capacity` messages: a receiver which
+/// falls further behind than that misses the oldest ones, and its next
+/// `recv` reports how many with `RecvError::Lagged` before picking up from
+/// the oldest still held, so a slow consumer never holds up the guest or
+/// the others. Reads are always at EOF. Clones publish on the same channel.
+#[derive(Clone)]
+pub struct BroadcastSink {
+    sender: broadcast::Sender<Bytes>,
+}
+
+impl BroadcastSink {
+    /// A sink whose channel holds up to `capacity` messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero, as `broadcast::channel` does.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        BroadcastSink { sender }
+    }
+
+    /// A receiver for everything written from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Bytes> {
+        self.sender.subscribe()
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for BroadcastSink {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::Pipe)
+    }
+    async fn read_vectored<'a>(&self, _bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let mut data = Vec::with_capacity(iovec_len(bufs.iter().map(|b| b.len()))?);
+        for b in bufs {
+            data.extend_from_slice(b);
+        }
+        let n = data.len() as u64;
+        if n > 0 {
+            // Only fails when there's no one to receive it.
+            let _ = self.sender.send(Bytes::from(data));
+        }
+        Ok(n)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BroadcastSink;
+    use std::io::IoSlice;
+    use tokio::sync::broadcast::error::RecvError;
+    use wasi_common::WasiFile;
+
+    #[tokio::test]
+    async fn every_receiver_sees_each_write() {
+        let sink = BroadcastSink::new(2);
+        let mut viewer = sink.subscribe();
+        let mut logger = sink.subscribe();
+
+        sink.write_vectored(&[IoSlice::new(b"hello "), IoSlice::new(b"world")])
+            .await
+            .unwrap();
+        assert_eq!(&viewer.recv().await.unwrap()[..], b"hello world");
+        assert_eq!(&logger.recv().await.unwrap()[..], b"hello world");
+
+        // The viewer keeps up, but the logger falls behind and loses the
+        // oldest write.
+        for line in [&b"one"[..], b"two", b"three"] {
+            sink.write_vectored(&[IoSlice::new(line)]).await.unwrap();
+            assert_eq!(&viewer.recv().await.unwrap()[..], line);
+        }
+        assert!(matches!(logger.recv().await, Err(RecvError::Lagged(1))));
+        assert_eq!(&logger.recv().await.unwrap()[..], b"two");
+        assert_eq!(&logger.recv().await.unwrap()[..], b"three");
+    }
+}
@@ -0,0 +1,297 @@
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// A `WasiFile` wrapper which collects small writes in a user-space buffer
+/// and hands them to the inner file in larger batches.
+///
+/// There are two levels of durability here, mirroring `fflush` and `fsync`:
+///
+/// * [`BufferedFile::flush`] drains the buffer to the inner file with a
+///   single `write_vectored`, making the bytes visible to other readers of
+///   the file without waiting for the disk.
+/// * `datasync` and `sync` flush the buffer and then sync the inner file. The
+///   buffer stays locked until the sync completes, so a concurrent write can
+///   neither slip in between the two nor be reported as synced when it isn't.
+///
+/// The buffer never holds more than its capacity. A write which wouldn't
+/// fit flushes what's already buffered first, and one at least as large as
+/// the capacity then goes straight to the inner file. If that flush fails,
+/// the write returns the error without taking any of its bytes, so a retry
+/// doesn't write them twice.
+///
+/// Every other operation which observes the file's contents or position,
+/// such as reads, seeks, and stats, flushes first.
+///
+/// Anything still buffered when the wrapper is dropped is written out on a
+/// best-effort basis, which only succeeds if the inner file's writes complete
+/// without yielding. Call [`BufferedFile::flush`] before dropping to observe
+/// errors.
+pub struct BufferedFile<F: WasiFile> {
+    inner: F,
+    buf: Mutex<Vec<u8>>,
+    capacity: usize,
+}
+
+impl<F: WasiFile> BufferedFile<F> {
+    pub fn new(inner: F) -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, inner)
+    }
+    pub fn with_capacity(capacity: usize, inner: F) -> Self {
+        BufferedFile {
+            inner,
+            buf: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    /// Write out everything buffered so far, without syncing it to disk.
+    pub async fn flush(&self) -> Result<(), Error> {
+        let mut buf = self.buf.lock().await;
+        self.flush_locked(&mut buf).await
+    }
+
+    async fn flush_locked(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        while !buf.is_empty() {
+            let n = self
+                .inner
+                .write_vectored(&[io::IoSlice::new(&buf[..])])
+                .await?;
+            if n == 0 {
+                return Err(Error::io().context("failed to flush buffered writes"));
+            }
+            buf.drain(..n as usize);
+        }
+        Ok(())
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for BufferedFile<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        let mut buf = self.buf.lock().await;
+        self.flush_locked(&mut buf).await?;
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        let mut buf = self.buf.lock().await;
+        self.flush_locked(&mut buf).await?;
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.flush().await?;
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.flush().await?;
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.flush().await?;
+        self.inner.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.flush().await?;
+        self.inner.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.flush().await?;
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        let mut buf = self.buf.lock().await;
+        if buf.len() + len > self.capacity {
+            self.flush_locked(&mut buf).await?;
+            if len >= self.capacity {
+                return self.inner.write_vectored(bufs).await;
+            }
+        }
+        for b in bufs {
+            buf.extend_from_slice(b);
+        }
+        Ok(len as u64)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.write_vectored_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+impl<F: WasiFile> Drop for BufferedFile<F> {
+    fn drop(&mut self) {
+        let mut buf = std::mem::take(self.buf.get_mut());
+        if !buf.is_empty() {
+            let _ = wiggle::run_in_dummy_executor(self.flush_locked(&mut buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BufferedFile;
+    use std::any::Any;
+    use std::io::IoSlice;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+    use wasi_common::{file::FileType, Error, ErrorExt, WasiFile};
+
+    #[derive(Debug, PartialEq)]
+    enum Op {
+        Write(Vec<u8>),
+        Datasync,
+    }
+
+    // Records the operations it sees, or fails writes while `.1` is set.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Op>>, AtomicBool);
+
+    #[wiggle::async_trait]
+    impl WasiFile for Recorder {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        async fn get_filetype(&self) -> Result<FileType, Error> {
+            Ok(FileType::RegularFile)
+        }
+        async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+            if self.1.load(Ordering::SeqCst) {
+                return Err(Error::io().context("write failed"));
+            }
+            let data = bufs
+                .iter()
+                .flat_map(|b| b.iter().copied())
+                .collect::<Vec<_>>();
+            let n = data.len() as u64;
+            self.0.lock().unwrap().push(Op::Write(data));
+            Ok(n)
+        }
+        async fn datasync(&self) -> Result<(), Error> {
+            self.0.lock().unwrap().push(Op::Datasync);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn datasync_flushes_then_syncs() {
+        let f = BufferedFile::new(Recorder::default());
+        f.write_vectored(&[IoSlice::new(b"hello ")]).await.unwrap();
+        f.write_vectored(&[IoSlice::new(b"world")]).await.unwrap();
+        assert!(f.get_ref().0.lock().unwrap().is_empty());
+
+        f.datasync().await.unwrap();
+        assert_eq!(
+            *f.get_ref().0.lock().unwrap(),
+            [Op::Write(b"hello world".to_vec()), Op::Datasync]
+        );
+    }
+
+    #[tokio::test]
+    async fn overflowing_the_buffer_writes_it_out() {
+        let f = BufferedFile::with_capacity(4, Recorder::default());
+        f.write_vectored(&[IoSlice::new(b"abc")]).await.unwrap();
+        assert!(f.get_ref().0.lock().unwrap().is_empty());
+        f.write_vectored(&[IoSlice::new(b"de")]).await.unwrap();
+        assert_eq!(*f.get_ref().0.lock().unwrap(), [Op::Write(b"abc".to_vec())]);
+
+        // A write no smaller than the buffer bypasses it.
+        f.write_vectored(&[IoSlice::new(b"fghi")]).await.unwrap();
+        assert_eq!(
+            *f.get_ref().0.lock().unwrap(),
+            [
+                Op::Write(b"abc".to_vec()),
+                Op::Write(b"de".to_vec()),
+                Op::Write(b"fghi".to_vec())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_flush_keeps_none_of_the_write() {
+        let f = BufferedFile::with_capacity(4, Recorder::default());
+        f.write_vectored(&[IoSlice::new(b"abc")]).await.unwrap();
+        f.get_ref().1.store(true, Ordering::SeqCst);
+        f.write_vectored(&[IoSlice::new(b"de")])
+            .await
+            .expect_err("flush fails");
+
+        // Retrying writes each byte once.
+        f.get_ref().1.store(false, Ordering::SeqCst);
+        f.write_vectored(&[IoSlice::new(b"de")]).await.unwrap();
+        f.flush().await.unwrap();
+        assert_eq!(
+            *f.get_ref().0.lock().unwrap(),
+            [Op::Write(b"abc".to_vec()), Op::Write(b"de".to_vec())]
+        );
+    }
+}
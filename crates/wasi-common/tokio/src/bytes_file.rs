@@ -0,0 +1,172 @@
+use crate::file::seek_target;
+use std::any::Any;
+use std::io;
+use std::sync::{Arc, Mutex};
+use wasi_common::{
+    file::{FileType, Filestat, WasiFile},
+    Error,
+};
+
+/// A read-only, seekable `WasiFile` whose contents are a byte slice in
+/// memory, for handing a guest embedded assets without touching the
+/// filesystem.
+///
+/// The contents are shared rather than copied, so any number of guests can
+/// each have their own `BytesFile`, with its own offset, over one
+/// `Arc<[u8]>`. Writes, truncation, and the like fail with `EBADF`, as they
+/// would on a descriptor opened read-only.
+pub struct BytesFile {
+    data: Arc<[u8]>,
+    position: Mutex<u64>,
+}
+
+impl BytesFile {
+    pub fn new(data: Arc<[u8]>) -> Self {
+        BytesFile {
+            data,
+            position: Mutex::new(0),
+        }
+    }
+
+    // Copy from `offset` into `bufs`, returning how much was copied.
+    fn read_at(&self, bufs: &mut [io::IoSliceMut<'_>], offset: u64) -> u64 {
+        let start = usize::try_from(offset).map_or(self.data.len(), |o| o.min(self.data.len()));
+        let mut rest = &self.data[start..];
+        let mut n = 0;
+        for buf in bufs {
+            let len = buf.len().min(rest.len());
+            buf[..len].copy_from_slice(&rest[..len]);
+            rest = &rest[len..];
+            n += len as u64;
+        }
+        n
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for BytesFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::RegularFile,
+            nlink: 1,
+            size: self.data.len() as u64,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut position = self.position.lock().unwrap();
+        let n = self.read_at(bufs, *position);
+        *position += n;
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        Ok(self.read_at(bufs, offset))
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let current = *self.position.lock().unwrap();
+        let new = seek_target(pos, current, || async { Ok(self.data.len() as u64) }).await?;
+        *self.position.lock().unwrap() = new;
+        Ok(new)
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        Ok(self.read_at(&mut [io::IoSliceMut::new(buf)], position))
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        Ok((self.data.len() as u64).saturating_sub(position))
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BytesFile;
+    use std::io::{IoSlice, IoSliceMut, SeekFrom};
+    use std::sync::Arc;
+    use wasi_common::{file::FileType, snapshots::preview_1::types::Errno, WasiFile};
+
+    async fn read(f: &BytesFile, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        buf.truncate(n as usize);
+        buf
+    }
+
+    #[tokio::test]
+    async fn seek_and_read() {
+        let data: Arc<[u8]> = Arc::from(&b"embedded asset"[..]);
+        let f = BytesFile::new(data.clone());
+        let other = BytesFile::new(data);
+
+        assert_eq!(read(&f, 8).await, b"embedded");
+        assert_eq!(f.seek(SeekFrom::Current(1)).await.unwrap(), 9);
+        assert_eq!(read(&f, 16).await, b"asset");
+        assert_eq!(read(&f, 16).await, b"");
+        assert_eq!(f.seek(SeekFrom::End(-5)).await.unwrap(), 9);
+        assert_eq!(read(&f, 2).await, b"as");
+        let err = f
+            .seek(SeekFrom::Current(-100))
+            .await
+            .expect_err("before start");
+        assert_eq!(err.downcast().unwrap(), Errno::Inval);
+
+        // Each file has its own offset.
+        assert_eq!(read(&other, 3).await, b"emb");
+    }
+
+    #[tokio::test]
+    async fn positional_reads_past_eof() {
+        let f = BytesFile::new(Arc::from(&b"0123456789"[..]));
+        let (mut a, mut b) = ([0; 3], [0; 3]);
+        let n = f
+            .read_vectored_at(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)], 5)
+            .await
+            .unwrap();
+        assert_eq!(n, 5);
+        assert_eq!((&a, &b[..2]), (b"567", &b"89"[..]));
+
+        for offset in [10, 11, u64::MAX] {
+            let n = f
+                .read_vectored_at(&mut [IoSliceMut::new(&mut a)], offset)
+                .await
+                .unwrap();
+            assert_eq!(n, 0);
+        }
+        // Positional reads don't move the offset.
+        assert_eq!(f.seek(SeekFrom::Current(0)).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn filestat_and_writes() {
+        let f = BytesFile::new(Arc::from(vec![7; 4096]));
+        let stat = f.get_filestat().await.unwrap();
+        assert_eq!(stat.size, 4096);
+        assert_eq!(stat.filetype, FileType::RegularFile);
+
+        let err = f
+            .write_vectored(&[IoSlice::new(b"x")])
+            .await
+            .expect_err("read-only");
+        assert_eq!(err.downcast().unwrap(), Errno::Badf);
+    }
+}
@@ -0,0 +1,82 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+use tokio::sync::Notify;
+use wasi_common::{snapshots::preview_1::types::Errno, Error};
+
+/// A handle a host can use to interrupt a guest's file operations, for
+/// example to enforce a deadline on a misbehaving guest.
+///
+/// Once [`CancellationToken::cancel`] is called, every file the token was
+/// given to fails its operations with `EINTR`: waits for readiness stop
+/// immediately, and no new operation starts. Clones of a token share its
+/// state, so one token can cover all of a guest's files.
+///
+/// A syscall which is already in progress can't be interrupted, and runs to
+/// completion before the guest sees the error. On a hung network filesystem
+/// that may be a long time, though the guest never starts another.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // Register for the wakeup before checking, so a `cancel` in
+            // between the two isn't missed.
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            return Err(interrupted());
+        }
+        Ok(())
+    }
+
+    /// Run `fut`, unless the token is cancelled first.
+    pub(crate) async fn run<T>(
+        &self,
+        fut: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        self.check()?;
+        let cancelled = self.cancelled();
+        tokio::pin!(fut, cancelled);
+        std::future::poll_fn(|cx| {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(interrupted()));
+            }
+            fut.as_mut().poll(cx)
+        })
+        .await
+    }
+}
+
+fn interrupted() -> Error {
+    Error::from(Errno::Intr).context("operation cancelled by the host")
+}
@@ -0,0 +1,90 @@
+use crate::file::File;
+use crate::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use crate::net::{UnixListener, UnixStream};
+use wasi_common::Error;
+
+// Every descriptor these types wrap comes from cap-std or std, which open
+// them with `O_CLOEXEC` or `SOCK_CLOEXEC`, and the reactor registers the
+// descriptor itself rather than a duplicate, so they're all close-on-exec
+// from the start.
+macro_rules! cloexec_impl {
+    ($ty:ty, $field:tt) => {
+        impl $ty {
+            /// Whether the descriptor is closed in a child the host process
+            /// `exec`s, which it is unless [`Self::set_cloexec`] says
+            /// otherwise. Not supported on Windows.
+            pub fn is_cloexec(&self) -> Result<bool, Error> {
+                sys::is_cloexec(&self.$field)
+            }
+
+            /// Set whether the descriptor is closed in a child the host
+            /// process `exec`s, for a host handing the descriptor to a child
+            /// deliberately. Not supported on Windows.
+            ///
+            /// A socket which is left open in a child stays registered with
+            /// the reactor, which follows the open socket rather than the
+            /// descriptor, until every process has closed it: readiness keeps
+            /// being reported to this process, and what the child reads is
+            /// gone when a read here acts on that readiness.
+            pub fn set_cloexec(&self, cloexec: bool) -> Result<(), Error> {
+                sys::set_cloexec(&self.$field, cloexec)
+            }
+        }
+    };
+}
+
+cloexec_impl!(File, inner);
+cloexec_impl!(TcpListener, 0);
+cloexec_impl!(TcpStream, inner);
+#[cfg(unix)]
+cloexec_impl!(UnixListener, 0);
+#[cfg(unix)]
+cloexec_impl!(UnixStream, inner);
+
+#[cfg(unix)]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::Error;
+
+    fn get_fd_flags(fd: &impl AsFd) -> Result<libc::c_int, Error> {
+        let flags = unsafe { libc::fcntl(fd.as_fd().as_raw_fd(), libc::F_GETFD) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(flags)
+    }
+
+    pub(super) fn is_cloexec(fd: &impl AsFd) -> Result<bool, Error> {
+        Ok(get_fd_flags(fd)? & libc::FD_CLOEXEC != 0)
+    }
+
+    pub(super) fn set_cloexec(fd: &impl AsFd, cloexec: bool) -> Result<(), Error> {
+        let flags = get_fd_flags(fd)?;
+        let flags = if cloexec {
+            flags | libc::FD_CLOEXEC
+        } else {
+            flags & !libc::FD_CLOEXEC
+        };
+        let ret = unsafe { libc::fcntl(fd.as_fd().as_raw_fd(), libc::F_SETFD, flags) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use wasi_common::{Error, ErrorExt};
+
+    pub(super) fn is_cloexec<T>(_handle: &T) -> Result<bool, Error> {
+        Err(Error::not_supported().context("close-on-exec is not supported on Windows"))
+    }
+
+    pub(super) fn set_cloexec<T>(_handle: &T, _cloexec: bool) -> Result<(), Error> {
+        Err(Error::not_supported().context("close-on-exec is not supported on Windows"))
+    }
+}
@@ -0,0 +1,219 @@
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+/// A `WasiFile` wrapper which translates line endings between a guest's
+/// Unix-style LF and the CRLF a Windows console expects: LF becomes CRLF on
+/// write, and CRLF becomes LF on read.
+///
+/// Translation is off unless a file is wrapped in this. Line endings which
+/// are already CRLF aren't doubled, even when a write ends with the CR and
+/// the next starts with the LF. Likewise on read, a CR at the end of what the
+/// inner file returned is held back until the next read shows whether an LF
+/// follows, or until EOF.
+///
+/// Writes are all or nothing: the translated bytes are written in full
+/// before returning the guest's length, so a short write can't split a
+/// translation. Translation depends on the order of the bytes, so positional
+/// reads and writes, and seeks, fail with `ESPIPE`.
+pub struct CrlfTranslate<F: WasiFile> {
+    inner: F,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    // Whether the last byte written was a CR.
+    wrote_cr: bool,
+    // Whether the last byte read from the inner file was a CR, not yet
+    // passed on.
+    held_cr: bool,
+    // Translated input which didn't fit in the guest's last read.
+    unread: Vec<u8>,
+}
+
+impl<F: WasiFile> CrlfTranslate<F> {
+    pub fn new(inner: F) -> Self {
+        CrlfTranslate {
+            inner,
+            state: Mutex::default(),
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+// Copy from the front of `src` into `bufs`, returning how much was copied.
+fn scatter(src: &[u8], bufs: &mut [io::IoSliceMut<'_>]) -> usize {
+    let mut n = 0;
+    for buf in bufs {
+        let len = buf.len().min(src.len() - n);
+        buf[..len].copy_from_slice(&src[n..n + len]);
+        n += len;
+    }
+    n
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for CrlfTranslate<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if len == 0 {
+            return Ok(0);
+        }
+        let mut state = self.state.lock().await;
+        while state.unread.is_empty() {
+            let mut raw = vec![0; len];
+            let n = self
+                .inner
+                .read_vectored(&mut [io::IoSliceMut::new(&mut raw)])
+                .await? as usize;
+            if n == 0 {
+                // At EOF, a held CR has nothing left to pair with.
+                if !std::mem::take(&mut state.held_cr) {
+                    return Ok(0);
+                }
+                state.unread.push(b'\r');
+                break;
+            }
+            for &b in &raw[..n] {
+                if std::mem::take(&mut state.held_cr) && b != b'\n' {
+                    state.unread.push(b'\r');
+                }
+                if b == b'\r' {
+                    state.held_cr = true;
+                } else {
+                    state.unread.push(b);
+                }
+            }
+        }
+        let n = scatter(&state.unread, bufs);
+        state.unread.drain(..n);
+        Ok(n as u64)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe().context("line-ending translation needs sequential reads"))
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        let mut state = self.state.lock().await;
+        let mut translated = Vec::with_capacity(len);
+        for &b in bufs.iter().flat_map(|buf| buf.iter()) {
+            if b == b'\n' && !state.wrote_cr {
+                translated.push(b'\r');
+            }
+            translated.push(b);
+            state.wrote_cr = b == b'\r';
+        }
+        let mut rest = &translated[..];
+        while !rest.is_empty() {
+            let n = self.inner.write_vectored(&[io::IoSlice::new(rest)]).await? as usize;
+            if n == 0 {
+                return Err(Error::io().context("inner file accepted no bytes"));
+            }
+            rest = &rest[n..];
+        }
+        Ok(len as u64)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe().context("line-ending translation needs sequential writes"))
+    }
+    async fn seek(&self, _pos: io::SeekFrom) -> Result<u64, Error> {
+        Err(Error::seek_pipe().context("line-ending translation needs sequential access"))
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CrlfTranslate;
+    use crate::{BytesFile, RingCapture};
+    use std::io::{IoSlice, IoSliceMut};
+    use std::sync::Arc;
+    use wasi_common::WasiFile;
+
+    #[tokio::test]
+    async fn writes_translate_lf_once() {
+        let capture = RingCapture::new(64);
+        let f = CrlfTranslate::new(capture.clone());
+        for chunk in [&b"one\ntwo\r"[..], b"\nthree\r\n", b"\n"] {
+            let n = f.write_vectored(&[IoSlice::new(chunk)]).await.unwrap();
+            assert_eq!(n, chunk.len() as u64);
+        }
+        assert_eq!(capture.contents(), b"one\r\ntwo\r\nthree\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn reads_translate_crlf_across_reads() {
+        let inner = BytesFile::new(Arc::from(&b"a\r\nb\r\rc\r"[..]));
+        let f = CrlfTranslate::new(inner);
+        let mut out = Vec::new();
+        // Two bytes at a time, so CRLFs straddle reads of the inner file.
+        loop {
+            let mut buf = [0; 2];
+            let n = f
+                .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                .await
+                .unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n as usize]);
+        }
+        assert_eq!(out, b"a\nb\r\rc\r");
+    }
+}
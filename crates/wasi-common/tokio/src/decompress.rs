@@ -0,0 +1,266 @@
+use crate::file::seek_target;
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+// How much compressed input to read from the inner file at a time.
+const INPUT_CHUNK: usize = 16 * 1024;
+
+/// A streaming decoder, such as gzip or zstd, for [`DecompressReader`] to
+/// run a file's contents through.
+pub trait Decompressor: Send {
+    /// Decode from the front of `input` into the front of `output`,
+    /// returning how many bytes of each were used. `finished` is true once no
+    /// input will follow what's in `input`.
+    ///
+    /// Using no input and producing no output means the decoder needs more
+    /// input, or, if `finished`, that the stream is over. A stream which is
+    /// corrupt, or cut short, should fail, conventionally with `EIO`.
+    fn decompress(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        finished: bool,
+    ) -> Result<(usize, usize), Error>;
+}
+
+/// A `WasiFile` wrapper which presents a compressed file to a guest as its
+/// decompressed contents, so a guest can read a `.gz` or `.zst` asset as if
+/// it were plain.
+///
+/// Decompression is a stream, so the file is too: it can only be read
+/// sequentially, with `read_vectored`. Seeking forward decompresses and
+/// discards up to the new offset; seeking backward, seeking from the end,
+/// and positional reads fail with `ESPIPE`. Writes fail with `EBADF`.
+///
+/// The decompressed size isn't known without decompressing everything, so
+/// `get_filestat` reports a size of 0. A guest which sizes its reads from
+/// `fstat` rather than reading to EOF won't see the contents.
+pub struct DecompressReader<F: WasiFile> {
+    inner: F,
+    state: Mutex<State>,
+}
+
+struct State {
+    decoder: Box<dyn Decompressor>,
+    // Compressed input read from the inner file, of which the first
+    // `consumed` bytes have been decoded.
+    input: Vec<u8>,
+    consumed: usize,
+    eof: bool,
+    done: bool,
+    position: u64,
+}
+
+impl<F: WasiFile> DecompressReader<F> {
+    pub fn new(inner: F, decoder: Box<dyn Decompressor>) -> Self {
+        DecompressReader {
+            inner,
+            state: Mutex::new(State {
+                decoder,
+                input: Vec::new(),
+                consumed: 0,
+                eof: false,
+                done: false,
+                position: 0,
+            }),
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    // Read more compressed input onto the end of what's still undecoded.
+    async fn fill(&self, state: &mut State) -> Result<(), Error> {
+        state.input.drain(..state.consumed);
+        state.consumed = 0;
+        let len = state.input.len();
+        state.input.resize(len + INPUT_CHUNK, 0);
+        let n = match self
+            .inner
+            .read_vectored(&mut [io::IoSliceMut::new(&mut state.input[len..])])
+            .await
+        {
+            Ok(n) => n as usize,
+            Err(e) => {
+                state.input.truncate(len);
+                return Err(e);
+            }
+        };
+        state.input.truncate(len + n);
+        state.eof = n == 0;
+        Ok(())
+    }
+
+    // Decode into `buf`, returning how much was produced. Once something has
+    // been, this returns rather than reading more input, which might block.
+    async fn decode(&self, state: &mut State, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut filled = 0;
+        while filled < buf.len() && !state.done {
+            let (used, produced) = state.decoder.decompress(
+                &state.input[state.consumed..],
+                &mut buf[filled..],
+                state.eof,
+            )?;
+            state.consumed += used;
+            filled += produced;
+            if used == 0 && produced == 0 {
+                if state.eof {
+                    state.done = true;
+                } else if filled > 0 {
+                    break;
+                } else {
+                    self.fill(state).await?;
+                }
+            }
+        }
+        state.position += filled as u64;
+        Ok(filled)
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for DecompressReader<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        let stat = self.inner.get_filestat().await?;
+        Ok(Filestat { size: 0, ..stat })
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut state = self.state.lock().await;
+        let mut n = 0;
+        for buf in bufs {
+            let produced = self.decode(&mut state, buf).await?;
+            n += produced as u64;
+            if produced < buf.len() {
+                break;
+            }
+        }
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe().context("decompressed files can only be read in order"))
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let mut state = self.state.lock().await;
+        let target = seek_target(pos, state.position, || async {
+            Err(Error::seek_pipe().context("decompressed size is unknown"))
+        })
+        .await?;
+        if target < state.position {
+            return Err(Error::seek_pipe().context("cannot seek backward in a decompressed file"));
+        }
+        let mut scratch = vec![0; INPUT_CHUNK];
+        while state.position < target && !state.done {
+            let want = (target - state.position).min(scratch.len() as u64) as usize;
+            self.decode(&mut state, &mut scratch[..want]).await?;
+        }
+        // As with lseek, an offset past the end is fine; reads there hit EOF.
+        state.position = target;
+        Ok(target)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DecompressReader, Decompressor};
+    use crate::BytesFile;
+    use std::io::{IoSliceMut, SeekFrom};
+    use std::sync::Arc;
+    use wasi_common::{snapshots::preview_1::types::Errno, Error, ErrorExt, WasiFile};
+
+    // Run-length decoding of (count, byte) pairs: a codec simple enough to
+    // check by eye, which still has runs spanning reads and a stream that
+    // can be cut short.
+    #[derive(Default)]
+    struct RunLength {
+        run: Option<(u8, u8)>,
+    }
+
+    impl Decompressor for RunLength {
+        fn decompress(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            finished: bool,
+        ) -> Result<(usize, usize), Error> {
+            let (mut used, mut produced) = (0, 0);
+            loop {
+                match self.run {
+                    Some((left, byte)) if produced < output.len() => {
+                        let n = (left as usize).min(output.len() - produced);
+                        output[produced..produced + n].fill(byte);
+                        produced += n;
+                        self.run = Some((left - n as u8, byte)).filter(|(left, _)| *left > 0);
+                    }
+                    Some(_) => break,
+                    None if input.len() - used >= 2 => {
+                        self.run = Some((input[used], input[used + 1]));
+                        used += 2;
+                    }
+                    None => break,
+                }
+            }
+            if finished && input.len() - used == 1 && produced == 0 {
+                return Err(Error::io().context("truncated run"));
+            }
+            Ok((used, produced))
+        }
+    }
+
+    fn reader(compressed: &[u8]) -> DecompressReader<BytesFile> {
+        let inner = BytesFile::new(Arc::from(compressed));
+        DecompressReader::new(inner, Box::new(RunLength::default()))
+    }
+
+    async fn read(f: &DecompressReader<BytesFile>, len: usize) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0; len];
+        let n = f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+        buf.truncate(n as usize);
+        Ok(buf)
+    }
+
+    #[tokio::test]
+    async fn reads_and_seeks_forward() {
+        let f = reader(&[3, b'a', 1, b'-', 5, b'b', 2, b'!']);
+        assert_eq!(read(&f, 2).await.unwrap(), b"aa");
+        assert_eq!(read(&f, 4).await.unwrap(), b"a-bb");
+
+        assert_eq!(f.seek(SeekFrom::Current(2)).await.unwrap(), 8);
+        let err = f.seek(SeekFrom::Start(1)).await.expect_err("backward");
+        assert_eq!(err.downcast().unwrap(), Errno::Spipe);
+        let err = f.seek(SeekFrom::End(0)).await.expect_err("from the end");
+        assert_eq!(err.downcast().unwrap(), Errno::Spipe);
+
+        assert_eq!(read(&f, 16).await.unwrap(), b"b!!");
+        assert_eq!(read(&f, 16).await.unwrap(), b"");
+        assert_eq!(f.get_filestat().await.unwrap().size, 0);
+    }
+
+    #[tokio::test]
+    async fn truncated_stream_is_an_error() {
+        let f = reader(&[2, b'x', 4]);
+        assert_eq!(read(&f, 16).await.unwrap(), b"xx");
+        let err = read(&f, 16).await.expect_err("cut short");
+        assert_eq!(err.downcast().unwrap(), Errno::Io);
+    }
+}
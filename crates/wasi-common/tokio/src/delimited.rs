@@ -0,0 +1,57 @@
+use std::io;
+use wasi_common::{snapshots::preview_1::types::Errno, Error, WasiFile};
+
+// Upper bound on how much we peek at once while scanning for a delimiter.
+const SCAN_CHUNK: usize = 4096;
+
+/// Read from `file` until `delim` has been consumed or `max` bytes have been
+/// read, whichever comes first.
+///
+/// Each step peeks ahead and then consumes only the bytes up to and including
+/// the delimiter, so on a socket nothing past the delimiter is taken out of
+/// the receive queue. An empty result means EOF.
+pub(crate) async fn read_until(
+    file: &dyn WasiFile,
+    delim: u8,
+    max: usize,
+) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    let mut scratch = vec![0; max.min(SCAN_CHUNK)];
+    while out.len() < max {
+        let want = (max - out.len()).min(scratch.len());
+        let peeked = match file.peek(&mut scratch[..want]).await {
+            Ok(n) => n as usize,
+            Err(e) if e.downcast_ref() == Some(&Errno::Again) => {
+                file.readable().await?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if peeked == 0 {
+            break;
+        }
+        let (consume, found) = match scratch[..peeked].iter().position(|b| *b == delim) {
+            Some(ix) => (ix + 1, true),
+            None => (peeked, false),
+        };
+
+        let start = out.len();
+        out.resize(start + consume, 0);
+        let mut filled = start;
+        while filled < start + consume {
+            let n = file
+                .read_vectored(&mut [io::IoSliceMut::new(&mut out[filled..start + consume])])
+                .await?;
+            if n == 0 {
+                // The peer went away between the peek and the read.
+                out.truncate(filled);
+                return Ok(out);
+            }
+            filled += n as usize;
+        }
+        if found {
+            break;
+        }
+    }
+    Ok(out)
+}
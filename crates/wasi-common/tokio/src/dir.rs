@@ -15,6 +15,80 @@ impl Dir {
     }
 }
 
+/// Stat `path`, relative to `dir`, without opening it, as `fstatat` does.
+/// With `follow_symlinks` false, a symlink is described itself rather than
+/// its target.
+///
+/// This is [`WasiDir::get_path_filestat`] for a `cap_std::fs::Dir` the host
+/// holds, run on tokio's blocking thread pool rather than in place. As
+/// there, `path` must stay within `dir`: an absolute path, or one which
+/// escapes through `..` or a symlink, fails with `EPERM`.
+pub async fn statat(
+    dir: &cap_std::fs::Dir,
+    path: &str,
+    follow_symlinks: bool,
+) -> Result<Filestat, Error> {
+    at(dir, path, move |dir, path| {
+        wiggle::run_in_dummy_executor(dir.get_path_filestat(path, follow_symlinks))
+    })
+    .await
+}
+
+/// Create the directory `path`, relative to `dir`, as `mkdirat` does. This
+/// fails with `EEXIST` if anything is already there, even a dangling symlink.
+///
+/// Like [`statat`], this is [`WasiDir::create_dir`] run on tokio's blocking
+/// thread pool, with `path` held within `dir`.
+pub async fn mkdir_at(dir: &cap_std::fs::Dir, path: &str) -> Result<(), Error> {
+    at(dir, path, |dir, path| {
+        wiggle::run_in_dummy_executor(dir.create_dir(path))
+    })
+    .await
+}
+
+/// Remove the empty directory `path`, relative to `dir`, as `unlinkat` with
+/// `AT_REMOVEDIR` does. This fails with `ENOTEMPTY` if the directory still
+/// has entries, and with `ENOTDIR` if `path` is something else.
+///
+/// Like [`statat`], this is [`WasiDir::remove_dir`] run on tokio's blocking
+/// thread pool, with `path` held within `dir`.
+pub async fn rmdir_at(dir: &cap_std::fs::Dir, path: &str) -> Result<(), Error> {
+    at(dir, path, |dir, path| {
+        wiggle::run_in_dummy_executor(dir.remove_dir(path))
+    })
+    .await
+}
+
+/// Remove the file or symlink `path`, relative to `dir`, as `unlinkat` does.
+/// A directory is left alone, and fails with `EISDIR`.
+///
+/// Like [`statat`], this is [`WasiDir::unlink_file`] run on tokio's blocking
+/// thread pool, with `path` held within `dir`.
+pub async fn unlink_at(dir: &cap_std::fs::Dir, path: &str) -> Result<(), Error> {
+    at(dir, path, |dir, path| {
+        wiggle::run_in_dummy_executor(dir.unlink_file(path))
+    })
+    .await
+}
+
+// Run `op` on a sync `Dir` for `dir` on the blocking thread pool. The
+// operations it wraps are synchronous, so the dummy executor always
+// completes them.
+async fn at<T, O>(dir: &cap_std::fs::Dir, path: &str, op: O) -> Result<T, Error>
+where
+    T: Send + 'static,
+    O: FnOnce(&wasi_cap_std_sync::dir::Dir, &str) -> Result<Result<T, Error>, Error>
+        + Send
+        + 'static,
+{
+    let dir = wasi_cap_std_sync::dir::Dir::from_cap_std(dir.try_clone()?);
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || {
+        op(&dir, &path).expect("wrapped operation should be synchronous")
+    })
+    .await?
+}
+
 #[wiggle::async_trait]
 impl WasiDir for Dir {
     fn as_any(&self) -> &dyn Any {
@@ -0,0 +1,64 @@
+use crate::net::TcpStream;
+use std::time::Duration;
+use wasi_common::{snapshots::preview_1::types::Errno, Error, WasiFile};
+
+// How long `flush_to_peer` waits when the stream has no write timeout.
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+// The send queue is checked often at first, backing off to this interval.
+const MAX_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+impl TcpStream {
+    /// Wait until everything written to the connection has been
+    /// acknowledged by the peer, so a host can drain a guest's socket before
+    /// handing it off or closing it.
+    ///
+    /// On Linux this checks the send queue with `SIOCOUTQ`, waiting for the
+    /// socket to be writable and then for a short, growing interval between
+    /// checks. It gives up with `ETIMEDOUT` after the stream's write timeout,
+    /// or 30 seconds if none is set. Elsewhere there's no way to see the send
+    /// queue, so this returns immediately.
+    pub async fn flush_to_peer(&self) -> Result<(), Error> {
+        let timeout = self.write_timeout().unwrap_or(DEFAULT_DRAIN_TIMEOUT);
+        let drain = async {
+            let mut interval = Duration::from_millis(1);
+            while matches!(sys::outq(&self.inner)?, Some(queued) if queued > 0) {
+                self.writable().await?;
+                tokio::time::sleep(interval).await;
+                interval = (interval * 2).min(MAX_CHECK_INTERVAL);
+            }
+            Ok(())
+        };
+        match tokio::time::timeout(timeout, drain).await {
+            Ok(r) => r,
+            Err(_elapsed) => {
+                Err(Error::from(Errno::Timedout).context("send queue did not drain to the peer"))
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::Error;
+
+    // The bytes in the send queue not yet acknowledged by the peer.
+    pub(super) fn outq(fd: impl AsFd) -> Result<Option<usize>, Error> {
+        let mut queued: libc::c_int = 0;
+        if unsafe { libc::ioctl(fd.as_fd().as_raw_fd(), libc::TIOCOUTQ, &mut queued) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Some(queued as usize))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod sys {
+    use wasi_common::Error;
+
+    pub(super) fn outq<F>(_fd: F) -> Result<Option<usize>, Error> {
+        Ok(None)
+    }
+}
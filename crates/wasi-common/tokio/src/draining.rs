@@ -0,0 +1,165 @@
+use crate::net::TcpListener;
+use std::any::Any;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+use tokio::sync::Notify;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error,
+};
+
+/// A handle a host can use to drain a [`DrainingListener`] once the
+/// listener itself has been handed to the guest.
+#[derive(Clone, Default)]
+pub struct DrainHandle(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    draining: AtomicBool,
+    notify: Notify,
+}
+
+impl DrainHandle {
+    /// Stop the listener accepting connections. This can't be undone.
+    pub fn begin_drain(&self) {
+        self.0.draining.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.0.draining.load(Ordering::SeqCst)
+    }
+
+    async fn drained(&self) {
+        loop {
+            // Register for the wakeup before checking, so a `begin_drain` in
+            // between the two isn't missed.
+            let notified = self.0.notify.notified();
+            if self.is_draining() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// A `TcpListener` wrapper which a host can stop accepting connections,
+/// while those already accepted carry on, for a graceful shutdown or a
+/// zero-downtime restart.
+///
+/// Once [`DrainingListener::begin_drain`] is called, `sock_accept` fails
+/// with `ECANCELED`, including an accept already waiting for a connection,
+/// so the guest can tell the listener is done with rather than merely idle.
+/// The listener reports itself readable from then on, so a guest waiting on
+/// it in `poll_oneoff` wakes up to find out. Streams already accepted are
+/// separate descriptors, and are unaffected. Connections still queued are
+/// refused when the host closes the listener.
+pub struct DrainingListener {
+    inner: TcpListener,
+    handle: DrainHandle,
+}
+
+impl DrainingListener {
+    pub fn new(inner: TcpListener) -> Self {
+        DrainingListener {
+            inner,
+            handle: DrainHandle::default(),
+        }
+    }
+    /// A handle which drains this listener, for the host to keep.
+    pub fn handle(&self) -> DrainHandle {
+        self.handle.clone()
+    }
+    pub fn begin_drain(&self) {
+        self.handle.begin_drain()
+    }
+    pub fn get_ref(&self) -> &TcpListener {
+        &self.inner
+    }
+    pub fn into_inner(self) -> TcpListener {
+        self.inner
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.handle.is_draining() {
+            return Err(Error::from(Errno::Canceled).context("listener is draining"));
+        }
+        Ok(())
+    }
+
+    // Wait for a connection to accept, or for draining to begin.
+    async fn ready(&self) -> Result<(), Error> {
+        let drained = self.handle.drained();
+        let readable = self.inner.readable();
+        tokio::pin!(drained, readable);
+        std::future::poll_fn(|cx| {
+            if drained.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Ok(()));
+            }
+            readable.as_mut().poll(cx)
+        })
+        .await
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for DrainingListener {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.ready().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+    #[cfg(not(windows))]
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        loop {
+            self.check()?;
+            match self.inner.accept_now(fdflags).await {
+                Ok((stream, _peer_addr)) => return Ok(Box::new(stream)),
+                // Wait here rather than in the inner listener, which doesn't
+                // know to stop when draining begins. Another task may take
+                // the connection first, so try again after.
+                Err(e) if e.downcast_ref() == Some(&Errno::Again) && !self.inner.1 => {
+                    self.ready().await?
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    // Windows has no reactor readiness to wait on, so an accept there
+    // blocks, and only one which starts after draining begins fails.
+    #[cfg(windows)]
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.check()?;
+        self.inner.sock_accept(fdflags).await
+    }
+}
@@ -0,0 +1,416 @@
+use crate::file::seek_target;
+use crate::iovec::{gather, iovec_len};
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+// The sealed file starts with the little-endian generation which blocks
+// written past the end are sealed with. Truncation raises it past every
+// generation it throws away, so a block written again after a truncation
+// doesn't reuse an old nonce.
+const HEADER_LEN: usize = 4;
+// Each sealed block starts with the little-endian count of times it has been
+// rewritten, which goes into its nonce.
+const GENERATION_LEN: usize = 4;
+
+/// An authenticated cipher, such as AES-GCM or ChaCha20-Poly1305, for
+/// [`EncryptedFile`] to seal and open blocks with.
+///
+/// Nonces are 96 bits: the block's index followed by its generation, both
+/// little-endian. [`EncryptedFile`] never uses the same nonce twice for one
+/// file, but it's up to the caller not to share a key between files.
+pub trait AeadCipher: Send + Sync {
+    /// The length of the tag `seal` appends.
+    fn tag_len(&self) -> usize;
+
+    /// Encrypt `block` in place and append its tag.
+    fn seal(&self, nonce: &[u8; 12], block: &mut Vec<u8>) -> Result<(), Error>;
+
+    /// Check and strip the tag from `block`, and decrypt it in place. This
+    /// should fail, conventionally with `EIO`, if the block doesn't
+    /// authenticate.
+    fn open(&self, nonce: &[u8; 12], block: &mut Vec<u8>) -> Result<(), Error>;
+}
+
+/// A `WasiFile` wrapper which encrypts contents on their way to the inner
+/// file, and decrypts them on their way back, so that data a guest writes is
+/// only ever at rest in encrypted form.
+///
+/// The plaintext is split into fixed-size blocks, each sealed separately
+/// with its own nonce, so positional reads and writes only touch the blocks
+/// they overlap. Writing part of a block reads, decrypts, and rewrites the
+/// whole of it. `get_filestat`, `seek`, and `set_filestat_size` all deal in
+/// plaintext sizes and offsets.
+///
+/// Each block is authenticated on its own, so a tampered block fails to read
+/// with an error from the cipher, but dropping whole blocks from the end of
+/// the file goes unnoticed. Shrinking the file reads the generation of each
+/// block it drops, to keep nonces unique. `FdFlags::APPEND` isn't supported.
+pub struct EncryptedFile<F: WasiFile> {
+    inner: F,
+    cipher: Box<dyn AeadCipher>,
+    block_size: usize,
+    // The plaintext offset. Its lock is held across each read and write, so
+    // a block's read-modify-write can't race with another's.
+    position: Mutex<u64>,
+}
+
+impl<F: WasiFile> EncryptedFile<F> {
+    pub fn new(inner: F, cipher: Box<dyn AeadCipher>) -> Self {
+        EncryptedFile {
+            inner,
+            cipher,
+            block_size: DEFAULT_BLOCK_SIZE,
+            position: Mutex::new(0),
+        }
+    }
+    /// Use blocks of `block_size` plaintext bytes rather than the default of
+    /// 4 KiB. This must match the size the file was written with.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert!(block_size > 0, "block size must be nonzero");
+        self.block_size = block_size;
+        self
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn overhead(&self) -> u64 {
+        (GENERATION_LEN + self.cipher.tag_len()) as u64
+    }
+
+    fn sealed_block_size(&self) -> u64 {
+        self.block_size as u64 + self.overhead()
+    }
+
+    fn block_offset(&self, index: u64) -> u64 {
+        HEADER_LEN as u64 + index * self.sealed_block_size()
+    }
+
+    fn plaintext_size(&self, size: u64) -> Result<u64, Error> {
+        if size == 0 {
+            return Ok(0);
+        }
+        let size = size
+            .checked_sub(HEADER_LEN as u64)
+            .ok_or_else(|| Error::io().context("encrypted file ends in its header"))?;
+        let full = size / self.sealed_block_size();
+        let rest = size % self.sealed_block_size();
+        if rest != 0 && rest <= self.overhead() {
+            return Err(Error::io().context("encrypted file ends in a partial block"));
+        }
+        Ok(full * self.block_size as u64 + rest.saturating_sub(self.overhead()))
+    }
+
+    fn sealed_size(&self, size: u64) -> u64 {
+        let full = size / self.block_size as u64;
+        let rest = size % self.block_size as u64;
+        let last = if rest == 0 { 0 } else { rest + self.overhead() };
+        HEADER_LEN as u64 + full * self.sealed_block_size() + last
+    }
+
+    async fn size(&self) -> Result<u64, Error> {
+        self.plaintext_size(self.inner.get_filestat().await?.size)
+    }
+
+    async fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), Error> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let n = self
+                .inner
+                .read_vectored_at(
+                    &mut [io::IoSliceMut::new(&mut buf[filled..])],
+                    offset + filled as u64,
+                )
+                .await?;
+            if n == 0 {
+                return Err(Error::io().context("encrypted file is truncated"));
+            }
+            filled += n as usize;
+        }
+        Ok(())
+    }
+
+    async fn write_all_at(&self, data: &[u8], offset: u64) -> Result<(), Error> {
+        let mut written = 0;
+        while written < data.len() {
+            let n = self
+                .inner
+                .write_vectored_at(
+                    &[io::IoSlice::new(&data[written..])],
+                    offset + written as u64,
+                )
+                .await?;
+            if n == 0 {
+                return Err(Error::io().context("short write to encrypted file"));
+            }
+            written += n as usize;
+        }
+        Ok(())
+    }
+
+    // The generation for blocks written past the end, or `None` if the file
+    // is empty and has no header yet.
+    async fn floor(&self) -> Result<Option<u32>, Error> {
+        if self.inner.get_filestat().await?.size == 0 {
+            return Ok(None);
+        }
+        let mut header = [0; HEADER_LEN];
+        self.read_exact_at(&mut header, 0).await?;
+        Ok(Some(u32::from_le_bytes(header)))
+    }
+
+    async fn read_generation(&self, index: u64) -> Result<u32, Error> {
+        let mut generation = [0; GENERATION_LEN];
+        self.read_exact_at(&mut generation, self.block_offset(index))
+            .await?;
+        Ok(u32::from_le_bytes(generation))
+    }
+
+    // Read and open block `index` of a file `size` bytes long, returning its
+    // generation and plaintext, or `None` if it's past the end.
+    async fn read_block(&self, index: u64, size: u64) -> Result<Option<(u32, Vec<u8>)>, Error> {
+        let start = index * self.block_size as u64;
+        if start >= size {
+            return Ok(None);
+        }
+        let len = (size - start).min(self.block_size as u64) as usize;
+        let mut sealed = vec![0; len + self.overhead() as usize];
+        self.read_exact_at(&mut sealed, self.block_offset(index))
+            .await?;
+        let mut generation = [0; GENERATION_LEN];
+        generation.copy_from_slice(&sealed[..GENERATION_LEN]);
+        let generation = u32::from_le_bytes(generation);
+        let mut block = sealed.split_off(GENERATION_LEN);
+        self.cipher.open(&nonce(index, generation), &mut block)?;
+        if block.len() != len {
+            return Err(Error::io().context("decrypted block has the wrong length"));
+        }
+        Ok(Some((generation, block)))
+    }
+
+    async fn write_block(
+        &self,
+        index: u64,
+        generation: u32,
+        mut block: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.cipher.seal(&nonce(index, generation), &mut block)?;
+        let mut sealed = generation.to_le_bytes().to_vec();
+        sealed.extend_from_slice(&block);
+        self.write_all_at(&sealed, self.block_offset(index)).await
+    }
+
+    async fn read_at(&self, bufs: &mut [io::IoSliceMut<'_>], offset: u64) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))? as u64;
+        let size = self.size().await?;
+        let end = offset.saturating_add(len).min(size);
+        if offset >= end {
+            return Ok(0);
+        }
+        let block_size = self.block_size as u64;
+        let mut data = Vec::with_capacity((end - offset) as usize);
+        for index in offset / block_size..=(end - 1) / block_size {
+            let start = index * block_size;
+            let (_, block) = self.read_block(index, size).await?.unwrap_or_default();
+            let lo = (offset.max(start) - start) as usize;
+            let hi = (end.min(start + block_size) - start) as usize;
+            data.extend_from_slice(&block[lo..hi]);
+        }
+        let mut rest = &data[..];
+        for buf in bufs {
+            let n = buf.len().min(rest.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            rest = &rest[n..];
+        }
+        Ok(data.len() as u64)
+    }
+
+    // Write `data` at `offset`, first filling any gap between the current end
+    // of the file and `offset` with zeros.
+    async fn write_at(&self, data: &[u8], offset: u64) -> Result<(), Error> {
+        let size = self.size().await?;
+        let end = offset
+            .checked_add(data.len() as u64)
+            .ok_or_else(|| Error::overflow().context("write past the largest file offset"))?;
+        let begin = offset.min(size);
+        if begin >= end {
+            return Ok(());
+        }
+        let floor = match self.floor().await? {
+            Some(floor) => floor,
+            None => {
+                self.write_all_at(&0u32.to_le_bytes(), 0).await?;
+                0
+            }
+        };
+        let block_size = self.block_size as u64;
+        for index in begin / block_size..=(end - 1) / block_size {
+            let start = index * block_size;
+            let (generation, mut block) = match self.read_block(index, size).await? {
+                Some((generation, block)) => {
+                    let generation = generation.checked_add(1).ok_or_else(|| {
+                        Error::overflow().context("encrypted block rewritten too many times")
+                    })?;
+                    (generation, block)
+                }
+                None => (floor, Vec::new()),
+            };
+            let len = (end - start).min(block_size) as usize;
+            if block.len() < len {
+                block.resize(len, 0);
+            }
+            let lo = offset.max(start);
+            let hi = end.min(start + block_size);
+            if lo < hi {
+                block[(lo - start) as usize..(hi - start) as usize]
+                    .copy_from_slice(&data[(lo - offset) as usize..(hi - offset) as usize]);
+            }
+            self.write_block(index, generation, block).await?;
+        }
+        Ok(())
+    }
+}
+
+fn nonce(index: u64, generation: u32) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&index.to_le_bytes());
+    nonce[8..].copy_from_slice(&generation.to_le_bytes());
+    nonce
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for EncryptedFile<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        if flags.contains(FdFlags::APPEND) {
+            return Err(Error::not_supported().context("cannot append to an encrypted file"));
+        }
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        let stat = self.inner.get_filestat().await?;
+        Ok(Filestat {
+            size: self.plaintext_size(stat.size)?,
+            ..stat
+        })
+    }
+    async fn set_filestat_size(&self, new_size: u64) -> Result<(), Error> {
+        let _position = self.position.lock().await;
+        let size = self.size().await?;
+        if new_size > size {
+            return self.write_at(&[], new_size).await;
+        }
+        if new_size == size {
+            return Ok(());
+        }
+        let block_size = self.block_size as u64;
+        let index = new_size / block_size;
+
+        // Raise the floor past the generations of the blocks being dropped
+        // before dropping them, so none of their nonces come round again.
+        let floor = self.floor().await?.unwrap_or(0);
+        let mut raised = floor;
+        let first_dropped = index + u64::from(new_size % block_size != 0);
+        for dropped in first_dropped..=(size - 1) / block_size {
+            let generation = self.read_generation(dropped).await?;
+            raised = raised.max(generation.checked_add(1).ok_or_else(|| {
+                Error::overflow().context("encrypted block rewritten too many times")
+            })?);
+        }
+        if raised != floor {
+            self.write_all_at(&raised.to_le_bytes(), 0).await?;
+        }
+
+        if new_size % block_size != 0 {
+            // The new last block is cut short, so reseal it at its new length.
+            if let Some((generation, mut block)) = self.read_block(index, size).await? {
+                let generation = generation.checked_add(1).ok_or_else(|| {
+                    Error::overflow().context("encrypted block rewritten too many times")
+                })?;
+                block.truncate((new_size % block_size) as usize);
+                self.write_block(index, generation, block).await?;
+            }
+        }
+        self.inner
+            .set_filestat_size(self.sealed_size(new_size))
+            .await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut position = self.position.lock().await;
+        let n = self.read_at(bufs, *position).await?;
+        *position += n;
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let _position = self.position.lock().await;
+        self.read_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let data = gather(bufs)?;
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let mut position = self.position.lock().await;
+        self.write_at(&data, *position).await?;
+        *position += data.len() as u64;
+        Ok(data.len() as u64)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let data = gather(bufs)?;
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let _position = self.position.lock().await;
+        self.write_at(&data, offset).await?;
+        Ok(data.len() as u64)
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let mut position = self.position.lock().await;
+        *position = seek_target(pos, *position, || self.size()).await?;
+        Ok(*position)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
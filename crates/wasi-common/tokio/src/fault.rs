@@ -0,0 +1,384 @@
+use crate::gated::FileOps;
+use crate::iovec::{capped, capped_mut};
+use std::any::Any;
+use std::io;
+use std::sync::Mutex;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error, SystemTimeSpec,
+};
+
+/// Which failures a [`FaultInjector`] injects, and when.
+///
+/// A policy is a list of rules, each covering some [`FileOps`]. Every rule
+/// counts the operations it covers, and the first rule which fires for an
+/// operation decides its fault; rules fire either on the nth operation they
+/// cover, or at random with some probability. The randomness comes from a
+/// generator seeded by [`FaultPolicy::new`], so a seed replays the same
+/// faults for the same sequence of operations.
+#[derive(Clone, Debug)]
+pub struct FaultPolicy {
+    seed: u64,
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    ops: FileOps,
+    trigger: Trigger,
+    fault: Fault,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Trigger {
+    Nth(u64),
+    Probability(f64),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Fault {
+    Fail(Errno),
+    Short,
+}
+
+impl FaultPolicy {
+    /// A policy with no rules yet, drawing its randomness from `seed`.
+    pub fn new(seed: u64) -> Self {
+        FaultPolicy {
+            seed,
+            rules: Vec::new(),
+        }
+    }
+    /// Fail the `n`th of the `ops` operations, counting from 1, with `errno`.
+    pub fn fail_nth(mut self, ops: FileOps, n: u64, errno: Errno) -> Self {
+        self.rules.push(Rule {
+            ops,
+            trigger: Trigger::Nth(n),
+            fault: Fault::Fail(errno),
+        });
+        self
+    }
+    /// Fail each of the `ops` operations with `errno` with the given
+    /// probability, between 0 and 1.
+    pub fn fail_with_probability(mut self, ops: FileOps, probability: f64, errno: Errno) -> Self {
+        self.rules.push(Rule {
+            ops,
+            trigger: Trigger::Probability(probability),
+            fault: Fault::Fail(errno),
+        });
+        self
+    }
+    /// Cut each of the `ops` reads and writes short with the given
+    /// probability, between 0 and 1, so it transfers at least one byte but
+    /// fewer than it was asked to. Other operations, and transfers of a
+    /// single byte, are let through whole.
+    pub fn short_with_probability(mut self, ops: FileOps, probability: f64) -> Self {
+        self.rules.push(Rule {
+            ops,
+            trigger: Trigger::Probability(probability),
+            fault: Fault::Short,
+        });
+        self
+    }
+}
+
+/// A `WasiFile` wrapper which injects failures into the operations a guest
+/// makes, as chosen by a [`FaultPolicy`], for a test harness exercising the
+/// guest's error handling.
+///
+/// A failed operation never reaches the inner file. Operations which only
+/// describe the file, namely `get_filetype`, `get_fdflags`, `isatty`, and
+/// readiness, are never failed, as with [`crate::Gated`].
+pub struct FaultInjector<F: WasiFile> {
+    inner: F,
+    policy: FaultPolicy,
+    state: Mutex<State>,
+}
+
+struct State {
+    rng: u64,
+    // How many operations each rule has seen.
+    counts: Vec<u64>,
+}
+
+impl State {
+    // splitmix64, which is plenty for picking faults.
+    fn next(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    // A uniform draw from [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+enum Decision {
+    Pass,
+    Fail(Errno),
+    // Transfer at most this many bytes.
+    Short(u64),
+}
+
+impl<F: WasiFile> FaultInjector<F> {
+    pub fn new(inner: F, policy: FaultPolicy) -> Self {
+        let state = State {
+            rng: policy.seed,
+            counts: vec![0; policy.rules.len()],
+        };
+        FaultInjector {
+            inner,
+            policy,
+            state: Mutex::new(state),
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    // Decide the fault, if any, for an operation of kind `op` transferring
+    // `len` bytes.
+    fn decide(&self, op: FileOps, len: usize) -> Decision {
+        let mut state = self.state.lock().unwrap();
+        let mut decision = Decision::Pass;
+        // Every rule covering the operation counts it and draws from the
+        // generator, even once one has fired, so rules don't perturb each
+        // other's sequences.
+        for (i, rule) in self.policy.rules.iter().enumerate() {
+            if !rule.ops.intersects(op) {
+                continue;
+            }
+            state.counts[i] += 1;
+            let fires = match rule.trigger {
+                Trigger::Nth(n) => state.counts[i] == n,
+                Trigger::Probability(p) => state.next_f64() < p,
+            };
+            if !fires || !matches!(decision, Decision::Pass) {
+                continue;
+            }
+            decision = match rule.fault {
+                Fault::Fail(errno) => Decision::Fail(errno),
+                Fault::Short if len > 1 => Decision::Short(1 + state.next() % (len as u64 - 1)),
+                Fault::Short => Decision::Pass,
+            };
+        }
+        decision
+    }
+
+    fn check(&self, op: FileOps) -> Result<(), Error> {
+        match self.decide(op, 0) {
+            Decision::Fail(errno) => Err(injected(errno)),
+            _ => Ok(()),
+        }
+    }
+
+    // Decide the fault for a transfer, returning how many bytes it may move.
+    fn check_transfer(&self, op: FileOps, len: usize) -> Result<usize, Error> {
+        match self.decide(op, len) {
+            Decision::Pass => Ok(len),
+            Decision::Fail(errno) => Err(injected(errno)),
+            Decision::Short(n) => Ok(n as usize),
+        }
+    }
+}
+
+fn injected(errno: Errno) -> Error {
+    Error::from(errno).context("injected fault")
+}
+
+fn total_len<T: std::ops::Deref<Target = [u8]>>(bufs: &[T]) -> usize {
+    bufs.iter().map(|b| b.len()).sum()
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for FaultInjector<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.check(FileOps::SOCKET)?;
+        self.inner.sock_accept(fdflags).await
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        let limit = self.check_transfer(FileOps::READ, total_len(ri_data))?;
+        let mut capped = capped_mut(ri_data, limit);
+        self.inner.sock_recv(&mut capped, ri_flags).await
+    }
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[io::IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        let limit = self.check_transfer(FileOps::WRITE, total_len(si_data))?;
+        self.inner
+            .sock_send(&capped(si_data, limit), si_flags)
+            .await
+    }
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.check(FileOps::SOCKET)?;
+        self.inner.sock_shutdown(how).await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.check(FileOps::SYNC)?;
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.check(FileOps::SYNC)?;
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.check(FileOps::SET_FDFLAGS)?;
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.check(FileOps::GET_STAT)?;
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.check(FileOps::SET_SIZE)?;
+        self.inner.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.check(FileOps::ADVISE)?;
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.check(FileOps::ALLOCATE)?;
+        self.inner.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.check(FileOps::SET_TIMES)?;
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let limit = self.check_transfer(FileOps::READ, total_len(bufs))?;
+        let mut capped = capped_mut(bufs, limit);
+        self.inner.read_vectored(&mut capped).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let limit = self.check_transfer(FileOps::READ, total_len(bufs))?;
+        let mut capped = capped_mut(bufs, limit);
+        self.inner.read_vectored_at(&mut capped, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let limit = self.check_transfer(FileOps::WRITE, total_len(bufs))?;
+        self.inner.write_vectored(&capped(bufs, limit)).await
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let limit = self.check_transfer(FileOps::WRITE, total_len(bufs))?;
+        self.inner
+            .write_vectored_at(&capped(bufs, limit), offset)
+            .await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.check(FileOps::SEEK)?;
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        let limit = self.check_transfer(FileOps::READ, buf.len())?;
+        self.inner.peek(&mut buf[..limit]).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FaultInjector, FaultPolicy};
+    use crate::{FileOps, NullFile};
+    use std::io::{IoSlice, IoSliceMut};
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    #[tokio::test]
+    async fn fails_the_nth_read() {
+        let policy = FaultPolicy::new(0).fail_nth(FileOps::READ, 3, Errno::Timedout);
+        let f = FaultInjector::new(NullFile::new(), policy);
+        let mut buf = [0; 4];
+        for n in 1..=4 {
+            let result = f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await;
+            if n == 3 {
+                assert_eq!(result.unwrap_err().downcast().unwrap(), Errno::Timedout);
+            } else {
+                result.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_every_sync() {
+        let policy = FaultPolicy::new(0).fail_with_probability(FileOps::SYNC, 1.0, Errno::Io);
+        let f = FaultInjector::new(NullFile::new(), policy);
+        let err = f.datasync().await.unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Io);
+        let err = f.sync().await.unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Io);
+        f.write_vectored(&[IoSlice::new(b"still fine")])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn short_writes_are_reproducible() {
+        async fn lengths(seed: u64) -> Vec<u64> {
+            let policy = FaultPolicy::new(seed).short_with_probability(FileOps::WRITE, 0.5);
+            let f = FaultInjector::new(NullFile::new(), policy);
+            let mut lengths = Vec::new();
+            for _ in 0..32 {
+                lengths.push(f.write_vectored(&[IoSlice::new(&[0; 100])]).await.unwrap());
+            }
+            lengths
+        }
+        let first = lengths(42).await;
+        assert_eq!(first, lengths(42).await);
+        assert!(first.iter().all(|n| (1..=100).contains(n)));
+        assert!(first.iter().any(|n| *n < 100));
+        assert!(first.iter().any(|n| *n == 100));
+    }
+}
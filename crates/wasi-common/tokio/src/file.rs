@@ -1,93 +1,744 @@
 use crate::block_on_dummy_executor;
+use crate::cancel::CancellationToken;
+use crate::file_reader::FileReader;
+use crate::ioprio::{IoPriority, IoPriorityClass};
+use crate::iovec::{capped_mut, iovec_len};
 #[cfg(windows)]
 use io_extras::os::windows::{AsRawHandleOrSocket, RawHandleOrSocket};
 #[cfg(not(windows))]
 use io_lifetimes::AsFd;
 use std::any::Any;
 use std::borrow::Borrow;
+use std::future::Future;
 use std::io;
+use std::sync::{Arc, Mutex};
+#[cfg(not(windows))]
+use wasi_common::snapshots::preview_1::types::Errno;
 use wasi_common::{
     file::{Advice, FdFlags, FileType, Filestat, WasiFile},
-    Error,
+    Error, ErrorExt,
 };
 
-pub struct File(wasi_cap_std_sync::file::File);
+const DEFAULT_INLINE_THRESHOLD: usize = 64 * 1024;
+// The largest read which goes through a scratch buffer when coalescing, so
+// the host never allocates more than this for a guest's read.
+const MAX_COALESCE_LEN: usize = 64 * 1024;
+
+bitflags::bitflags! {
+    /// What [`File::sync_range`] does with the range, as with the flags to
+    /// Linux's `sync_file_range`.
+    pub struct SyncRangeFlags: u32 {
+        /// Wait for writeback of the range which was already under way.
+        const WAIT_BEFORE = 0b1;
+        /// Start writeback of the range's dirty pages.
+        const WRITE       = 0b10;
+        /// Wait for writeback of the range to finish.
+        const WAIT_AFTER  = 0b100;
+    }
+}
+
+pub struct File {
+    pub(crate) inner: wasi_cap_std_sync::file::File,
+    max_read_bytes: Option<usize>,
+    inline_threshold: usize,
+    coalesce_threshold: Option<usize>,
+    cancel: Option<CancellationToken>,
+    io_priority: Option<IoPriority>,
+    // Shared with every `try_clone` of this file, since they share an offset.
+    position: Arc<Mutex<Position>>,
+    // Held across each operation which uses and moves the offset, so
+    // concurrent ones act as if they ran one after another. Shared like
+    // `position`.
+    cursor: Arc<tokio::sync::Mutex<()>>,
+    // False for pipes, sockets, and character devices, which have no offset.
+    seekable: bool,
+    // A duplicate of the descriptor for byte-range locking to use on a
+    // blocking thread, made on first use. It's kept until this file and all
+    // its clones are dropped, since closing any descriptor for the file drops
+    // the process's locks on it.
+    pub(crate) lock_handle: Arc<Mutex<Option<Arc<wasi_cap_std_sync::file::File>>>>,
+}
+
+// The file offset as last observed through this file or one of its clones.
+struct Position {
+    // `None` until the first query, and again after an append-mode write,
+    // since only a syscall can tell where that left the offset.
+    offset: Option<u64>,
+    append: bool,
+}
 
 impl File {
-    pub(crate) fn from_inner(file: wasi_cap_std_sync::file::File) -> Self {
-        File(file)
+    pub(crate) fn from_inner(inner: wasi_cap_std_sync::file::File) -> Self {
+        let seekable = is_seekable(&inner);
+        // Whether writes append has to be known before the first seek, which
+        // records the offset, and a write after it, which would otherwise
+        // move it on from there.
+        let append = matches!(
+            wiggle::run_in_dummy_executor(inner.get_fdflags()),
+            Ok(Ok(fdflags)) if fdflags.contains(FdFlags::APPEND)
+        );
+        File {
+            inner,
+            max_read_bytes: None,
+            inline_threshold: DEFAULT_INLINE_THRESHOLD,
+            coalesce_threshold: None,
+            cancel: None,
+            io_priority: None,
+            position: Arc::new(Mutex::new(Position {
+                offset: None,
+                append,
+            })),
+            cursor: Arc::default(),
+            seekable,
+            lock_handle: Arc::default(),
+        }
     }
     pub fn from_cap_std(file: cap_std::fs::File) -> Self {
         Self::from_inner(wasi_cap_std_sync::file::File::from_cap_std(file))
     }
-}
 
-pub struct TcpListener(wasi_cap_std_sync::net::TcpListener);
+    /// Never read more than `max` bytes in a single `read_vectored` or
+    /// `read_vectored_at` call, however large the guest's iovecs are. Reads
+    /// are unlimited by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero, since every read would then look like EOF.
+    pub fn with_max_read_bytes(mut self, max: usize) -> Self {
+        assert_ne!(max, 0, "max_read_bytes must be nonzero");
+        self.max_read_bytes = Some(max);
+        self
+    }
 
-impl TcpListener {
-    pub(crate) fn from_inner(listener: wasi_cap_std_sync::net::TcpListener) -> Self {
-        TcpListener(listener)
+    /// Run reads and writes of fewer than `bytes` bytes directly on the
+    /// calling task, and only tell tokio that larger ones block. The default
+    /// is 64KiB.
+    ///
+    /// Handing a worker thread's other tasks off to another thread, as
+    /// `block_in_place` does, costs more than a small read or write of a
+    /// regular file usually takes, since those are typically served from the
+    /// page cache. The tradeoff is that an operation run inline stalls the
+    /// worker's other tasks if it does end up waiting on the disk or a slow
+    /// network filesystem, so hosts on such storage may want a lower
+    /// threshold; zero restores offloading every operation.
+    pub fn with_inline_threshold(mut self, bytes: usize) -> Self {
+        self.inline_threshold = bytes;
+        self
     }
-    pub fn from_cap_std(listener: cap_std::net::TcpListener) -> Self {
-        Self::from_inner(wasi_cap_std_sync::net::TcpListener::from_cap_std(listener))
+
+    /// Serve reads given more than `count` buffers by reading into one
+    /// scratch buffer of their total size and copying the data out, rather
+    /// than passing them all to `readv`. Off by default.
+    ///
+    /// The kernel does work per buffer, which for a guest passing hundreds
+    /// of tiny ones can cost more than the copy; the `coalesce` benchmark
+    /// compares the two. Reads of more than 64KiB in total always go to
+    /// `readv`, which keeps the scratch buffer small whatever the guest asks
+    /// for.
+    pub fn with_coalesce_threshold(mut self, count: usize) -> Self {
+        self.coalesce_threshold = Some(count);
+        self
     }
-}
 
-pub struct TcpStream(wasi_cap_std_sync::net::TcpStream);
+    /// Fail this file's operations with `EINTR` once `token` is cancelled.
+    /// See [`CancellationToken`] for what can and can't be interrupted.
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
 
-impl TcpStream {
-    pub(crate) fn from_inner(stream: wasi_cap_std_sync::net::TcpStream) -> Self {
-        TcpStream(stream)
+    /// Run this file's reads, writes, and syncs at the I/O priority `class`
+    /// and `level`, from 0, the most urgent, to 7, so a host can keep a batch
+    /// guest's disk access from starving everyone else's.
+    ///
+    /// Linux only has per-thread I/O priorities, so the priority is set on
+    /// whichever thread runs each operation, and that thread's own is put
+    /// back after. Only schedulers which support priorities, such as BFQ,
+    /// heed them, and buffered writes reach the disk later from the kernel's
+    /// writeback threads, so it's reads, syncs, and direct I/O which are
+    /// held back. Fails with `EINVAL` for a level past 7, `EPERM` for
+    /// [`IoPriorityClass::RealTime`] without `CAP_SYS_ADMIN`, and `ENOTSUP`
+    /// on other platforms.
+    pub fn set_io_priority(&mut self, class: IoPriorityClass, level: u8) -> Result<(), Error> {
+        self.io_priority = Some(IoPriority::new(class, level)?);
+        Ok(())
     }
-    pub fn from_cap_std(stream: cap_std::net::TcpStream) -> Self {
-        Self::from_inner(wasi_cap_std_sync::net::TcpStream::from_cap_std(stream))
+
+    /// Duplicate this file's descriptor, as with POSIX `dup`.
+    ///
+    /// The returned file refers to the same open file description as `self`,
+    /// so the two share a file offset: a seek or read through one moves the
+    /// position observed by the other. Opening the same path a second time is
+    /// what yields an independent offset. Either descriptor may be closed
+    /// without affecting the other.
+    ///
+    /// Reads, writes, and seeks which use the shared offset take turns, across
+    /// the original and all its clones, so concurrent reads never see the same
+    /// bytes twice or skip any; the positional `_at` operations don't wait.
+    pub fn try_clone(&self) -> Result<Box<dyn WasiFile>, Error> {
+        let inner = self.inner.try_clone()?;
+        Ok(Box::new(File {
+            inner,
+            max_read_bytes: self.max_read_bytes,
+            inline_threshold: self.inline_threshold,
+            coalesce_threshold: self.coalesce_threshold,
+            cancel: self.cancel.clone(),
+            io_priority: self.io_priority,
+            position: self.position.clone(),
+            cursor: self.cursor.clone(),
+            seekable: self.seekable,
+            lock_handle: self.lock_handle.clone(),
+        }))
     }
-}
 
-#[cfg(unix)]
-pub struct UnixListener(wasi_cap_std_sync::net::UnixListener);
+    /// The current file offset, as `lseek(fd, 0, SEEK_CUR)` would report it.
+    ///
+    /// The offset is tracked across reads, writes, and seeks through this
+    /// file and its [`File::try_clone`]s, and left alone by the positional
+    /// `_at` operations, so this is usually answered without a syscall. The
+    /// first call does a real seek to learn the offset, as does the first
+    /// call after a write in `APPEND` mode. Movements made through descriptors
+    /// this `File` doesn't know about, such as a `dup` made by the host before
+    /// wrapping it, are not seen.
+    pub async fn stream_position(&self) -> Result<u64, Error> {
+        self.check_seekable()?;
+        if let Some(offset) = self.position.lock().unwrap().offset {
+            return Ok(offset);
+        }
+        // A read or write in flight would move the offset after the seek
+        // learns it, with nothing yet recorded for it to move on from, so
+        // wait for it to finish first.
+        let _cursor = self.cursor.lock().await;
+        if let Some(offset) = self.position.lock().unwrap().offset {
+            return Ok(offset);
+        }
+        let offset = block_on_dummy_executor(|| self.inner.seek(io::SeekFrom::Current(0)))?;
+        self.position.lock().unwrap().offset = Some(offset);
+        Ok(offset)
+    }
 
-#[cfg(unix)]
-impl UnixListener {
-    pub(crate) fn from_inner(listener: wasi_cap_std_sync::net::UnixListener) -> Self {
-        UnixListener(listener)
+    // Record that a read or write moved the offset forward by `n` bytes.
+    fn advance(&self, n: u64, is_write: bool) {
+        let mut position = self.position.lock().unwrap();
+        if is_write && position.append {
+            position.offset = None;
+        } else if let Some(offset) = position.offset {
+            // Past `u64::MAX` the offset can't be tracked, so forget it.
+            position.offset = offset.checked_add(n);
+        }
+    }
+
+    /// Read up to and including the next `delim` byte, or until `max` bytes
+    /// have been read. Returns an empty buffer at EOF.
+    pub async fn read_until(&self, delim: u8, max: usize) -> Result<Vec<u8>, Error> {
+        crate::delimited::read_until(self, delim, max).await
+    }
+
+    /// Read into `bufs` as `read_vectored` does, but return how many bytes
+    /// landed in each buffer rather than the total. A short read fills the
+    /// buffers in order, so the counts are a run of full buffers, at most one
+    /// partly filled one, and zeros for the rest.
+    pub async fn read_vectored_filled(
+        &self,
+        bufs: &mut [io::IoSliceMut<'_>],
+    ) -> Result<Vec<usize>, Error> {
+        let mut left = self.read_vectored(bufs).await? as usize;
+        Ok(bufs
+            .iter()
+            .map(|buf| {
+                let n = buf.len().min(left);
+                left -= n;
+                n
+            })
+            .collect())
+    }
+
+    /// Write the dirty pages in `len` bytes from `offset` back to disk, as
+    /// `flags` says, on tokio's blocking thread pool. A `len` of zero means
+    /// through the end of the file.
+    ///
+    /// This is `sync_file_range` on Linux, which only concerns the file's
+    /// data: it doesn't flush the file's metadata, such as its size or the
+    /// extents allocated to a newly written range, or the disk's write
+    /// cache, so even with every flag set, the data isn't durable until an
+    /// `fdatasync` or `fsync`. It's for starting writeback early, or pacing
+    /// it, ahead of that. Elsewhere, this is `fdatasync` of the whole file,
+    /// whatever the flags.
+    pub async fn sync_range(
+        &self,
+        offset: u64,
+        len: u64,
+        flags: SyncRangeFlags,
+    ) -> Result<(), Error> {
+        self.check_cancelled()?;
+        let file = self.inner.try_clone()?;
+        let io_priority = self.io_priority;
+        tokio::task::spawn_blocking(move || {
+            prioritized(io_priority, || sync_file_range(&file, offset, len, flags))
+        })
+        .await?
+    }
+
+    /// A `tokio::io::AsyncRead` and `AsyncSeek` view of this file, for use
+    /// with tokio's utilities. See [`FileReader`] for how it behaves.
+    pub fn as_async_read(&self) -> FileReader<'_> {
+        FileReader::new(self)
+    }
+
+    fn check_cancelled(&self) -> Result<(), Error> {
+        match &self.cancel {
+            Some(token) => token.check(),
+            None => Ok(()),
+        }
+    }
+
+    // Fail with `ESPIPE` if the file has no offset to seek or read or write
+    // at, rather than leave it to the device, which may ignore the offset.
+    fn check_seekable(&self) -> Result<(), Error> {
+        if self.seekable {
+            Ok(())
+        } else {
+            Err(Error::seek_pipe().context("file is not seekable"))
+        }
     }
-    pub fn from_cap_std(listener: cap_std::os::unix::net::UnixListener) -> Self {
-        Self::from_inner(wasi_cap_std_sync::net::UnixListener::from_cap_std(listener))
+
+    // Run a read or write of `len` bytes, inline if it is small enough.
+    fn block_on_sized<'a, F, Fut, T>(&self, len: usize, f: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> Fut + Send + 'a,
+        Fut: Future<Output = Result<T, Error>>,
+        T: Send + 'static,
+    {
+        prioritized(self.io_priority, || {
+            if len < self.inline_threshold {
+                crate::run_inline(f)
+            } else {
+                block_on_dummy_executor(f)
+            }
+        })
+    }
+
+    // Read into `bufs`, at `offset` if there is one, going through a scratch
+    // buffer if there are more of them than the coalescing threshold, and
+    // they're small enough in total.
+    fn read_bufs(
+        &self,
+        bufs: &mut [io::IoSliceMut<'_>],
+        offset: Option<u64>,
+    ) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        let coalesce =
+            self.coalesce_threshold.map_or(false, |t| bufs.len() > t) && len <= MAX_COALESCE_LEN;
+        if !coalesce {
+            return match offset {
+                None => self.block_on_sized(len, move || self.inner.read_vectored(bufs)),
+                Some(offset) => {
+                    self.block_on_sized(len, move || self.inner.read_vectored_at(bufs, offset))
+                }
+            };
+        }
+        let mut scratch = vec![0; len];
+        let n = {
+            let scratch = &mut [io::IoSliceMut::new(&mut scratch)][..];
+            match offset {
+                None => self.block_on_sized(len, move || self.inner.read_vectored(scratch))?,
+                Some(offset) => {
+                    self.block_on_sized(len, move || self.inner.read_vectored_at(scratch, offset))?
+                }
+            }
+        };
+        let mut data = &scratch[..n as usize];
+        for buf in bufs.iter_mut() {
+            if data.is_empty() {
+                break;
+            }
+            let take = buf.len().min(data.len());
+            buf[..take].copy_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+        Ok(n)
+    }
+
+    // Shorten `bufs` so they hold no more than `max_read_bytes` in total.
+    fn capped_bufs<'b>(&self, bufs: &'b mut [io::IoSliceMut<'_>]) -> Vec<io::IoSliceMut<'b>> {
+        capped_mut(bufs, self.max_read_bytes.unwrap_or(usize::MAX))
     }
 }
 
-#[cfg(unix)]
-pub struct UnixStream(wasi_cap_std_sync::net::UnixStream);
+// Run `op` under `io_priority`, if there is one.
+fn prioritized<T>(
+    io_priority: Option<IoPriority>,
+    op: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    match io_priority {
+        Some(io_priority) => io_priority.run(op),
+        None => op(),
+    }
+}
 
-#[cfg(unix)]
-impl UnixStream {
-    fn from_inner(stream: wasi_cap_std_sync::net::UnixStream) -> Self {
-        UnixStream(stream)
+/// Where a seek to `pos` from `current` lands, for files which keep their
+/// own offset. As with `lseek`, the offset may go past the end, but not
+/// before the start, nor past `u64::MAX`; those are `EINVAL`. `end` gives
+/// the file's size, and is only called for a seek from the end, since
+/// finding it may take I/O.
+pub(crate) async fn seek_target<Fut>(
+    pos: io::SeekFrom,
+    current: u64,
+    end: impl FnOnce() -> Fut,
+) -> Result<u64, Error>
+where
+    Fut: Future<Output = Result<u64, Error>>,
+{
+    let target = match pos {
+        io::SeekFrom::Start(offset) => Some(offset),
+        io::SeekFrom::End(delta) => end().await?.checked_add_signed(delta),
+        io::SeekFrom::Current(delta) => current.checked_add_signed(delta),
+    };
+    target.ok_or_else(|| Error::invalid_argument().context("seek out of range"))
+}
+
+#[cfg(target_os = "linux")]
+fn sync_file_range(
+    file: &wasi_cap_std_sync::file::File,
+    offset: u64,
+    len: u64,
+    flags: SyncRangeFlags,
+) -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+    let offset = i64::try_from(offset).map_err(|_| Error::invalid_argument())?;
+    let len = i64::try_from(len).map_err(|_| Error::invalid_argument())?;
+    let mut raw = 0;
+    for (flag, bit) in [
+        (
+            SyncRangeFlags::WAIT_BEFORE,
+            libc::SYNC_FILE_RANGE_WAIT_BEFORE,
+        ),
+        (SyncRangeFlags::WRITE, libc::SYNC_FILE_RANGE_WRITE),
+        (SyncRangeFlags::WAIT_AFTER, libc::SYNC_FILE_RANGE_WAIT_AFTER),
+    ] {
+        if flags.contains(flag) {
+            raw |= bit;
+        }
     }
-    pub fn from_cap_std(stream: cap_std::os::unix::net::UnixStream) -> Self {
-        Self::from_inner(wasi_cap_std_sync::net::UnixStream::from_cap_std(stream))
+    let ret = unsafe { libc::sync_file_range(file.as_fd().as_raw_fd(), offset, len, raw) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error().into());
     }
+    Ok(())
 }
 
-pub struct Stdin(wasi_cap_std_sync::stdio::Stdin);
+#[cfg(not(target_os = "linux"))]
+fn sync_file_range(
+    file: &wasi_cap_std_sync::file::File,
+    _offset: u64,
+    _len: u64,
+    _flags: SyncRangeFlags,
+) -> Result<(), Error> {
+    wiggle::run_in_dummy_executor(file.datasync()).expect("wrapped operation should be synchronous")
+}
 
-pub fn stdin() -> Stdin {
-    Stdin(wasi_cap_std_sync::stdio::stdin())
+// Whether `file` has an offset: pipes, sockets, and character devices don't.
+// If that can't be told, leave it to the syscalls to fail.
+#[cfg(not(windows))]
+fn is_seekable(file: &wasi_cap_std_sync::file::File) -> bool {
+    use rustix::fs::FileType;
+    match rustix::fs::fstat(file.as_fd()) {
+        Ok(stat) => !matches!(
+            FileType::from_raw_mode(stat.st_mode),
+            FileType::Fifo | FileType::Socket | FileType::CharacterDevice
+        ),
+        Err(_) => true,
+    }
+}
+
+#[cfg(windows)]
+fn is_seekable(file: &wasi_cap_std_sync::file::File) -> bool {
+    !matches!(
+        wiggle::run_in_dummy_executor(file.get_filetype()),
+        Ok(Ok(FileType::CharacterDevice))
+    )
+}
+
+#[wiggle::async_trait]
+impl WasiFile for File {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        Some(self.inner.as_fd())
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        Some(self.inner.as_raw_handle_or_socket())
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.check_cancelled()?;
+        prioritized(self.io_priority, || {
+            block_on_dummy_executor(|| self.inner.datasync())
+        })
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.check_cancelled()?;
+        prioritized(self.io_priority, || {
+            block_on_dummy_executor(|| self.inner.sync())
+        })
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        block_on_dummy_executor(|| self.inner.get_filetype())
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        block_on_dummy_executor(|| self.inner.get_fdflags())
+    }
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        block_on_dummy_executor(|| self.inner.set_fdflags(fdflags))?;
+        self.position.lock().unwrap().append = fdflags.contains(FdFlags::APPEND);
+        Ok(())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        block_on_dummy_executor(|| self.inner.get_filestat())
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.check_cancelled()?;
+        block_on_dummy_executor(move || self.inner.set_filestat_size(size))
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        block_on_dummy_executor(move || self.inner.advise(offset, len, advice))
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.check_cancelled()?;
+        block_on_dummy_executor(move || self.inner.allocate(offset, len))
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.check_cancelled()?;
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if len == 0 {
+            return Ok(0);
+        }
+        let _cursor = self.cursor.lock().await;
+        let n = if self.max_read_bytes.is_none() {
+            self.read_bufs(bufs, None)?
+        } else {
+            self.read_bufs(&mut self.capped_bufs(bufs), None)?
+        };
+        self.advance(n, false);
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.check_cancelled()?;
+        self.check_seekable()?;
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if len == 0 {
+            return Ok(0);
+        }
+        if self.max_read_bytes.is_none() {
+            self.read_bufs(bufs, Some(offset))
+        } else {
+            self.read_bufs(&mut self.capped_bufs(bufs), Some(offset))
+        }
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.check_cancelled()?;
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if len == 0 {
+            return Ok(0);
+        }
+        let _cursor = self.cursor.lock().await;
+        let n = self.block_on_sized(len, move || self.inner.write_vectored(bufs))?;
+        self.advance(n, true);
+        Ok(n)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.check_cancelled()?;
+        self.check_seekable()?;
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if len == 0 {
+            return Ok(0);
+        }
+        self.block_on_sized(len, move || self.inner.write_vectored_at(bufs, offset))
+    }
+    async fn seek(&self, pos: std::io::SeekFrom) -> Result<u64, Error> {
+        self.check_cancelled()?;
+        self.check_seekable()?;
+        let _cursor = self.cursor.lock().await;
+        let offset = block_on_dummy_executor(move || self.inner.seek(pos))?;
+        self.position.lock().unwrap().offset = Some(offset);
+        Ok(offset)
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.check_cancelled()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.block_on_sized(buf.len(), move || self.inner.peek(buf))
+    }
+    async fn set_times(
+        &self,
+        atime: Option<wasi_common::SystemTimeSpec>,
+        mtime: Option<wasi_common::SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.check_cancelled()?;
+        block_on_dummy_executor(move || self.inner.set_times(atime, mtime))
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+
+    #[cfg(not(windows))]
+    async fn readable(&self) -> Result<(), Error> {
+        match &self.cancel {
+            Some(token) => token.run(wait_readable(self.inner.as_fd())).await,
+            None => wait_readable(self.inner.as_fd()).await,
+        }
+    }
+
+    #[cfg(not(windows))]
+    async fn writable(&self) -> Result<(), Error> {
+        match &self.cancel {
+            Some(token) => token.run(wait_writable(self.inner.as_fd())).await,
+            None => wait_writable(self.inner.as_fd()).await,
+        }
+    }
+}
+
+#[cfg(windows)]
+impl AsRawHandleOrSocket for File {
+    #[inline]
+    fn as_raw_handle_or_socket(&self) -> RawHandleOrSocket {
+        self.inner.as_raw_handle_or_socket()
+    }
 }
 
 pub struct Stdout(wasi_cap_std_sync::stdio::Stdout);
 
+/// The host's standard output, for a guest to write to.
+///
+/// A guest piped into something like `head` writes on after the reader has
+/// gone. On Unix, such a write raises `SIGPIPE`, which kills the process
+/// unless it's ignored, as a Rust `main` does but other hosts may not. A host
+/// which wants the guest to see `EPIPE` instead should ignore the signal
+/// itself, or call [`ignore_sigpipe`] before the guest runs.
 pub fn stdout() -> Stdout {
     Stdout(wasi_cap_std_sync::stdio::stdout())
 }
 
 pub struct Stderr(wasi_cap_std_sync::stdio::Stderr);
 
+/// The host's standard error. Writes after the reader has gone raise
+/// `SIGPIPE`, as with [`stdout`].
 pub fn stderr() -> Stderr {
     Stderr(wasi_cap_std_sync::stdio::stderr())
 }
 
+/// Ignore `SIGPIPE` if it's still set to kill the process, so that a guest's
+/// write to stdout, stderr, or another pipe whose reader has gone fails with
+/// `EPIPE` instead. A handler the host installed itself is left alone.
+///
+/// Signal dispositions are process-wide, so this is never done implicitly;
+/// it's for the host to call, if it hasn't ignored the signal already.
+/// Sockets don't need it, since writes to them never raise the signal.
+///
+/// Windows has no `SIGPIPE`, so this does nothing there.
+#[cfg(unix)]
+pub fn ignore_sigpipe() {
+    static IGNORE: std::sync::Once = std::sync::Once::new();
+    IGNORE.call_once(|| unsafe {
+        let mut old = std::mem::zeroed::<libc::sigaction>();
+        if libc::sigaction(libc::SIGPIPE, std::ptr::null(), &mut old) == 0
+            && old.sa_sigaction == libc::SIG_DFL
+        {
+            libc::signal(libc::SIGPIPE, libc::SIG_IGN);
+        }
+    });
+}
+
+#[cfg(windows)]
+pub fn ignore_sigpipe() {}
+
+// The Inner impls OwnsRaw, which asserts exclusive use of the handle by the owned object.
+// AsyncFd needs to wrap an owned `impl std::os::unix::io::AsRawFd`. Rather than introduce
+// mutability to let it own the `Inner`, we are depending on the `&mut self` bound on the
+// async methods calling these to ensure this is the only Future which can access the RawFd
+// during the lifetime of the AsyncFd.
+//
+// Since the AsyncFd only wraps the raw fd, a failed registration leaves the descriptor with
+// its owner, open and usable for another attempt.
+#[cfg(not(windows))]
+fn register(
+    fd: rustix::fd::BorrowedFd<'_>,
+    interest: tokio::io::Interest,
+) -> Result<Option<tokio::io::unix::AsyncFd<std::os::unix::io::RawFd>>, Error> {
+    use std::os::unix::io::AsRawFd;
+    match tokio::io::unix::AsyncFd::with_interest(fd.as_raw_fd(), interest) {
+        Ok(asyncfd) => Ok(Some(asyncfd)),
+        // if e is EPERM, this file isnt supported by epoll because it is immediately
+        // available:
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Ok(None),
+        Err(e) => Err(registration_error(e)),
+    }
+}
+
+// Registration fails when the kernel refuses to watch any more descriptors. Report this
+// as `ENFILE`, so a host can tell that it's near a limit, and shed load or raise the
+// limit, rather than seeing whatever the kernel happened to return. Tokio's own errors
+// carry no OS error code, and the one a wait starting during runtime shutdown gets is
+// indistinguishable from the others, so they are all treated as shutdown, as they are
+// by `readiness_error`.
+#[cfg(not(windows))]
+fn registration_error(e: std::io::Error) -> Error {
+    match e.raw_os_error() {
+        Some(code) if [libc::EMFILE, libc::ENFILE, libc::ENOSPC, libc::ENOMEM].contains(&code) => {
+            Error::from(Errno::Nfile).context(format!("reactor cannot register descriptor: {}", e))
+        }
+        _ => readiness_error(e),
+    }
+}
+
+// Once a descriptor is registered, tokio only fails a wait on it when the
+// reactor it was registered with has shut down, as it does with its runtime.
+// Report this as `ECANCELED`, so a host can tell a wait which was cut short by
+// shutdown from one which failed.
+#[cfg(not(windows))]
+fn readiness_error(e: std::io::Error) -> Error {
+    if e.raw_os_error().is_none() {
+        Error::from(Errno::Canceled).context(format!("reactor shut down: {}", e))
+    } else {
+        e.into()
+    }
+}
+
+// Each wait registers the descriptor afresh, and the registration is dropped
+// with the wait, so readiness is never carried over from one wait to the
+// next, and there is none to clear. Adding a descriptor to epoll reports its
+// readiness as of that moment, so data a guest left unread after a partial
+// read is seen by its next wait, and a drained descriptor waits for more.
+#[cfg(not(windows))]
+pub(crate) async fn wait_readable(fd: rustix::fd::BorrowedFd<'_>) -> Result<(), Error> {
+    if let Some(asyncfd) = register(fd, tokio::io::Interest::READABLE)? {
+        let _ = asyncfd.readable().await.map_err(readiness_error)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub(crate) async fn wait_writable(fd: rustix::fd::BorrowedFd<'_>) -> Result<(), Error> {
+    if let Some(asyncfd) = register(fd, tokio::io::Interest::WRITABLE)? {
+        let _ = asyncfd.writable().await.map_err(readiness_error)?;
+    }
+    Ok(())
+}
+
 macro_rules! wasi_file_impl {
     ($ty:ty) => {
         #[wiggle::async_trait]
@@ -175,50 +826,12 @@ macro_rules! wasi_file_impl {
 
             #[cfg(not(windows))]
             async fn readable(&self) -> Result<(), Error> {
-                // The Inner impls OwnsRaw, which asserts exclusive use of the handle by the owned object.
-                // AsyncFd needs to wrap an owned `impl std::os::unix::io::AsRawFd`. Rather than introduce
-                // mutability to let it own the `Inner`, we are depending on the `&mut self` bound on this
-                // async method to ensure this is the only Future which can access the RawFd during the
-                // lifetime of the AsyncFd.
-                use std::os::unix::io::AsRawFd;
-                use tokio::io::{unix::AsyncFd, Interest};
-                let rawfd = self.0.borrow().as_fd().as_raw_fd();
-                match AsyncFd::with_interest(rawfd, Interest::READABLE) {
-                    Ok(asyncfd) => {
-                        let _ = asyncfd.readable().await?;
-                        Ok(())
-                    }
-                    Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
-                        // if e is EPERM, this file isnt supported by epoll because it is immediately
-                        // available for reading:
-                        Ok(())
-                    }
-                    Err(e) => Err(e.into()),
-                }
+                crate::file::wait_readable(self.0.borrow().as_fd()).await
             }
 
             #[cfg(not(windows))]
             async fn writable(&self) -> Result<(), Error> {
-                // The Inner impls OwnsRaw, which asserts exclusive use of the handle by the owned object.
-                // AsyncFd needs to wrap an owned `impl std::os::unix::io::AsRawFd`. Rather than introduce
-                // mutability to let it own the `Inner`, we are depending on the `&mut self` bound on this
-                // async method to ensure this is the only Future which can access the RawFd during the
-                // lifetime of the AsyncFd.
-                use std::os::unix::io::AsRawFd;
-                use tokio::io::{unix::AsyncFd, Interest};
-                let rawfd = self.0.borrow().as_fd().as_raw_fd();
-                match AsyncFd::with_interest(rawfd, Interest::WRITABLE) {
-                    Ok(asyncfd) => {
-                        let _ = asyncfd.writable().await?;
-                        Ok(())
-                    }
-                    Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
-                        // if e is EPERM, this file isnt supported by epoll because it is immediately
-                        // available for writing:
-                        Ok(())
-                    }
-                    Err(e) => Err(e.into()),
-                }
+                crate::file::wait_writable(self.0.borrow().as_fd()).await
             }
 
             async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
@@ -235,13 +848,57 @@ macro_rules! wasi_file_impl {
     };
 }
 
-wasi_file_impl!(File);
-wasi_file_impl!(TcpListener);
-wasi_file_impl!(TcpStream);
-#[cfg(unix)]
-wasi_file_impl!(UnixListener);
-#[cfg(unix)]
-wasi_file_impl!(UnixStream);
-wasi_file_impl!(Stdin);
 wasi_file_impl!(Stdout);
 wasi_file_impl!(Stderr);
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::{readiness_error, registration_error, wait_readable};
+    use io_lifetimes::AsFd;
+    use std::io;
+    use wasi_common::snapshots::preview_1::types::Errno;
+
+    #[test]
+    fn full_reactor_is_reported_as_nfile() {
+        for e in [
+            io::Error::from_raw_os_error(libc::ENOSPC),
+            io::Error::from_raw_os_error(libc::EMFILE),
+        ] {
+            let err = registration_error(e);
+            assert_eq!(err.downcast().unwrap(), Errno::Nfile);
+        }
+
+        let err = registration_error(io::Error::from_raw_os_error(libc::EBADF));
+        assert_eq!(err.downcast().unwrap(), Errno::Badf);
+    }
+
+    // A wait which only starts once its runtime is shutting down fails to
+    // register, rather than failing a registration it already had.
+    #[test]
+    fn registering_during_shutdown_is_canceled() {
+        let (r, _w) = std::os::unix::net::UnixStream::pair().unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let handle = rt.handle().clone();
+        drop(rt);
+        let _enter = handle.enter();
+        let err = wiggle::run_in_dummy_executor(wait_readable(r.as_fd()))
+            .expect("fails without waiting")
+            .expect_err("the reactor is gone");
+        assert_eq!(err.downcast().unwrap(), Errno::Canceled);
+    }
+
+    #[test]
+    fn runtime_shutdown_is_reported_as_canceled() {
+        let err = readiness_error(io::Error::new(
+            io::ErrorKind::Other,
+            "IO driver has terminated",
+        ));
+        assert_eq!(err.downcast().unwrap(), Errno::Canceled);
+
+        let err = readiness_error(io::Error::from_raw_os_error(libc::EBADF));
+        assert_eq!(err.downcast().unwrap(), Errno::Badf);
+    }
+}
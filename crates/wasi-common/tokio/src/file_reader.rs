@@ -0,0 +1,130 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use wasi_common::{Error, WasiFile};
+
+type Op<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+enum State<'a> {
+    Idle,
+    Reading(Op<'a, Vec<u8>>),
+    Seeking(Op<'a, u64>),
+}
+
+/// A `tokio::io::AsyncRead` and `AsyncSeek` view of a `WasiFile`, so that
+/// host code can use tokio's utilities, such as `AsyncBufReadExt::lines`, on
+/// a guest's file.
+///
+/// Reads go through `read_vectored` and seeks through `seek`, so they share
+/// the file's offset with the guest. A read which returns `Pending` stays in
+/// flight, sized for the buffer it was first polled with, and the next poll
+/// must pass a buffer with at least as much room; a seek can't start while a
+/// read is in flight, or the other way around. Those misuses, and errors from
+/// the file, are reported as [`io::ErrorKind::Other`], the latter with the
+/// original error inside.
+pub struct FileReader<'a> {
+    file: &'a dyn WasiFile,
+    state: State<'a>,
+}
+
+impl<'a> FileReader<'a> {
+    pub fn new(file: &'a dyn WasiFile) -> Self {
+        FileReader {
+            file,
+            state: State::Idle,
+        }
+    }
+}
+
+fn to_io_error(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+impl AsyncRead for FileReader<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        if !matches!(self.state, State::Reading(_)) {
+            if let State::Seeking(_) = self.state {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "read while a seek is in progress",
+                )));
+            }
+            let file = self.file;
+            let len = buf.remaining();
+            // The read can't borrow `buf`, which only lives for this poll.
+            self.state = State::Reading(Box::pin(async move {
+                let mut data = vec![0; len];
+                let n = file
+                    .read_vectored(&mut [io::IoSliceMut::new(&mut data)])
+                    .await?;
+                data.truncate(n as usize);
+                Ok(data)
+            }));
+        }
+        let result = match &mut self.state {
+            State::Reading(op) => match op.as_mut().poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            },
+            _ => unreachable!(),
+        };
+        self.state = State::Idle;
+        let data = result.map_err(to_io_error)?;
+        // A read started by an earlier poll may have had a larger buffer.
+        if data.len() > buf.remaining() {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Other,
+                "read buffer shrank while a read was in flight",
+            )));
+        }
+        buf.put_slice(&data);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for FileReader<'_> {
+    fn start_seek(mut self: Pin<&mut Self>, pos: io::SeekFrom) -> io::Result<()> {
+        if !matches!(self.state, State::Idle) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "seek while another operation is in progress",
+            ));
+        }
+        let file = self.file;
+        self.state = State::Seeking(Box::pin(async move { file.seek(pos).await }));
+        Ok(())
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        if matches!(self.state, State::Idle) {
+            // No seek was started, so report where the file is.
+            let file = self.file;
+            self.state = State::Seeking(Box::pin(async move {
+                file.seek(io::SeekFrom::Current(0)).await
+            }));
+        }
+        let result = match &mut self.state {
+            State::Seeking(op) => match op.as_mut().poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            },
+            _ => {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "seek while a read is in progress",
+                )))
+            }
+        };
+        self.state = State::Idle;
+        Poll::Ready(result.map_err(to_io_error))
+    }
+}
@@ -0,0 +1,166 @@
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, WasiFile},
+    Error, ErrorExt,
+};
+
+/// A `WasiFile` wrapper which frames a guest's output for a raw socket: each
+/// `write_vectored` is one message, and goes to the inner file prefixed with
+/// its length as a big-endian `u32`.
+///
+/// Every write is framed as a whole, across all of its iovecs, and is all or
+/// nothing: the prefix and message are written in full before the guest's
+/// length is returned, so a short write can't leave a partial frame on the
+/// wire. Frames from concurrent writes don't interleave. A zero-length write
+/// sends nothing, and a message longer than `u32::MAX` fails with
+/// `EOVERFLOW`. Positional writes and seeks would break the framing, so they
+/// fail with `ESPIPE`. Reads, positional reads, and peeks are passed
+/// through unframed, as are `datasync` and `sync`, which wait for the frames
+/// already written. Other changes to the file, such as truncating it, are
+/// refused with `EBADF`.
+pub struct FramedWriter<F: WasiFile> {
+    inner: F,
+    // Held while a frame is written, so frames go out whole.
+    writing: Mutex<()>,
+}
+
+impl<F: WasiFile> FramedWriter<F> {
+    pub fn new(inner: F) -> Self {
+        FramedWriter {
+            inner,
+            writing: Mutex::new(()),
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for FramedWriter<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        self.inner.sock_recv(ri_data, ri_flags).await
+    }
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.inner.sock_shutdown(how).await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.inner.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if len == 0 {
+            return Ok(0);
+        }
+        let prefix = u32::try_from(len)
+            .map_err(|_| Error::overflow().context("message too long for a u32 length prefix"))?;
+        let mut frame = Vec::with_capacity(4 + len);
+        frame.extend_from_slice(&prefix.to_be_bytes());
+        for buf in bufs {
+            frame.extend_from_slice(buf);
+        }
+        let _writing = self.writing.lock().await;
+        let mut rest = &frame[..];
+        while !rest.is_empty() {
+            let n = self.inner.write_vectored(&[io::IoSlice::new(rest)]).await? as usize;
+            if n == 0 {
+                return Err(Error::io().context("inner file accepted no bytes"));
+            }
+            rest = &rest[n..];
+        }
+        Ok(len as u64)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe().context("framed output needs sequential writes"))
+    }
+    async fn seek(&self, _pos: io::SeekFrom) -> Result<u64, Error> {
+        Err(Error::seek_pipe().context("framed output needs sequential writes"))
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FramedWriter;
+    use crate::RingCapture;
+    use std::io::IoSlice;
+    use wasi_common::WasiFile;
+
+    #[tokio::test]
+    async fn each_write_is_one_frame() {
+        let capture = RingCapture::new(64);
+        let f = FramedWriter::new(capture.clone());
+        let n = f
+            .write_vectored(&[IoSlice::new(b"he"), IoSlice::new(b"llo")])
+            .await
+            .unwrap();
+        assert_eq!(n, 5);
+        let n = f.write_vectored(&[IoSlice::new(b"hi")]).await.unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(capture.contents(), b"\0\0\0\x05hello\0\0\0\x02hi");
+    }
+}
@@ -0,0 +1,226 @@
+use bitflags::bitflags;
+use std::any::Any;
+use std::io;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+bitflags! {
+    /// The operations a [`Gated`] file lets through.
+    pub struct FileOps: u32 {
+        /// `read_vectored`, `read_vectored_at`, `peek`, and `sock_recv`.
+        const READ        = 0b1;
+        /// `write_vectored`, `write_vectored_at`, and `sock_send`.
+        const WRITE       = 0b10;
+        const SEEK        = 0b100;
+        /// `datasync` and `sync`.
+        const SYNC        = 0b1000;
+        const SET_TIMES   = 0b10000;
+        /// `set_filestat_size`, that is, truncation.
+        const SET_SIZE    = 0b100000;
+        const ALLOCATE    = 0b1000000;
+        const ADVISE      = 0b10000000;
+        const GET_STAT    = 0b100000000;
+        const SET_FDFLAGS = 0b1000000000;
+        /// `sock_accept` and `sock_shutdown`.
+        const SOCKET      = 0b10000000000;
+    }
+}
+
+/// A `WasiFile` wrapper which only lets a chosen set of operations through
+/// to the inner file, failing the rest with `EPERM`.
+///
+/// This narrows what a guest can do with a descriptor beyond what its WASI
+/// rights express, as a second line of defense: for example, a log file can
+/// allow writes and syncs but not seeks or truncation, and a file can be
+/// readable without its timestamps being settable. Wrappers compose, so a
+/// `Gated<ReadOnly<F>>` is both.
+///
+/// Operations which only describe the file, namely `get_filetype`,
+/// `get_fdflags`, `isatty`, and readiness, are always allowed.
+pub struct Gated<F: WasiFile> {
+    inner: F,
+    allowed: FileOps,
+}
+
+impl<F: WasiFile> Gated<F> {
+    pub fn new(inner: F, allowed: FileOps) -> Self {
+        Gated { inner, allowed }
+    }
+    pub fn allowed(&self) -> FileOps {
+        self.allowed
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn check(&self, op: FileOps) -> Result<(), Error> {
+        if self.allowed.contains(op) {
+            Ok(())
+        } else {
+            Err(Error::perm().context(format!("{:?} is not allowed on this file", op)))
+        }
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for Gated<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.check(FileOps::SOCKET)?;
+        self.inner.sock_accept(fdflags).await
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        self.check(FileOps::READ)?;
+        self.inner.sock_recv(ri_data, ri_flags).await
+    }
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[io::IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        self.check(FileOps::WRITE)?;
+        self.inner.sock_send(si_data, si_flags).await
+    }
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.check(FileOps::SOCKET)?;
+        self.inner.sock_shutdown(how).await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.check(FileOps::SYNC)?;
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.check(FileOps::SYNC)?;
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.check(FileOps::SET_FDFLAGS)?;
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.check(FileOps::GET_STAT)?;
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.check(FileOps::SET_SIZE)?;
+        self.inner.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.check(FileOps::ADVISE)?;
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.check(FileOps::ALLOCATE)?;
+        self.inner.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.check(FileOps::SET_TIMES)?;
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.check(FileOps::READ)?;
+        self.inner.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.check(FileOps::READ)?;
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.check(FileOps::WRITE)?;
+        self.inner.write_vectored(bufs).await
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.check(FileOps::WRITE)?;
+        self.inner.write_vectored_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.check(FileOps::SEEK)?;
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.check(FileOps::READ)?;
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FileOps, Gated};
+    use crate::File;
+    use std::io::{IoSliceMut, Write};
+    use wasi_common::{snapshots::preview_1::types::Errno, SystemTimeSpec, WasiFile};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn gates_set_times_allows_reads() {
+        let mut file = tempfile::tempfile().expect("create temporary file");
+        file.write_all(b"gated").expect("write contents");
+        let file = Gated::new(
+            File::from_cap_std(cap_std::fs::File::from_std(file)),
+            FileOps::READ | FileOps::SEEK,
+        );
+
+        file.seek(std::io::SeekFrom::Start(0))
+            .await
+            .expect("seek is allowed");
+        let mut buf = [0; 16];
+        let n = file
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .expect("read is allowed");
+        assert_eq!(&buf[..n as usize], b"gated");
+
+        let err = file
+            .set_times(None, Some(SystemTimeSpec::SymbolicNow))
+            .await
+            .expect_err("set_times is gated");
+        assert_eq!(err.downcast().expect("errno"), Errno::Perm);
+        let err = file.sync().await.expect_err("sync is gated");
+        assert_eq!(err.downcast().expect("errno"), Errno::Perm);
+    }
+}
@@ -0,0 +1,276 @@
+use std::any::Any;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error, SystemTimeSpec,
+};
+
+/// A `WasiFile` wrapper which gives up on a connection once it has gone
+/// without reads or writes for a while, so a server doesn't keep idle guest
+/// connections open forever.
+///
+/// Every read or write which succeeds restarts the clock. A background task,
+/// living as long as the wrapper, watches for the idle time running out, so
+/// [`IdleTimeout::new`] must be called from within a tokio runtime. Once it
+/// has, reads, writes, and readiness all fail with `ETIMEDOUT`, which nothing
+/// else here reports, and pending readiness waits wake up to see it. The
+/// inner file is only closed when the wrapper is dropped.
+pub struct IdleTimeout<F: WasiFile> {
+    inner: F,
+    state: Arc<State>,
+    watcher: JoinHandle<()>,
+}
+
+struct State {
+    idle: Duration,
+    last_io: Mutex<Instant>,
+    expired: AtomicBool,
+}
+
+impl State {
+    fn deadline(&self) -> Instant {
+        *self.last_io.lock().unwrap() + self.idle
+    }
+}
+
+impl<F: WasiFile> IdleTimeout<F> {
+    pub fn new(inner: F, idle: Duration) -> Self {
+        let state = Arc::new(State {
+            idle,
+            last_io: Mutex::new(Instant::now()),
+            expired: AtomicBool::new(false),
+        });
+        let watcher = tokio::spawn({
+            let state = state.clone();
+            async move {
+                // I/O may have moved the deadline while this slept.
+                loop {
+                    let deadline = state.deadline();
+                    if deadline <= Instant::now() {
+                        break;
+                    }
+                    tokio::time::sleep_until(deadline).await;
+                }
+                state.expired.store(true, Ordering::SeqCst);
+            }
+        });
+        IdleTimeout {
+            inner,
+            state,
+            watcher,
+        }
+    }
+    pub fn idle(&self) -> Duration {
+        self.state.idle
+    }
+    /// Whether the connection has gone idle for too long.
+    pub fn is_expired(&self) -> bool {
+        self.state.expired.load(Ordering::SeqCst)
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.is_expired() {
+            return Err(Error::from(Errno::Timedout).context("connection was idle for too long"));
+        }
+        Ok(())
+    }
+
+    // Restart the clock if `result` is a success.
+    fn note_io<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        if result.is_ok() && !self.is_expired() {
+            *self.state.last_io.lock().unwrap() = Instant::now();
+        }
+        result
+    }
+
+    // Wait for `ready` to finish, or for the connection to expire.
+    async fn ready(
+        &self,
+        ready: impl std::future::Future<Output = Result<(), Error>>,
+    ) -> Result<(), Error> {
+        tokio::pin!(ready);
+        loop {
+            self.check()?;
+            // Waiting doesn't count as I/O, but the deadline can still move
+            // if another task reads or writes in the meantime.
+            let deadline = self.state.deadline();
+            if let Ok(result) = tokio::time::timeout_at(deadline, &mut ready).await {
+                return result;
+            }
+            if deadline <= Instant::now() && self.state.deadline() <= Instant::now() {
+                self.state.expired.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+impl<F: WasiFile> Drop for IdleTimeout<F> {
+    fn drop(&mut self) {
+        self.watcher.abort();
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for IdleTimeout<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.inner.sock_accept(fdflags).await
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        self.check()?;
+        let result = self.inner.sock_recv(ri_data, ri_flags).await;
+        self.note_io(result)
+    }
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[io::IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        self.check()?;
+        let result = self.inner.sock_send(si_data, si_flags).await;
+        self.note_io(result)
+    }
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.inner.sock_shutdown(how).await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.inner.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.inner.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.check()?;
+        let result = self.inner.read_vectored(bufs).await;
+        self.note_io(result)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.check()?;
+        let result = self.inner.read_vectored_at(bufs, offset).await;
+        self.note_io(result)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.check()?;
+        let result = self.inner.write_vectored(bufs).await;
+        self.note_io(result)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.check()?;
+        let result = self.inner.write_vectored_at(bufs, offset).await;
+        self.note_io(result)
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.check()?;
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.check()?;
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.ready(self.inner.readable()).await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.ready(self.inner.writable()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IdleTimeout;
+    use crate::BytesFile;
+    use std::io::IoSliceMut;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    async fn read(f: &IdleTimeout<BytesFile>) -> Result<u64, wasi_common::Error> {
+        let mut buf = [0; 1];
+        f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await
+    }
+
+    #[tokio::test]
+    async fn errors_once_idle() {
+        let inner = BytesFile::new(Arc::from(&b"abcdef"[..]));
+        let f = IdleTimeout::new(inner, Duration::from_millis(300));
+
+        // Reads closer together than the timeout keep the connection open.
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(read(&f).await.unwrap(), 1);
+        }
+        assert!(!f.is_expired());
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(f.is_expired());
+        let err = read(&f).await.expect_err("idle");
+        assert_eq!(err.downcast().unwrap(), Errno::Timedout);
+        let err = f.readable().await.expect_err("idle");
+        assert_eq!(err.downcast().unwrap(), Errno::Timedout);
+    }
+}
@@ -0,0 +1,123 @@
+use wasi_common::Error;
+
+/// The scheduling class of an I/O priority, as with Linux's `ioprio_set`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoPriorityClass {
+    /// Served ahead of every other class. Setting it takes `CAP_SYS_ADMIN`.
+    RealTime,
+    /// Served in turn with other best-effort I/O, by level. This is what a
+    /// thread gets by default.
+    BestEffort,
+    /// Only served when no other I/O wants the disk.
+    Idle,
+}
+
+// An I/O priority a file's blocking operations run under.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct IoPriority(sys::RawPriority);
+
+impl IoPriority {
+    pub(crate) fn new(class: IoPriorityClass, level: u8) -> Result<Self, Error> {
+        sys::new(class, level).map(IoPriority)
+    }
+
+    // Run `op` with the calling thread's I/O priority set to this one, then
+    // put the thread's own back, even if `op` panics.
+    pub(crate) fn run<T>(self, op: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        let _restore = sys::Restore::set(self.0)?;
+        op()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::IoPriorityClass;
+    use std::io;
+    use wasi_common::{Error, ErrorExt};
+
+    pub(super) type RawPriority = libc::c_int;
+
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_NONE: libc::c_int = 0;
+
+    pub(super) fn new(class: IoPriorityClass, level: u8) -> Result<RawPriority, Error> {
+        if level > 7 {
+            return Err(Error::invalid_argument().context("I/O priority levels run from 0 to 7"));
+        }
+        let class = match class {
+            IoPriorityClass::RealTime => 1,
+            IoPriorityClass::BestEffort => 2,
+            IoPriorityClass::Idle => 3,
+        };
+        let prio = (class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(level);
+        // Try it out on this thread, so a priority the process may not take
+        // fails here rather than on every operation.
+        drop(Restore::set(prio)?);
+        Ok(prio)
+    }
+
+    // Puts the calling thread's I/O priority back when dropped.
+    pub(super) struct Restore(libc::c_int);
+
+    impl Restore {
+        pub(super) fn set(prio: RawPriority) -> Result<Self, Error> {
+            let old = get()?;
+            set(prio)?;
+            Ok(Restore(old))
+        }
+    }
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = set(self.0);
+        }
+    }
+
+    // `ioprio_set` and `ioprio_get` with a `who` of zero act on the calling
+    // thread, not the whole process.
+    fn get() -> Result<libc::c_int, Error> {
+        let ret = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let prio = ret as libc::c_int;
+        // A thread which never set a priority reports no class, along with a
+        // level derived from its nice value which `ioprio_set` won't take
+        // back; no class at all is what restores deriving it.
+        if prio >> IOPRIO_CLASS_SHIFT == IOPRIO_CLASS_NONE {
+            Ok(0)
+        } else {
+            Ok(prio)
+        }
+    }
+
+    fn set(prio: libc::c_int) -> Result<(), Error> {
+        let ret = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::IoPriorityClass;
+    use wasi_common::{Error, ErrorExt};
+
+    // No priority can be made here, so there's none to run under.
+    pub(super) type RawPriority = std::convert::Infallible;
+
+    pub(super) fn new(_class: IoPriorityClass, _level: u8) -> Result<RawPriority, Error> {
+        Err(Error::not_supported().context("I/O priorities are only available on Linux"))
+    }
+
+    pub(super) enum Restore {}
+
+    impl Restore {
+        pub(super) fn set(prio: RawPriority) -> Result<Self, Error> {
+            match prio {}
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::io;
+use wasi_common::{Error, ErrorExt};
+
+/// The total length of a set of iovecs. They may alias one another, so on a
+/// 32-bit host a guest can pass a set whose lengths sum past `usize::MAX`;
+/// that's `EOVERFLOW` rather than a panic or a wrapped total.
+pub(crate) fn iovec_len(lens: impl IntoIterator<Item = usize>) -> Result<usize, Error> {
+    lens.into_iter()
+        .try_fold(0usize, usize::checked_add)
+        .ok_or_else(|| Error::overflow().context("total iovec length overflows usize"))
+}
+
+// Gather `bufs` into one buffer, for wrappers which need a write's data in
+// one piece, such as to pick up the rest of it after a short write.
+pub(crate) fn gather(bufs: &[io::IoSlice<'_>]) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::with_capacity(iovec_len(bufs.iter().map(|b| b.len()))?);
+    for buf in bufs {
+        data.extend_from_slice(buf);
+    }
+    Ok(data)
+}
+
+// Shorten `bufs` so they hold no more than `max` bytes in total.
+pub(crate) fn capped<'b>(bufs: &'b [io::IoSlice<'_>], max: usize) -> Vec<io::IoSlice<'b>> {
+    let mut left = max;
+    let mut capped = Vec::with_capacity(bufs.len());
+    for buf in bufs {
+        if left == 0 {
+            break;
+        }
+        let n = buf.len().min(left);
+        capped.push(io::IoSlice::new(&buf[..n]));
+        left -= n;
+    }
+    capped
+}
+
+// As `capped`, for buffers to read into.
+pub(crate) fn capped_mut<'b>(
+    bufs: &'b mut [io::IoSliceMut<'_>],
+    max: usize,
+) -> Vec<io::IoSliceMut<'b>> {
+    let mut left = max;
+    let mut capped = Vec::with_capacity(bufs.len());
+    for buf in bufs.iter_mut() {
+        if left == 0 {
+            break;
+        }
+        let n = buf.len().min(left);
+        capped.push(io::IoSliceMut::new(&mut buf[..n]));
+        left -= n;
+    }
+    capped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{capped, capped_mut, gather, iovec_len};
+    use std::io::{IoSlice, IoSliceMut};
+    use wasi_common::snapshots::preview_1::types::Errno;
+
+    #[test]
+    fn iovec_len_overflow_is_an_error() {
+        assert_eq!(iovec_len([3, 0, 5]).unwrap(), 8);
+        assert_eq!(iovec_len([usize::MAX, 0]).unwrap(), usize::MAX);
+
+        // What a 32-bit host sees from a guest passing aliased iovecs.
+        for lens in [vec![usize::MAX, 1], vec![usize::MAX / 2 + 1; 2]] {
+            let err = iovec_len(lens).expect_err("overflows");
+            assert_eq!(err.downcast().unwrap(), Errno::Overflow);
+        }
+    }
+
+    #[test]
+    fn gather_and_cap() {
+        let bufs = [IoSlice::new(b"abc"), IoSlice::new(b""), IoSlice::new(b"de")];
+        assert_eq!(gather(&bufs).unwrap(), b"abcde");
+        let short = capped(&bufs, 4);
+        assert_eq!(gather(&short).unwrap(), b"abcd");
+
+        let (mut a, mut b) = ([0; 3], [0; 3]);
+        let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        let lens = capped_mut(&mut bufs, 4)
+            .iter()
+            .map(|b| b.len())
+            .collect::<Vec<_>>();
+        assert_eq!(lens, [3, 1]);
+    }
+}
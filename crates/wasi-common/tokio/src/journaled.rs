@@ -0,0 +1,333 @@
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// A `WasiFile` wrapper which gathers a guest's small positional writes into
+/// whole blocks, so the inner file only sees writes which start and end on a
+/// block boundary, as a write-ahead log on an `O_DIRECT` file needs to avoid
+/// read-modify-write cycles in the device.
+///
+/// Writes go through `write_vectored_at`, and each one must either pick up
+/// where the previous one left off or start on a block boundary; anything
+/// else fails with `EINVAL`, as the bytes before it in its block are
+/// unknown. Once a block fills it's written out, and the partial block at
+/// the end stays buffered.
+///
+/// `datasync` and `sync` write the partial block out padded with zeros, then
+/// sync the inner file. The block stays buffered, and is written again in
+/// full once later writes fill it, so the file ends in zeros up to a block
+/// boundary, which the log's own framing has to tolerate. Reads, seeks, and
+/// stats write the partial block out the same way first. Stream writes fail
+/// with `ENOTSUP`.
+///
+/// A partial block still buffered when the wrapper is dropped is written out
+/// on a best-effort basis, which only succeeds if the inner file's writes
+/// complete without yielding. Call `datasync` before dropping to observe
+/// errors.
+pub struct Journaled<F: WasiFile> {
+    inner: F,
+    block_size: usize,
+    tail: Mutex<Option<Tail>>,
+}
+
+// The buffered partial block.
+struct Tail {
+    // Where the block starts, on a block boundary.
+    start: u64,
+    // The bytes written to the block so far, fewer than a block's worth.
+    data: Vec<u8>,
+    // Whether `data` has changed since it was last written out.
+    dirty: bool,
+}
+
+impl<F: WasiFile> Journaled<F> {
+    pub fn new(inner: F) -> Self {
+        Journaled {
+            inner,
+            block_size: DEFAULT_BLOCK_SIZE,
+            tail: Mutex::new(None),
+        }
+    }
+    /// Use blocks of `block_size` bytes rather than the default of 4096.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert!(block_size > 0, "block size must be nonzero");
+        self.block_size = block_size;
+        self
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    async fn write_all_at(&self, mut data: &[u8], mut offset: u64) -> Result<(), Error> {
+        while !data.is_empty() {
+            let n = self
+                .inner
+                .write_vectored_at(&[io::IoSlice::new(data)], offset)
+                .await?;
+            if n == 0 {
+                return Err(Error::io().context("failed to write journal block"));
+            }
+            data = &data[n as usize..];
+            offset += n;
+        }
+        Ok(())
+    }
+
+    // Write out the partial block, padded to a whole one.
+    async fn flush_locked(&self, tail: &mut Option<Tail>) -> Result<(), Error> {
+        if let Some(tail) = tail.as_mut().filter(|t| t.dirty) {
+            let mut block = tail.data.clone();
+            block.resize(self.block_size, 0);
+            self.write_all_at(&block, tail.start).await?;
+            tail.dirty = false;
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        let mut tail = self.tail.lock().await;
+        self.flush_locked(&mut tail).await
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for Journaled<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        let mut tail = self.tail.lock().await;
+        self.flush_locked(&mut tail).await?;
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        let mut tail = self.tail.lock().await;
+        self.flush_locked(&mut tail).await?;
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.flush().await?;
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.flush().await?;
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        // The buffered block may not survive the truncation, so later writes
+        // start over on a block boundary.
+        let mut tail = self.tail.lock().await;
+        self.flush_locked(&mut tail).await?;
+        *tail = None;
+        self.inner.set_filestat_size(size).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, _bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        Err(Error::not_supported().context("journaled files only take positional writes"))
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        offset
+            .checked_add(len as u64)
+            .ok_or_else(|| Error::overflow().context("write extends past u64::MAX"))?;
+        let mut tail = self.tail.lock().await;
+        let continues = tail
+            .as_ref()
+            .map_or(false, |t| t.start + t.data.len() as u64 == offset);
+        let (start, mut data) = if continues {
+            let t = tail.as_ref().unwrap();
+            (t.start, t.data.clone())
+        } else if offset % self.block_size as u64 == 0 {
+            self.flush_locked(&mut tail).await?;
+            (offset, Vec::new())
+        } else {
+            return Err(Error::invalid_argument().context(
+                "journal writes must continue the last one or start on a block boundary",
+            ));
+        };
+        for buf in bufs {
+            data.extend_from_slice(buf);
+        }
+
+        // Only commit the new tail once the full blocks are out, so a failed
+        // write leaves the journal as it was.
+        let full = data.len() - data.len() % self.block_size;
+        if full > 0 {
+            self.write_all_at(&data[..full], start).await?;
+        }
+        let dirty = if full > 0 {
+            data.len() > full
+        } else {
+            len > 0 || tail.as_ref().map_or(false, |t| t.dirty)
+        };
+        *tail = Some(Tail {
+            start: start + full as u64,
+            data: data.split_off(full),
+            dirty,
+        });
+        Ok(len as u64)
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.flush().await?;
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+impl<F: WasiFile> Drop for Journaled<F> {
+    fn drop(&mut self) {
+        let mut tail = self.tail.get_mut().take();
+        if tail.as_ref().map_or(false, |t| t.dirty) {
+            let _ = wiggle::run_in_dummy_executor(self.flush_locked(&mut tail));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Journaled;
+    use std::any::Any;
+    use std::io::IoSlice;
+    use std::sync::Mutex;
+    use wasi_common::{file::FileType, snapshots::preview_1::types::Errno, Error, WasiFile};
+
+    // Records each positional write as its offset and contents.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(u64, Vec<u8>)>>);
+
+    #[wiggle::async_trait]
+    impl WasiFile for Recorder {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        async fn get_filetype(&self) -> Result<FileType, Error> {
+            Ok(FileType::RegularFile)
+        }
+        async fn write_vectored_at<'a>(
+            &self,
+            bufs: &[IoSlice<'a>],
+            offset: u64,
+        ) -> Result<u64, Error> {
+            let data = bufs
+                .iter()
+                .flat_map(|b| b.iter().copied())
+                .collect::<Vec<_>>();
+            let n = data.len() as u64;
+            self.0.lock().unwrap().push((offset, data));
+            Ok(n)
+        }
+        async fn datasync(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn small_writes_become_aligned_blocks() {
+        let f = Journaled::new(Recorder::default()).with_block_size(64);
+        let mut offset = 0;
+        for i in 0..50u8 {
+            let record = [i; 7];
+            f.write_vectored_at(&[IoSlice::new(&record)], offset)
+                .await
+                .unwrap();
+            offset += record.len() as u64;
+        }
+        f.datasync().await.unwrap();
+
+        let writes = f.get_ref().0.lock().unwrap();
+        // 350 bytes make five full blocks and a padded partial one.
+        assert_eq!(writes.len(), 6);
+        for (at, data) in writes.iter() {
+            assert_eq!(at % 64, 0, "unaligned offset {}", at);
+            assert_eq!(data.len(), 64);
+        }
+        let mut file = vec![0; 6 * 64];
+        for (at, data) in writes.iter() {
+            file[*at as usize..][..data.len()].copy_from_slice(data);
+        }
+        let expected = (0..50u8).flat_map(|i| [i; 7]).collect::<Vec<_>>();
+        assert_eq!(&file[..350], &expected[..]);
+        assert!(file[350..].iter().all(|b| *b == 0));
+    }
+
+    #[tokio::test]
+    async fn partial_block_is_rewritten_once_filled() {
+        let f = Journaled::new(Recorder::default()).with_block_size(8);
+        f.write_vectored_at(&[IoSlice::new(b"abc")], 0)
+            .await
+            .unwrap();
+        f.datasync().await.unwrap();
+        f.write_vectored_at(&[IoSlice::new(b"defghij")], 3)
+            .await
+            .unwrap();
+        f.datasync().await.unwrap();
+        assert_eq!(
+            *f.get_ref().0.lock().unwrap(),
+            [
+                (0, b"abc\0\0\0\0\0".to_vec()),
+                (0, b"abcdefgh".to_vec()),
+                (8, b"ij\0\0\0\0\0\0".to_vec()),
+            ]
+        );
+
+        // A write in the middle of some other block can't be aligned.
+        let err = f
+            .write_vectored_at(&[IoSlice::new(b"x")], 20)
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Inval);
+        let err = f.write_vectored(&[IoSlice::new(b"x")]).await.unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Notsup);
+    }
+}
@@ -1,19 +1,117 @@
 #![cfg_attr(io_lifetimes_use_std, feature(io_safety))]
 
+mod audit_log;
+mod broadcast;
+mod buffered;
+mod bytes_file;
+mod cancel;
+mod cloexec;
+mod crlf;
+mod decompress;
+mod delimited;
 mod dir;
+mod drain;
+mod draining;
+mod encrypted;
+mod fault;
 mod file;
+mod file_reader;
+mod framed;
+mod fs_file;
+mod gated;
+mod idle_timeout;
+mod ioprio;
+mod iovec;
+mod journaled;
+mod lock;
+mod max_lifetime;
+mod mem_file;
+mod msg_more;
 pub mod net;
+mod nonblocking;
+mod null;
+mod object_store;
+mod ordered;
+mod paged;
+mod pausable;
+mod prefixed;
+mod probe;
+mod publish;
+mod rate_limit;
+mod read_only;
+mod read_stream;
+mod readahead;
+mod reconnect;
+mod remote;
+mod replay;
+mod retry;
+mod ring_capture;
 pub mod sched;
+mod shared_sink;
+mod sockopt;
+mod stdin;
 pub mod stdio;
+mod sub_file;
+mod swappable;
+mod sync_group;
+mod tagged;
+mod tmpfile;
+#[cfg(unix)]
+mod ucred;
+mod verified;
+mod xattr;
 
 use std::future::Future;
 use std::path::Path;
 pub use wasi_cap_std_sync::{clocks_ctx, random_ctx};
 use wasi_common::{Error, Table, WasiCtx, WasiFile};
 
-pub use dir::Dir;
-pub use file::File;
+pub use audit_log::AuditLog;
+pub use broadcast::BroadcastSink;
+pub use buffered::BufferedFile;
+pub use bytes_file::BytesFile;
+pub use cancel::CancellationToken;
+pub use crlf::CrlfTranslate;
+pub use decompress::{DecompressReader, Decompressor};
+pub use dir::{mkdir_at, rmdir_at, statat, unlink_at, Dir};
+pub use draining::{DrainHandle, DrainingListener};
+pub use encrypted::{AeadCipher, EncryptedFile};
+pub use fault::{FaultInjector, FaultPolicy};
+pub use file::{File, SyncRangeFlags};
+pub use file_reader::FileReader;
+pub use framed::FramedWriter;
+pub use fs_file::TokioFsFile;
+pub use gated::{FileOps, Gated};
+pub use idle_timeout::IdleTimeout;
+pub use ioprio::IoPriorityClass;
+pub use journaled::Journaled;
+pub use max_lifetime::MaxLifetime;
+pub use mem_file::MemFile;
 pub use net::*;
+pub use null::{NullFile, ZeroFile};
+pub use object_store::{ObjectBackend, ObjectStoreFile};
+pub use ordered::Ordered;
+pub use paged::{PageSource, PagedFile};
+pub use pausable::{Pausable, PauseSwitch};
+pub use prefixed::PrefixedFile;
+pub use publish::PublishOnClose;
+pub use rate_limit::{RateLimited, SharedRateLimiter};
+pub use read_only::ReadOnly;
+pub use read_stream::AsyncReadStream;
+pub use reconnect::Reconnecting;
+pub use remote::{RemoteBackend, RemoteFile};
+pub use replay::{Recording, Replay};
+pub use retry::{RetryBackoff, RetryEagain};
+pub use ring_capture::RingCapture;
+pub use shared_sink::{SharedSink, SharedSinkWriter};
+pub use sockopt::{MtuDiscover, SockFilter, SocketControl, MAX_SOCKOPT_LEN};
+pub use sub_file::SubFile;
+pub use swappable::{SwapHandle, Swappable};
+pub use sync_group::sync_all;
+pub use tagged::{StreamKind, TaggedWriter};
+#[cfg(unix)]
+pub use ucred::UCred;
+pub use verified::{Checksum, VerifiedReader};
 use wasi_cap_std_sync::net::Socket;
 use wasi_common::file::FileCaps;
 
@@ -131,3 +229,15 @@ where
         wiggle::run_in_dummy_executor(f()).expect("wrapped operation should be synchronous")
     })
 }
+
+// Like `block_on_dummy_executor`, but without telling tokio that the code
+// blocks. This is only for operations which are expected to finish too quickly
+// for handing off the worker's other tasks to pay for itself.
+pub(crate) fn run_inline<'a, F, Fut, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Fut + Send + 'a,
+    Fut: Future<Output = Result<T, Error>>,
+    T: Send + 'static,
+{
+    wiggle::run_in_dummy_executor(f()).expect("wrapped operation should be synchronous")
+}
@@ -0,0 +1,193 @@
+use crate::file::File;
+use std::sync::Arc;
+use wasi_common::Error;
+
+impl File {
+    /// Lock the byte range `[offset, offset + len)`, waiting until no other
+    /// process holds a conflicting lock on it. A `len` of zero extends the range
+    /// to the end of the file, however far the file grows.
+    ///
+    /// On Unix this is `fcntl(F_SETLKW)`; on Windows it is `LockFileEx`.
+    ///
+    /// Beware the classic POSIX footgun: these locks are owned by the process,
+    /// not the descriptor. Closing *any* descriptor for the same file in this
+    /// process, including one obtained through [`File::try_clone`] or an
+    /// unrelated open of the same path, silently drops every lock the process
+    /// holds on that file. Locks held by a single process never conflict with
+    /// each other, either.
+    ///
+    /// The wait happens on a blocking thread. Dropping the returned future
+    /// stops waiting for it, but the thread carries on, and the lock is taken
+    /// whenever it is granted.
+    ///
+    /// Offsets and lengths too large for the platform's lock calls fail with
+    /// `EINVAL`.
+    pub async fn lock_range(&self, offset: u64, len: u64, exclusive: bool) -> Result<(), Error> {
+        let handle = self.lock_handle()?;
+        tokio::task::spawn_blocking(move || sys::lock_range(&*handle, offset, len, exclusive))
+            .await?
+    }
+
+    /// Release a lock previously taken with [`File::lock_range`].
+    pub async fn unlock_range(&self, offset: u64, len: u64) -> Result<(), Error> {
+        let handle = self.lock_handle()?;
+        tokio::task::spawn_blocking(move || sys::unlock_range(&*handle, offset, len)).await?
+    }
+
+    /// Check whether an exclusive lock on `[offset, offset + len)` would be
+    /// granted, returning the pid of a process holding a conflicting lock if
+    /// there is one. Locks held by this process are never reported.
+    ///
+    /// Windows has no equivalent query, so this returns
+    /// `Error::not_supported()` there.
+    pub async fn test_lock(&self, offset: u64, len: u64) -> Result<Option<u32>, Error> {
+        let handle = self.lock_handle()?;
+        tokio::task::spawn_blocking(move || sys::test_lock(&*handle, offset, len)).await?
+    }
+
+    // The descriptor to lock through, which a blocking thread can own a
+    // reference to even if this file is dropped while it waits.
+    fn lock_handle(&self) -> Result<Arc<wasi_cap_std_sync::file::File>, Error> {
+        let mut handle = self.lock_handle.lock().unwrap();
+        if let Some(handle) = &*handle {
+            return Ok(handle.clone());
+        }
+        let new = Arc::new(self.inner.try_clone()?);
+        *handle = Some(new.clone());
+        Ok(new)
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::convert::TryInto;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::{Error, ErrorExt};
+
+    fn flock(offset: u64, len: u64, ty: libc::c_short) -> Result<libc::flock, Error> {
+        let out_of_range = |_| Error::invalid_argument().context("lock range exceeds off_t");
+        // Some platforms have extra fields in `struct flock`, so start from zero.
+        let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+        lock.l_type = ty;
+        lock.l_whence = libc::SEEK_SET as libc::c_short;
+        lock.l_start = offset.try_into().map_err(out_of_range)?;
+        lock.l_len = len.try_into().map_err(out_of_range)?;
+        Ok(lock)
+    }
+
+    fn fcntl_lock(fd: impl AsFd, cmd: libc::c_int, lock: &mut libc::flock) -> Result<(), Error> {
+        loop {
+            let ret = unsafe { libc::fcntl(fd.as_fd().as_raw_fd(), cmd, lock as *mut libc::flock) };
+            if ret == 0 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+        }
+    }
+
+    pub(super) fn lock_range(
+        fd: impl AsFd,
+        offset: u64,
+        len: u64,
+        exclusive: bool,
+    ) -> Result<(), Error> {
+        let ty = if exclusive {
+            libc::F_WRLCK
+        } else {
+            libc::F_RDLCK
+        };
+        let mut lock = flock(offset, len, ty as libc::c_short)?;
+        fcntl_lock(fd, libc::F_SETLKW, &mut lock)
+    }
+
+    pub(super) fn unlock_range(fd: impl AsFd, offset: u64, len: u64) -> Result<(), Error> {
+        let mut lock = flock(offset, len, libc::F_UNLCK as libc::c_short)?;
+        fcntl_lock(fd, libc::F_SETLK, &mut lock)
+    }
+
+    pub(super) fn test_lock(fd: impl AsFd, offset: u64, len: u64) -> Result<Option<u32>, Error> {
+        let mut lock = flock(offset, len, libc::F_WRLCK as libc::c_short)?;
+        fcntl_lock(fd, libc::F_GETLK, &mut lock)?;
+        if lock.l_type == libc::F_UNLCK as libc::c_short {
+            Ok(None)
+        } else {
+            Ok(Some(lock.l_pid as u32))
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use io_lifetimes::AsHandle;
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+    use wasi_common::{Error, ErrorExt};
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        LockFileEx, UnlockFileEx, LOCKFILE_EXCLUSIVE_LOCK,
+    };
+    use windows_sys::Win32::System::IO::{OVERLAPPED, OVERLAPPED_0, OVERLAPPED_0_0};
+
+    fn overlapped(offset: u64) -> OVERLAPPED {
+        OVERLAPPED {
+            Internal: 0,
+            InternalHigh: 0,
+            Anonymous: OVERLAPPED_0 {
+                Anonymous: OVERLAPPED_0_0 {
+                    Offset: offset as u32,
+                    OffsetHigh: (offset >> 32) as u32,
+                },
+            },
+            hEvent: 0,
+        }
+    }
+
+    // As with `fcntl`, a zero length covers everything through the end of the file.
+    fn region_len(len: u64) -> (u32, u32) {
+        let len = if len == 0 { u64::MAX } else { len };
+        (len as u32, (len >> 32) as u32)
+    }
+
+    pub(super) fn lock_range(
+        handle: impl AsHandle,
+        offset: u64,
+        len: u64,
+        exclusive: bool,
+    ) -> Result<(), Error> {
+        let flags = if exclusive {
+            LOCKFILE_EXCLUSIVE_LOCK
+        } else {
+            0
+        };
+        let (low, high) = region_len(len);
+        let mut overlapped = overlapped(offset);
+        let handle = handle.as_handle().as_raw_handle() as HANDLE;
+        if unsafe { LockFileEx(handle, flags, 0, low, high, &mut overlapped) } == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn unlock_range(handle: impl AsHandle, offset: u64, len: u64) -> Result<(), Error> {
+        let (low, high) = region_len(len);
+        let mut overlapped = overlapped(offset);
+        let handle = handle.as_handle().as_raw_handle() as HANDLE;
+        if unsafe { UnlockFileEx(handle, 0, low, high, &mut overlapped) } == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn test_lock(
+        _handle: impl AsHandle,
+        _offset: u64,
+        _len: u64,
+    ) -> Result<Option<u32>, Error> {
+        Err(Error::not_supported().context("Windows cannot query byte-range locks"))
+    }
+}
@@ -0,0 +1,250 @@
+use std::any::Any;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// A `WasiFile` wrapper which closes the inner file once it has been open
+/// for a set time, however busy it is, so a multi-tenant host can bound how
+/// long any guest holds a descriptor.
+///
+/// Unlike [`crate::IdleTimeout`], activity doesn't extend the lifetime. A
+/// background task, living as long as the wrapper, releases the inner file
+/// when the lifetime is up, so [`MaxLifetime::new`] must be called from
+/// within a tokio runtime. From then on every operation fails with `ESTALE`,
+/// which nothing else here reports; operations still in flight at that point,
+/// including readiness waits, are cut off with the same error, and the inner
+/// file is dropped as soon as they have been.
+///
+/// `isatty` reports `false` once the file is released, and `set_fdflags`
+/// fails with `ENOTSUP`, since the inner file is shared with operations in
+/// flight.
+pub struct MaxLifetime<F: WasiFile> {
+    inner: Arc<Mutex<Option<Arc<F>>>>,
+    deadline: Instant,
+    reaper: JoinHandle<()>,
+}
+
+impl<F: WasiFile + 'static> MaxLifetime<F> {
+    pub fn new(inner: F, lifetime: Duration) -> Self {
+        let deadline = Instant::now() + lifetime;
+        let inner = Arc::new(Mutex::new(Some(Arc::new(inner))));
+        let reaper = tokio::spawn({
+            let inner = inner.clone();
+            async move {
+                tokio::time::sleep_until(deadline).await;
+                drop(inner.lock().unwrap().take());
+            }
+        });
+        MaxLifetime {
+            inner,
+            deadline,
+            reaper,
+        }
+    }
+}
+
+impl<F: WasiFile> MaxLifetime<F> {
+    /// Whether the lifetime is up and the inner file released.
+    pub fn is_expired(&self) -> bool {
+        self.inner.lock().unwrap().is_none()
+    }
+
+    fn file(&self) -> Result<Arc<F>, Error> {
+        self.inner.lock().unwrap().clone().ok_or_else(expired)
+    }
+
+    // Run `op` on the inner file, giving up when the lifetime is up.
+    async fn run<T, Fut>(&self, op: impl FnOnce(Arc<F>) -> Fut) -> Result<T, Error>
+    where
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let file = self.file()?;
+        match tokio::time::timeout_at(self.deadline, op(file)).await {
+            Ok(result) => result,
+            Err(_) => Err(expired()),
+        }
+    }
+}
+
+fn expired() -> Error {
+    Error::from(Errno::Stale).context("descriptor outlived its maximum lifetime")
+}
+
+impl<F: WasiFile> Drop for MaxLifetime<F> {
+    fn drop(&mut self) {
+        self.reaper.abort();
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for MaxLifetime<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.run(|f| async move { f.get_filetype().await }).await
+    }
+    fn isatty(&self) -> bool {
+        self.file().map_or(false, |f| f.isatty())
+    }
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.run(|f| async move { f.sock_accept(fdflags).await })
+            .await
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        self.run(|f| async move { f.sock_recv(ri_data, ri_flags).await })
+            .await
+    }
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[io::IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        self.run(|f| async move { f.sock_send(si_data, si_flags).await })
+            .await
+    }
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.run(|f| async move { f.sock_shutdown(how).await })
+            .await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.run(|f| async move { f.datasync().await }).await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.run(|f| async move { f.sync().await }).await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.run(|f| async move { f.get_fdflags().await }).await
+    }
+    async fn set_fdflags(&mut self, _flags: FdFlags) -> Result<(), Error> {
+        self.file()?;
+        Err(Error::not_supported().context("cannot set flags on a file with a maximum lifetime"))
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.run(|f| async move { f.get_filestat().await }).await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.run(|f| async move { f.set_filestat_size(size).await })
+            .await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.run(|f| async move { f.advise(offset, len, advice).await })
+            .await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.run(|f| async move { f.allocate(offset, len).await })
+            .await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.run(|f| async move { f.set_times(atime, mtime).await })
+            .await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.run(|f| async move { f.read_vectored(bufs).await })
+            .await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.run(|f| async move { f.read_vectored_at(bufs, offset).await })
+            .await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.run(|f| async move { f.write_vectored(bufs).await })
+            .await
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.run(|f| async move { f.write_vectored_at(bufs, offset).await })
+            .await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.run(|f| async move { f.seek(pos).await }).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.run(|f| async move { f.peek(buf).await }).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.file()?.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.run(|f| async move { f.readable().await }).await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.run(|f| async move { f.writable().await }).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MaxLifetime;
+    use crate::BytesFile;
+    use std::io::IoSliceMut;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    #[tokio::test]
+    async fn errors_and_releases_once_expired() {
+        let data: Arc<[u8]> = Arc::from(&b"abcdef"[..]);
+        let f = MaxLifetime::new(BytesFile::new(data.clone()), Duration::from_millis(300));
+        let mut buf = [0; 1];
+
+        // Activity doesn't keep the file open.
+        let start = tokio::time::Instant::now();
+        while start.elapsed() < Duration::from_millis(200) {
+            f.read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!f.is_expired());
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(f.is_expired());
+        // The inner file's copy of the data is gone along with it.
+        assert_eq!(Arc::strong_count(&data), 1);
+        let err = f
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Stale);
+        let err = f.get_filestat().await.unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Stale);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cuts_off_waits_in_flight() {
+        let (ours, _theirs) = std::os::unix::net::UnixStream::pair().unwrap();
+        let stream =
+            crate::UnixStream::from_cap_std(cap_std::os::unix::net::UnixStream::from_std(ours));
+        let f = MaxLifetime::new(stream, Duration::from_millis(100));
+        let err = tokio::time::timeout(Duration::from_secs(5), f.readable())
+            .await
+            .expect("wait was cut off")
+            .unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Stale);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::file::seek_target;
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use std::sync::{Arc, Mutex};
+use wasi_common::{
+    file::{FileType, Filestat, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error, ErrorExt,
+};
+
+/// A readable, writable, seekable `WasiFile` whose contents live in memory,
+/// for giving a guest a scratch file without touching the filesystem.
+///
+/// Writes past the end grow the file, with any gap between the old end and
+/// the write filled with zeros, and `set_filestat_size` truncates or extends
+/// it the same way. Clones share the contents but not the offset, as two
+/// opens of the same file would, so the host can keep one to inspect what
+/// the guest wrote with [`MemFile::contents`].
+///
+/// Nothing bounds the size unless [`MemFile::with_max_size`] does, so a
+/// guest can otherwise grow it as far as the host's memory allows; past the
+/// limit, writes and resizes fail with `EFBIG`.
+pub struct MemFile {
+    data: Arc<Mutex<Vec<u8>>>,
+    position: Mutex<u64>,
+    max_size: u64,
+}
+
+impl MemFile {
+    /// An empty file.
+    pub fn new() -> Self {
+        Self::from_vec(Vec::new())
+    }
+    /// A file which starts out holding `data`.
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        MemFile {
+            data: Arc::new(Mutex::new(data)),
+            position: Mutex::new(0),
+            max_size: u64::MAX,
+        }
+    }
+    /// Fail writes and resizes which would make the file larger than `max`
+    /// bytes.
+    pub fn with_max_size(mut self, max: u64) -> Self {
+        self.max_size = max;
+        self
+    }
+
+    /// A copy of the file's current contents.
+    pub fn contents(&self) -> Vec<u8> {
+        self.data.lock().unwrap().clone()
+    }
+
+    // The size a file growing to `size` bytes would have in memory.
+    fn checked_size(&self, size: u64) -> Result<usize, Error> {
+        if size > self.max_size {
+            return Err(
+                Error::from(Errno::Fbig).context("memory file would exceed its maximum size")
+            );
+        }
+        usize::try_from(size).map_err(|_| Error::overflow().context("file too large for memory"))
+    }
+
+    fn read_at(&self, bufs: &mut [io::IoSliceMut<'_>], offset: u64) -> u64 {
+        let data = self.data.lock().unwrap();
+        let start = usize::try_from(offset).map_or(data.len(), |o| o.min(data.len()));
+        let mut rest = &data[start..];
+        let mut n = 0;
+        for buf in bufs {
+            let len = buf.len().min(rest.len());
+            buf[..len].copy_from_slice(&rest[..len]);
+            rest = &rest[len..];
+            n += len as u64;
+        }
+        n
+    }
+
+    fn write_at(&self, bufs: &[io::IoSlice<'_>], offset: u64) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if len == 0 {
+            return Ok(0);
+        }
+        let end = offset
+            .checked_add(len as u64)
+            .ok_or_else(|| Error::overflow().context("write extends past u64::MAX"))?;
+        let end = self.checked_size(end)?;
+        let mut data = self.data.lock().unwrap();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        let mut at = end - len;
+        for buf in bufs {
+            data[at..at + buf.len()].copy_from_slice(buf);
+            at += buf.len();
+        }
+        Ok(len as u64)
+    }
+}
+
+impl Default for MemFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for MemFile {
+    /// Another file over the same contents, with its own offset, starting at
+    /// zero.
+    fn clone(&self) -> Self {
+        MemFile {
+            data: self.data.clone(),
+            position: Mutex::new(0),
+            max_size: self.max_size,
+        }
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for MemFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::RegularFile,
+            nlink: 1,
+            size: self.data.lock().unwrap().len() as u64,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        let size = self.checked_size(size)?;
+        self.data.lock().unwrap().resize(size, 0);
+        Ok(())
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        let end = offset
+            .checked_add(len)
+            .ok_or_else(|| Error::overflow().context("range extends past u64::MAX"))?;
+        let end = self.checked_size(end)?;
+        let mut data = self.data.lock().unwrap();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        Ok(())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut position = self.position.lock().unwrap();
+        let n = self.read_at(bufs, *position);
+        *position += n;
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        Ok(self.read_at(bufs, offset))
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let mut position = self.position.lock().unwrap();
+        let n = self.write_at(bufs, *position)?;
+        *position += n;
+        Ok(n)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.write_at(bufs, offset)
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let current = *self.position.lock().unwrap();
+        let new = seek_target(pos, current, || async {
+            Ok(self.data.lock().unwrap().len() as u64)
+        })
+        .await?;
+        *self.position.lock().unwrap() = new;
+        Ok(new)
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        Ok(self.read_at(&mut [io::IoSliceMut::new(buf)], position))
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        Ok((self.data.lock().unwrap().len() as u64).saturating_sub(position))
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MemFile;
+    use std::io::{IoSlice, IoSliceMut, SeekFrom};
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    async fn read_all(f: &MemFile) -> Vec<u8> {
+        let mut buf = vec![0; 64];
+        let n = f
+            .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+            .await
+            .unwrap();
+        buf.truncate(n as usize);
+        buf
+    }
+
+    #[tokio::test]
+    async fn writes_grow_the_file() {
+        let f = MemFile::new();
+        f.write_vectored(&[IoSlice::new(b"scr"), IoSlice::new(b"atch")])
+            .await
+            .unwrap();
+        assert_eq!(read_all(&f).await, b"scratch");
+
+        // Writing past the end fills the gap with zeros.
+        f.write_vectored_at(&[IoSlice::new(b"!")], 10)
+            .await
+            .unwrap();
+        assert_eq!(read_all(&f).await, b"scratch\0\0\0!");
+        assert_eq!(f.get_filestat().await.unwrap().size, 11);
+
+        // The offset picks up where the last stream write left off.
+        assert_eq!(f.seek(SeekFrom::Current(0)).await.unwrap(), 7);
+        assert_eq!(f.seek(SeekFrom::End(0)).await.unwrap(), 11);
+    }
+
+    #[tokio::test]
+    async fn truncate_and_extend() {
+        let f = MemFile::from_vec(b"scratch file".to_vec()).with_max_size(16);
+        f.set_filestat_size(7).await.unwrap();
+        assert_eq!(read_all(&f).await, b"scratch");
+        f.set_filestat_size(9).await.unwrap();
+        assert_eq!(read_all(&f).await, b"scratch\0\0");
+
+        let err = f.set_filestat_size(17).await.unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Fbig);
+        let err = f
+            .write_vectored_at(&[IoSlice::new(b"xx")], 15)
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Fbig);
+        assert_eq!(f.get_filestat().await.unwrap().size, 9);
+    }
+
+    #[tokio::test]
+    async fn clones_share_contents_but_not_offset() {
+        let host = MemFile::new();
+        let guest = host.clone();
+        guest
+            .write_vectored(&[IoSlice::new(b"from the guest")])
+            .await
+            .unwrap();
+        assert_eq!(host.contents(), b"from the guest");
+        assert_eq!(host.seek(SeekFrom::Current(0)).await.unwrap(), 0);
+
+        host.set_filestat_size(4).await.unwrap();
+        assert_eq!(guest.get_filestat().await.unwrap().size, 4);
+        let mut buf = [0; 8];
+        let n = guest
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(n, 0, "offset is past the truncated end");
+    }
+}
@@ -0,0 +1,51 @@
+use crate::net::TcpStream;
+use wasi_common::Error;
+
+impl TcpStream {
+    /// Send `buf` with `MSG_MORE`, telling the kernel more data is on its
+    /// way, so that a message assembled from several writes goes out in as
+    /// few segments as possible. Data is held back until a write without the
+    /// flag, such as an ordinary `write_vectored`.
+    ///
+    /// This is a cork for a single write: where [`TcpStream::set_cork`] holds
+    /// data until it's cleared, this holds it until the next plain write. If
+    /// the connection is corked as well, data stays held until the cork is
+    /// cleared. Returns the number of bytes sent.
+    ///
+    /// Only supported on Linux.
+    pub fn send_more(&self, buf: &[u8]) -> Result<u64, Error> {
+        sys::send_more(&self.inner, buf)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::Error;
+
+    pub(super) fn send_more(fd: impl AsFd, buf: &[u8]) -> Result<u64, Error> {
+        let n = unsafe {
+            libc::send(
+                fd.as_fd().as_raw_fd(),
+                buf.as_ptr().cast(),
+                buf.len(),
+                libc::MSG_MORE | libc::MSG_NOSIGNAL,
+            )
+        };
+        if n == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(n as u64)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod sys {
+    use wasi_common::{Error, ErrorExt};
+
+    pub(super) fn send_more<F>(_fd: F, _buf: &[u8]) -> Result<u64, Error> {
+        Err(Error::not_supported().context("MSG_MORE is only supported on Linux"))
+    }
+}
@@ -1,6 +1,1144 @@
-pub use crate::file::TcpListener;
-pub use crate::file::TcpStream;
+use crate::block_on_dummy_executor;
+#[cfg(windows)]
+use io_extras::os::windows::{AsRawHandleOrSocket, RawHandleOrSocket};
+#[cfg(not(windows))]
+use io_lifetimes::AsFd;
+use io_lifetimes::AsSocketlike;
+use std::any::Any;
+use std::future::Future;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error, ErrorExt,
+};
+
+pub use crate::file::{stderr, stdout, Stderr, Stdout};
+pub use crate::stdin::{stdin, Stdin};
+
+macro_rules! wasi_listener_impl {
+    ($ty:ty) => {
+        #[wiggle::async_trait]
+        impl WasiFile for $ty {
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+            #[cfg(unix)]
+            fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+                Some(self.0.as_fd())
+            }
+            #[cfg(windows)]
+            fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+                Some(self.0.as_raw_handle_or_socket())
+            }
+            async fn get_filetype(&self) -> Result<FileType, Error> {
+                block_on_dummy_executor(|| self.0.get_filetype())
+            }
+            async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+                let mut fdflags = block_on_dummy_executor(|| self.0.get_fdflags())?;
+                fdflags.set(FdFlags::NONBLOCK, self.1);
+                Ok(fdflags)
+            }
+            async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+                // The socket itself stays non-blocking, except on Windows;
+                // see `accept_with`.
+                #[cfg(windows)]
+                block_on_dummy_executor(|| self.0.set_fdflags(fdflags))?;
+                #[cfg(not(windows))]
+                {
+                    if fdflags != FdFlags::NONBLOCK && !fdflags.is_empty() {
+                        return Err(Error::not_supported()
+                            .context("cannot set anything else than NONBLOCK"));
+                    }
+                }
+                self.1 = fdflags.contains(FdFlags::NONBLOCK);
+                Ok(())
+            }
+            async fn get_filestat(&self) -> Result<Filestat, Error> {
+                block_on_dummy_executor(|| self.0.get_filestat())
+            }
+            fn num_ready_bytes(&self) -> Result<u64, Error> {
+                self.0.num_ready_bytes()
+            }
+
+            #[cfg(not(windows))]
+            async fn readable(&self) -> Result<(), Error> {
+                crate::file::wait_readable(self.0.as_fd()).await
+            }
+
+            #[cfg(not(windows))]
+            async fn writable(&self) -> Result<(), Error> {
+                crate::file::wait_writable(self.0.as_fd()).await
+            }
+
+            async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+                let (stream, _peer_addr) = self.accept(fdflags).await?;
+                Ok(Box::new(stream))
+            }
+        }
+        #[cfg(windows)]
+        impl AsRawHandleOrSocket for $ty {
+            #[inline]
+            fn as_raw_handle_or_socket(&self) -> RawHandleOrSocket {
+                self.0.as_raw_handle_or_socket()
+            }
+        }
+    };
+}
+
+macro_rules! wasi_stream_impl {
+    ($ty:ty) => {
+        impl $ty {
+            /// Duplicate this socket's descriptor, sharing the same open file
+            /// description. Timeouts are not carried over to the duplicate.
+            pub fn try_clone(&self) -> Result<Box<dyn WasiFile>, Error> {
+                let inner = self.inner.try_clone()?;
+                Ok(Box::new(Self::from_inner(inner)))
+            }
+            /// Read up to and including the next `delim` byte, or until `max` bytes
+            /// have been read. Bytes after the delimiter are left in the socket's
+            /// receive queue. Returns an empty buffer at EOF.
+            pub async fn read_until(&self, delim: u8, max: usize) -> Result<Vec<u8>, Error> {
+                crate::delimited::read_until(self, delim, max).await
+            }
+            /// Read until every buffer in `bufs` is full, as with `MSG_WAITALL`,
+            /// for a fixed-size message such as a protocol header.
+            ///
+            /// Fails with `EIO` if the peer closes the connection first, as
+            /// with [`io::ErrorKind::UnexpectedEof`]; what was read by then is
+            /// left in `bufs`.
+            pub async fn read_exact_vectored(
+                &self,
+                bufs: &mut [io::IoSliceMut<'_>],
+            ) -> Result<(), Error> {
+                // The buffer being filled, and how much of it is.
+                let (mut ix, mut filled) = (0, 0);
+                loop {
+                    while ix < bufs.len() && filled == bufs[ix].len() {
+                        ix += 1;
+                        filled = 0;
+                    }
+                    let (first, rest) = match bufs[ix..].split_first_mut() {
+                        Some(split) => split,
+                        None => return Ok(()),
+                    };
+                    let mut remaining = Vec::with_capacity(1 + rest.len());
+                    remaining.push(io::IoSliceMut::new(&mut first[filled..]));
+                    remaining.extend(rest.iter_mut().map(|b| io::IoSliceMut::new(&mut b[..])));
+                    let mut n = match self.read_vectored(&mut remaining).await {
+                        Ok(0) => {
+                            return Err(
+                                Error::io().context("connection closed before the read was filled")
+                            )
+                        }
+                        Ok(n) => n as usize,
+                        // Readiness can be spurious, so wait for more to
+                        // arrive rather than retrying straight away.
+                        Err(e) if e.downcast_ref() == Some(&Errno::Again) => {
+                            self.readable().await?;
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    while n > 0 {
+                        let take = n.min(bufs[ix].len() - filled);
+                        filled += take;
+                        n -= take;
+                        if filled == bufs[ix].len() {
+                            ix += 1;
+                            filled = 0;
+                        }
+                    }
+                }
+            }
+            /// Set the timeout applied to each `read_vectored` on this socket.
+            ///
+            /// As with [`std::net::TcpStream::set_read_timeout`], `None` waits
+            /// indefinitely and a zero duration is rejected. When set, a read
+            /// first waits for the reactor to report the socket readable, and
+            /// fails with `ETIMEDOUT` if that doesn't happen in time.
+            ///
+            /// Not supported on Windows, where there is no reactor readiness
+            /// to wait on.
+            pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+                Timeouts::set(&self.timeouts.read, timeout)
+            }
+            pub fn read_timeout(&self) -> Option<Duration> {
+                *self.timeouts.read.lock().unwrap()
+            }
+            /// Set the timeout applied to each `write_vectored` on this socket.
+            ///
+            /// This is the writing counterpart of [`Self::set_read_timeout`].
+            pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+                Timeouts::set(&self.timeouts.write, timeout)
+            }
+            pub fn write_timeout(&self) -> Option<Duration> {
+                *self.timeouts.write.lock().unwrap()
+            }
+        }
+
+        #[wiggle::async_trait]
+        impl WasiFile for $ty {
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+            #[cfg(unix)]
+            fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+                Some(self.inner.as_fd())
+            }
+            #[cfg(windows)]
+            fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+                Some(self.inner.as_raw_handle_or_socket())
+            }
+            async fn get_filetype(&self) -> Result<FileType, Error> {
+                block_on_dummy_executor(|| self.inner.get_filetype())
+            }
+            async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+                block_on_dummy_executor(|| self.inner.get_fdflags())
+            }
+            async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+                block_on_dummy_executor(|| self.inner.set_fdflags(fdflags))
+            }
+            async fn get_filestat(&self) -> Result<Filestat, Error> {
+                block_on_dummy_executor(|| self.inner.get_filestat())
+            }
+            async fn read_vectored<'a>(
+                &self,
+                bufs: &mut [io::IoSliceMut<'a>],
+            ) -> Result<u64, Error> {
+                // An empty read would otherwise wait for data it won't take.
+                if crate::iovec::iovec_len(bufs.iter().map(|b| b.len()))? == 0 {
+                    return Ok(0);
+                }
+                Timeouts::wait(self.read_timeout(), self.readable()).await?;
+                block_on_dummy_executor(move || self.inner.read_vectored(bufs))
+            }
+            async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+                if crate::iovec::iovec_len(bufs.iter().map(|b| b.len()))? == 0 {
+                    return Ok(0);
+                }
+                Timeouts::wait(self.write_timeout(), self.writable()).await?;
+                block_on_dummy_executor(move || async move { write_stream(&self.inner, bufs) })
+            }
+            async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+                block_on_dummy_executor(move || self.inner.peek(buf))
+            }
+            fn num_ready_bytes(&self) -> Result<u64, Error> {
+                self.inner.num_ready_bytes()
+            }
+
+            #[cfg(not(windows))]
+            async fn readable(&self) -> Result<(), Error> {
+                crate::file::wait_readable(self.inner.as_fd()).await
+            }
+
+            #[cfg(not(windows))]
+            async fn writable(&self) -> Result<(), Error> {
+                crate::file::wait_writable(self.inner.as_fd()).await
+            }
+        }
+        #[cfg(windows)]
+        impl AsRawHandleOrSocket for $ty {
+            #[inline]
+            fn as_raw_handle_or_socket(&self) -> RawHandleOrSocket {
+                self.inner.as_raw_handle_or_socket()
+            }
+        }
+    };
+}
+
+// Write to a stream socket without raising `SIGPIPE` if the peer has gone, so
+// that the write fails with `EPIPE` instead, whatever the host does with the
+// signal. Apple's platforms have no `MSG_NOSIGNAL`, and set `SO_NOSIGPIPE` on
+// the socket instead; see `no_sigpipe`.
 #[cfg(unix)]
-pub use crate::file::UnixListener;
+fn write_stream(socket: impl AsFd, bufs: &[io::IoSlice<'_>]) -> Result<u64, Error> {
+    use std::os::unix::io::AsRawFd;
+    #[cfg(not(target_vendor = "apple"))]
+    const FLAGS: libc::c_int = libc::MSG_NOSIGNAL;
+    #[cfg(target_vendor = "apple")]
+    const FLAGS: libc::c_int = 0;
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    // `IoSlice` is guaranteed to have the same layout as `iovec`.
+    msg.msg_iov = bufs.as_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = bufs.len() as _;
+    let n = unsafe { libc::sendmsg(socket.as_fd().as_raw_fd(), &msg, FLAGS) };
+    if n == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(n as u64)
+}
+
+// Windows has no `SIGPIPE`; writes to a closed socket just fail.
+#[cfg(windows)]
+fn write_stream(socket: impl AsSocketlike, bufs: &[io::IoSlice<'_>]) -> Result<u64, Error> {
+    use std::io::Write;
+    let socket = socket.as_socketlike_view::<std::net::TcpStream>();
+    Ok((&*socket).write_vectored(bufs)? as u64)
+}
+
+#[cfg(target_vendor = "apple")]
+fn no_sigpipe(socket: impl AsFd) {
+    use std::os::unix::io::AsRawFd;
+    let on: libc::c_int = 1;
+    // This can't fail for a valid socket, and if it somehow does, the socket
+    // is no worse off than before.
+    unsafe {
+        libc::setsockopt(
+            socket.as_fd().as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_NOSIGPIPE,
+            (&on as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+}
+
+// Outside Windows, a listener's socket is always non-blocking, so that an
+// accept can be tried without committing a thread to waiting for it, and the
+// guest's `NONBLOCK` flag is kept alongside it. See `accept_with`.
+//
+// Returns whether the socket was already non-blocking, which is where the
+// guest's flag starts out.
+#[cfg(not(windows))]
+fn listener_nonblocking(listener: impl AsFd) -> bool {
+    use std::os::unix::io::AsRawFd;
+    let fd = listener.as_fd().as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 {
+        return false;
+    }
+    // This can't fail for a valid descriptor.
+    unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+    flags & libc::O_NONBLOCK != 0
+}
+
+#[cfg(windows)]
+fn listener_nonblocking<T>(_listener: T) -> bool {
+    false
+}
+
+// Accept from a listener's socket. If no connection is queued, this fails
+// with `EAGAIN` unless `wait` is set, in which case it waits for the reactor
+// to report one and tries again, as another task may have taken the one it
+// reported. Waiting on the reactor rather than in the kernel means that a
+// runtime shutdown fails the accept with `ECANCELED`, rather than blocking a
+// thread forever.
+//
+// Windows has no reactor readiness to wait on, so there the socket is only
+// non-blocking if the guest asked for that, and a blocking accept blocks in
+// the kernel.
+async fn accept_with<T>(
+    listener: &(impl WasiFile + ?Sized),
+    wait: bool,
+    mut accept: impl FnMut() -> io::Result<T>,
+) -> Result<T, Error> {
+    loop {
+        #[cfg(windows)]
+        let result = tokio::task::block_in_place(&mut accept);
+        #[cfg(not(windows))]
+        let result = accept();
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && wait => listener.readable().await?,
+            result => return Ok(result?),
+        }
+    }
+}
+
+pub struct TcpListener(
+    pub(crate) wasi_cap_std_sync::net::TcpListener,
+    // Whether the guest has set `NONBLOCK`.
+    pub(crate) bool,
+);
+
+impl TcpListener {
+    pub(crate) fn from_inner(listener: wasi_cap_std_sync::net::TcpListener) -> Self {
+        let nonblocking = listener_nonblocking(&listener);
+        TcpListener(listener, nonblocking)
+    }
+    pub fn from_cap_std(listener: cap_std::net::TcpListener) -> Self {
+        Self::from_inner(wasi_cap_std_sync::net::TcpListener::from_cap_std(listener))
+    }
+    /// Duplicate this socket's descriptor, sharing the same open file description.
+    pub fn try_clone(&self) -> Result<Box<dyn WasiFile>, Error> {
+        let listener = self.0.try_clone()?;
+        Ok(Box::new(TcpListener(listener, self.1)))
+    }
+    /// The address this listener is bound to, as with `getsockname`.
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr, Error> {
+        Ok(self
+            .0
+            .as_socketlike_view::<std::net::TcpListener>()
+            .local_addr()?)
+    }
+    /// Accept a connection, applying `fdflags` to it.
+    ///
+    /// The peer's address comes back from `accept` itself, and is also cached
+    /// on the stream so that [`TcpStream::peer_addr`] doesn't need another
+    /// syscall. `sock_accept` goes through here too.
+    ///
+    /// If no connection is queued, this fails with `EAGAIN` if the listener
+    /// has `NONBLOCK` set. Otherwise, except on Windows, it waits for the
+    /// reactor to report a connection, so that if the runtime shuts down in
+    /// the meantime, the accept fails with `ECANCELED` rather than blocking a
+    /// thread forever.
+    pub async fn accept(
+        &self,
+        fdflags: FdFlags,
+    ) -> Result<(TcpStream, std::net::SocketAddr), Error> {
+        self.accept_inner(fdflags, !self.1).await
+    }
+    // Accept a connection if one is queued, failing with `EAGAIN` rather than
+    // waiting for one whatever the guest's `NONBLOCK` flag says, for wrappers
+    // which need to wait on something else as well.
+    pub(crate) async fn accept_now(
+        &self,
+        fdflags: FdFlags,
+    ) -> Result<(TcpStream, std::net::SocketAddr), Error> {
+        self.accept_inner(fdflags, false).await
+    }
+    async fn accept_inner(
+        &self,
+        fdflags: FdFlags,
+        wait: bool,
+    ) -> Result<(TcpStream, std::net::SocketAddr), Error> {
+        let (stream, peer_addr) = accept_with(self, wait, || {
+            self.0
+                .as_socketlike_view::<std::net::TcpListener>()
+                .accept()
+        })
+        .await?;
+        let mut stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(stream));
+        stream.peer_addr = Some(peer_addr);
+        stream.set_fdflags(fdflags).await?;
+        Ok((stream, peer_addr))
+    }
+    /// Accept a connection as with [`TcpListener::accept`], or return
+    /// `Ok(None)` if none arrives within `timeout`, so an accept loop can do
+    /// housekeeping in between connections.
+    ///
+    /// Only the wait for a connection is timed, and the accept itself happens
+    /// after that wait, so a timeout never loses a connection: it stays
+    /// queued for the next call. If another task accepts the connection
+    /// first, this goes on to wait for the next one for whatever remains of
+    /// the timeout. The guest's `NONBLOCK` flag doesn't apply, since the
+    /// timeout says how long to wait. Not supported on Windows.
+    pub async fn accept_timeout(
+        &self,
+        fdflags: FdFlags,
+        timeout: Duration,
+    ) -> Result<Option<(TcpStream, std::net::SocketAddr)>, Error> {
+        if cfg!(windows) {
+            return Err(Error::not_supported().context("accept timeouts require AsyncFd"));
+        }
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match self.accept_now(fdflags).await {
+                Err(e) if e.downcast_ref() == Some(&Errno::Again) => {}
+                result => return result.map(Some),
+            }
+            match tokio::time::timeout_at(deadline, self.readable()).await {
+                Ok(ready) => ready?,
+                Err(_elapsed) => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl TcpListener {
+    /// Create `count` listeners all bound to `addr` with `SO_REUSEPORT`, so
+    /// that the kernel spreads incoming connections across them and each
+    /// worker task can accept from a listener of its own.
+    ///
+    /// If `addr` has port 0, every listener shares the port picked for the
+    /// first. Binding uses ambient authority, just as the `cap_std` functions
+    /// which take an `AmbientAuthority` do.
+    ///
+    /// Only Linux and Android balance connections across such a group, so on
+    /// other platforms this fails with `ENOTSUP`.
+    pub fn reuseport_group(
+        addr: std::net::SocketAddr,
+        count: usize,
+        ambient_authority: cap_std::AmbientAuthority,
+    ) -> Result<Vec<TcpListener>, Error> {
+        let _ = ambient_authority;
+        let mut addr = addr;
+        let mut group = Vec::with_capacity(count);
+        for _ in 0..count {
+            let listener = inet::bind(addr, libc::IPPROTO_TCP, true)?;
+            addr = listener.local_addr()?;
+            group.push(Self::from_cap_std(cap_std::net::TcpListener::from_std(
+                listener,
+            )));
+        }
+        Ok(group)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+impl TcpListener {
+    /// Create `count` listeners all bound to `addr` with `SO_REUSEPORT`.
+    ///
+    /// Only Linux and Android balance connections across such a group, so
+    /// this always fails with `ENOTSUP` here.
+    pub fn reuseport_group(
+        _addr: std::net::SocketAddr,
+        _count: usize,
+        _ambient_authority: cap_std::AmbientAuthority,
+    ) -> Result<Vec<TcpListener>, Error> {
+        Err(Error::not_supported().context("SO_REUSEPORT load balancing requires Linux"))
+    }
+}
+
+wasi_listener_impl!(TcpListener);
+
+pub struct TcpStream {
+    pub(crate) inner: wasi_cap_std_sync::net::TcpStream,
+    timeouts: Timeouts,
+    // Known when the stream came from `TcpListener::accept`.
+    peer_addr: Option<std::net::SocketAddr>,
+}
+
+impl TcpStream {
+    pub(crate) fn from_inner(inner: wasi_cap_std_sync::net::TcpStream) -> Self {
+        #[cfg(target_vendor = "apple")]
+        no_sigpipe(&inner);
+        TcpStream {
+            inner,
+            timeouts: Timeouts::default(),
+            peer_addr: None,
+        }
+    }
+    pub fn from_cap_std(stream: cap_std::net::TcpStream) -> Self {
+        Self::from_inner(wasi_cap_std_sync::net::TcpStream::from_cap_std(stream))
+    }
+    /// The local address of this connection, as with `getsockname`.
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr, Error> {
+        Ok(self
+            .inner
+            .as_socketlike_view::<std::net::TcpStream>()
+            .local_addr()?)
+    }
+    /// The remote address of this connection, as with `getpeername`.
+    pub fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
+        if let Some(addr) = self.peer_addr {
+            return Ok(addr);
+        }
+        Ok(self
+            .inner
+            .as_socketlike_view::<std::net::TcpStream>()
+            .peer_addr()?)
+    }
+    /// Whether the socket is connected to a peer, which is to say whether
+    /// `getpeername` succeeds. This is a cheap check that a stream from an
+    /// asynchronous connect has completed, but a connection the peer has
+    /// since closed may still count as connected.
+    pub fn is_connected(&self) -> bool {
+        self.inner
+            .as_socketlike_view::<std::net::TcpStream>()
+            .peer_addr()
+            .is_ok()
+    }
+    /// Close the connection cleanly: shut down the write half, so the peer
+    /// sees EOF, then read whatever the peer still sends until it closes its
+    /// own write half, and return it.
+    ///
+    /// Stops reading once `timeout` has passed, for a peer which never
+    /// closes, and returns what arrived by then. The stream itself is closed
+    /// when it's dropped.
+    pub async fn shutdown_and_drain(&self, timeout: Duration) -> Result<Vec<u8>, Error> {
+        self.inner
+            .as_socketlike_view::<std::net::TcpStream>()
+            .shutdown(std::net::Shutdown::Write)?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut drained = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let read = self.read_vectored(&mut [io::IoSliceMut::new(&mut buf)]);
+            match tokio::time::timeout_at(deadline, read).await {
+                Ok(n) => match n? {
+                    0 => break,
+                    n => drained.extend_from_slice(&buf[..n as usize]),
+                },
+                Err(_) => break,
+            }
+        }
+        Ok(drained)
+    }
+}
+
+wasi_stream_impl!(TcpStream);
+
 #[cfg(unix)]
-pub use crate::file::UnixStream;
+pub struct UnixListener(
+    pub(crate) wasi_cap_std_sync::net::UnixListener,
+    // Whether the guest has set `NONBLOCK`, as for `TcpListener`.
+    pub(crate) bool,
+);
+
+#[cfg(unix)]
+impl UnixListener {
+    pub(crate) fn from_inner(listener: wasi_cap_std_sync::net::UnixListener) -> Self {
+        let nonblocking = listener_nonblocking(&listener);
+        UnixListener(listener, nonblocking)
+    }
+    pub fn from_cap_std(listener: cap_std::os::unix::net::UnixListener) -> Self {
+        Self::from_inner(wasi_cap_std_sync::net::UnixListener::from_cap_std(listener))
+    }
+    /// Duplicate this socket's descriptor, sharing the same open file description.
+    pub fn try_clone(&self) -> Result<Box<dyn WasiFile>, Error> {
+        let listener = self.0.try_clone()?;
+        Ok(Box::new(UnixListener(listener, self.1)))
+    }
+    /// The address this listener is bound to, as with `getsockname`.
+    pub fn local_addr(&self) -> Result<std::os::unix::net::SocketAddr, Error> {
+        Ok(self
+            .0
+            .as_socketlike_view::<std::os::unix::net::UnixListener>()
+            .local_addr()?)
+    }
+    /// Accept a connection, applying `fdflags` to it. As with
+    /// [`TcpListener::accept`], the peer's address is cached on the stream,
+    /// `NONBLOCK` is honored, and a runtime shutdown fails the wait with
+    /// `ECANCELED`.
+    pub async fn accept(
+        &self,
+        fdflags: FdFlags,
+    ) -> Result<(UnixStream, std::os::unix::net::SocketAddr), Error> {
+        let (stream, peer_addr) = accept_with(self, !self.1, || {
+            self.0
+                .as_socketlike_view::<std::os::unix::net::UnixListener>()
+                .accept()
+        })
+        .await?;
+        let mut stream =
+            UnixStream::from_cap_std(cap_std::os::unix::net::UnixStream::from_std(stream));
+        stream.peer_addr = Some(peer_addr.clone());
+        stream.set_fdflags(fdflags).await?;
+        Ok((stream, peer_addr))
+    }
+}
+
+#[cfg(unix)]
+wasi_listener_impl!(UnixListener);
+
+#[cfg(unix)]
+pub struct UnixStream {
+    pub(crate) inner: wasi_cap_std_sync::net::UnixStream,
+    timeouts: Timeouts,
+    // Known when the stream came from `UnixListener::accept`.
+    peer_addr: Option<std::os::unix::net::SocketAddr>,
+}
+
+#[cfg(unix)]
+impl UnixStream {
+    fn from_inner(inner: wasi_cap_std_sync::net::UnixStream) -> Self {
+        #[cfg(target_vendor = "apple")]
+        no_sigpipe(&inner);
+        UnixStream {
+            inner,
+            timeouts: Timeouts::default(),
+            peer_addr: None,
+        }
+    }
+    pub fn from_cap_std(stream: cap_std::os::unix::net::UnixStream) -> Self {
+        Self::from_inner(wasi_cap_std_sync::net::UnixStream::from_cap_std(stream))
+    }
+    /// The local address of this connection, as with `getsockname`.
+    pub fn local_addr(&self) -> Result<std::os::unix::net::SocketAddr, Error> {
+        Ok(self
+            .inner
+            .as_socketlike_view::<std::os::unix::net::UnixStream>()
+            .local_addr()?)
+    }
+    /// The remote address of this connection, as with `getpeername`.
+    pub fn peer_addr(&self) -> Result<std::os::unix::net::SocketAddr, Error> {
+        if let Some(addr) = &self.peer_addr {
+            return Ok(addr.clone());
+        }
+        Ok(self
+            .inner
+            .as_socketlike_view::<std::os::unix::net::UnixStream>()
+            .peer_addr()?)
+    }
+}
+
+#[cfg(unix)]
+wasi_stream_impl!(UnixStream);
+
+/// A listening one-to-one style SCTP socket, which is an `IPPROTO_SCTP`
+/// socket of type `SOCK_STREAM`.
+///
+/// SCTP sockets are only available on Linux; elsewhere [`SctpListener::bind`]
+/// and [`SctpStream::connect`] fail with `ENOTSUP`.
+#[cfg(feature = "sctp")]
+pub struct SctpListener(
+    pub(crate) wasi_cap_std_sync::net::TcpListener,
+    // Whether the guest has set `NONBLOCK`, as for `TcpListener`.
+    pub(crate) bool,
+);
+
+#[cfg(feature = "sctp")]
+impl SctpListener {
+    /// Bind to `addr` and start listening for associations.
+    pub fn bind(addr: std::net::SocketAddr) -> Result<Self, Error> {
+        let listener = sctp::bind(addr)?;
+        let nonblocking = listener_nonblocking(&listener);
+        Ok(SctpListener(
+            wasi_cap_std_sync::net::TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(
+                listener,
+            )),
+            nonblocking,
+        ))
+    }
+    /// The address this listener is bound to, as with `getsockname`.
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr, Error> {
+        Ok(self
+            .0
+            .as_socketlike_view::<std::net::TcpListener>()
+            .local_addr()?)
+    }
+    /// Accept an association, applying `fdflags` to it, as with
+    /// [`TcpListener::accept`].
+    pub async fn accept(
+        &self,
+        fdflags: FdFlags,
+    ) -> Result<(SctpStream, std::net::SocketAddr), Error> {
+        let (stream, peer_addr) = accept_with(self, !self.1, || {
+            self.0
+                .as_socketlike_view::<std::net::TcpListener>()
+                .accept()
+        })
+        .await?;
+        let mut stream = SctpStream::from_std(stream);
+        stream.set_fdflags(fdflags).await?;
+        Ok((stream, peer_addr))
+    }
+}
+
+#[cfg(feature = "sctp")]
+wasi_listener_impl!(SctpListener);
+
+/// A one-to-one style SCTP association. See [`SctpListener`].
+///
+/// Reads and writes see the association as a byte stream, as with
+/// [`TcpStream`].
+#[cfg(feature = "sctp")]
+pub struct SctpStream {
+    pub(crate) inner: wasi_cap_std_sync::net::TcpStream,
+    timeouts: Timeouts,
+}
+
+#[cfg(feature = "sctp")]
+impl SctpStream {
+    fn from_inner(inner: wasi_cap_std_sync::net::TcpStream) -> Self {
+        #[cfg(target_vendor = "apple")]
+        no_sigpipe(&inner);
+        SctpStream {
+            inner,
+            timeouts: Timeouts::default(),
+        }
+    }
+    // The kernel handles an SCTP socket of this style with the same calls as
+    // TCP, so std's TCP types can own it.
+    fn from_std(stream: std::net::TcpStream) -> Self {
+        Self::from_inner(wasi_cap_std_sync::net::TcpStream::from_cap_std(
+            cap_std::net::TcpStream::from_std(stream),
+        ))
+    }
+    /// Open an association with `addr`.
+    pub async fn connect(addr: std::net::SocketAddr) -> Result<Self, Error> {
+        let stream = tokio::task::block_in_place(|| sctp::connect(addr))?;
+        Ok(Self::from_std(stream))
+    }
+}
+
+#[cfg(feature = "sctp")]
+wasi_stream_impl!(SctpStream);
+
+#[cfg(all(feature = "sctp", target_os = "linux"))]
+mod sctp {
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use wasi_common::Error;
+
+    pub(super) fn connect(addr: SocketAddr) -> Result<TcpStream, Error> {
+        super::inet::connect(addr, libc::IPPROTO_SCTP)
+    }
+
+    pub(super) fn bind(addr: SocketAddr) -> Result<TcpListener, Error> {
+        super::inet::bind(addr, libc::IPPROTO_SCTP, false)
+    }
+}
+
+#[cfg(all(feature = "sctp", not(target_os = "linux")))]
+mod sctp {
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use wasi_common::{Error, ErrorExt};
+
+    pub(super) fn connect(_addr: SocketAddr) -> Result<TcpStream, Error> {
+        Err(Error::not_supported().context("SCTP is only supported on Linux"))
+    }
+
+    pub(super) fn bind(_addr: SocketAddr) -> Result<TcpListener, Error> {
+        Err(Error::not_supported().context("SCTP is only supported on Linux"))
+    }
+}
+
+// Stream sockets set up by hand, for the options and protocols std's
+// constructors don't offer.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod inet {
+    use std::io;
+    use std::mem;
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+    use wasi_common::Error;
+
+    fn socket(addr: &SocketAddr, protocol: libc::c_int) -> io::Result<RawFd> {
+        let family = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = unsafe { libc::socket(family, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, protocol) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(fd)
+    }
+
+    fn sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let len = match addr {
+            SocketAddr::V4(a) => {
+                let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = a.port().to_be();
+                sin.sin_addr.s_addr = u32::from_ne_bytes(a.ip().octets());
+                mem::size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(a) => {
+                let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = a.port().to_be();
+                sin6.sin6_flowinfo = a.flowinfo();
+                sin6.sin6_addr.s6_addr = a.ip().octets();
+                sin6.sin6_scope_id = a.scope_id();
+                mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+
+    #[cfg_attr(not(all(feature = "sctp", target_os = "linux")), allow(dead_code))]
+    pub(super) fn connect(addr: SocketAddr, protocol: libc::c_int) -> Result<TcpStream, Error> {
+        // Take ownership right away, so the socket is closed on any error.
+        let stream = unsafe { TcpStream::from_raw_fd(socket(&addr, protocol)?) };
+        let (storage, len) = sockaddr(&addr);
+        loop {
+            let ret = unsafe {
+                libc::connect(
+                    stream.as_raw_fd(),
+                    &storage as *const _ as *const libc::sockaddr,
+                    len,
+                )
+            };
+            if ret == 0 {
+                return Ok(stream);
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+        }
+    }
+
+    pub(super) fn bind(
+        addr: SocketAddr,
+        protocol: libc::c_int,
+        reuseport: bool,
+    ) -> Result<TcpListener, Error> {
+        let listener = unsafe { TcpListener::from_raw_fd(socket(&addr, protocol)?) };
+        let fd = listener.as_raw_fd();
+        if reuseport {
+            let one: libc::c_int = 1;
+            let ret = unsafe {
+                libc::setsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_REUSEPORT,
+                    &one as *const _ as *const libc::c_void,
+                    mem::size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            };
+            if ret != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        let (storage, len) = sockaddr(&addr);
+        if unsafe { libc::bind(fd, &storage as *const _ as *const libc::sockaddr, len) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        if unsafe { libc::listen(fd, 128) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(listener)
+    }
+}
+
+#[cfg(feature = "interfaces")]
+bitflags::bitflags! {
+    /// The state of a network interface, as reported by [`local_interfaces`].
+    pub struct InterfaceFlags: u32 {
+        const UP             = 0b1;
+        /// The interface has resources allocated, and is ready for traffic.
+        const RUNNING        = 0b10;
+        const LOOPBACK       = 0b100;
+        const BROADCAST      = 0b1000;
+        const POINT_TO_POINT = 0b10000;
+        const MULTICAST      = 0b100000;
+    }
+}
+
+/// One address of a local network interface.
+#[cfg(feature = "interfaces")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub addr: std::net::IpAddr,
+    pub netmask: Option<std::net::IpAddr>,
+    pub flags: InterfaceFlags,
+}
+
+/// List the host's IPv4 and IPv6 interface addresses, one entry per address,
+/// so a guest extension can offer the addresses it's able to bind to.
+///
+/// This is `getifaddrs` on Unix and `GetAdaptersAddresses` on Windows.
+/// Windows only reports the `UP`, `RUNNING`, and `LOOPBACK` flags.
+///
+/// This reveals the host's network configuration, which WASI otherwise
+/// keeps from guests, so it's only built with the `interfaces` feature, and
+/// it's up to the embedder whether any of it reaches a guest.
+#[cfg(feature = "interfaces")]
+pub fn local_interfaces() -> Result<Vec<InterfaceInfo>, Error> {
+    ifaddrs::local_interfaces()
+}
+
+#[cfg(all(feature = "interfaces", unix))]
+mod ifaddrs {
+    use super::{InterfaceFlags, InterfaceInfo};
+    use std::ffi::CStr;
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use wasi_common::Error;
+
+    pub(super) fn local_interfaces() -> Result<Vec<InterfaceInfo>, Error> {
+        let mut head = std::ptr::null_mut();
+        if unsafe { libc::getifaddrs(&mut head) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mut interfaces = Vec::new();
+        let mut next = head;
+        while let Some(ifa) = unsafe { next.as_ref() } {
+            next = ifa.ifa_next;
+            // Skip link-layer and other non-IP entries.
+            if let Some(addr) = unsafe { ip_addr(ifa.ifa_addr) } {
+                interfaces.push(InterfaceInfo {
+                    name: unsafe { CStr::from_ptr(ifa.ifa_name) }
+                        .to_string_lossy()
+                        .into_owned(),
+                    addr,
+                    netmask: unsafe { ip_addr(ifa.ifa_netmask) },
+                    flags: flags(ifa.ifa_flags as libc::c_int),
+                });
+            }
+        }
+        unsafe { libc::freeifaddrs(head) };
+        Ok(interfaces)
+    }
+
+    // The address `sa` points to, if any, and if it's IPv4 or IPv6.
+    unsafe fn ip_addr(sa: *const libc::sockaddr) -> Option<IpAddr> {
+        match sa.as_ref()?.sa_family as libc::c_int {
+            libc::AF_INET => {
+                let sin = &*(sa as *const libc::sockaddr_in);
+                Some(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)).into())
+            }
+            libc::AF_INET6 => {
+                let sin6 = &*(sa as *const libc::sockaddr_in6);
+                Some(Ipv6Addr::from(sin6.sin6_addr.s6_addr).into())
+            }
+            _ => None,
+        }
+    }
+
+    fn flags(raw: libc::c_int) -> InterfaceFlags {
+        let mut flags = InterfaceFlags::empty();
+        for (bit, flag) in [
+            (libc::IFF_UP, InterfaceFlags::UP),
+            (libc::IFF_RUNNING, InterfaceFlags::RUNNING),
+            (libc::IFF_LOOPBACK, InterfaceFlags::LOOPBACK),
+            (libc::IFF_BROADCAST, InterfaceFlags::BROADCAST),
+            (libc::IFF_POINTOPOINT, InterfaceFlags::POINT_TO_POINT),
+            (libc::IFF_MULTICAST, InterfaceFlags::MULTICAST),
+        ] {
+            if raw & bit != 0 {
+                flags |= flag;
+            }
+        }
+        flags
+    }
+}
+
+#[cfg(all(feature = "interfaces", windows))]
+mod ifaddrs {
+    use super::{InterfaceFlags, InterfaceInfo};
+    use std::ffi::OsString;
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::os::windows::ffi::OsStringExt;
+    use wasi_common::Error;
+    use windows_sys::Win32::Foundation::{ERROR_BUFFER_OVERFLOW, NO_ERROR};
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        GetAdaptersAddresses, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER,
+        GAA_FLAG_SKIP_MULTICAST, IF_TYPE_SOFTWARE_LOOPBACK, IP_ADAPTER_ADDRESSES_LH,
+    };
+    use windows_sys::Win32::NetworkManagement::Ndis::IfOperStatusUp;
+    use windows_sys::Win32::Networking::WinSock::{
+        AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6,
+    };
+
+    pub(super) fn local_interfaces() -> Result<Vec<InterfaceInfo>, Error> {
+        let gaa_flags = GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER;
+        let mut size = 16 * 1024;
+        // Adapters can appear between sizing the buffer and filling it, so
+        // keep trying until it's big enough. It's made of `u64`s to be
+        // aligned for `IP_ADAPTER_ADDRESSES_LH`.
+        let buf = loop {
+            let mut buf = vec![0u64; (size as usize + 7) / 8];
+            let ret = unsafe {
+                GetAdaptersAddresses(
+                    AF_UNSPEC as u32,
+                    gaa_flags,
+                    std::ptr::null(),
+                    buf.as_mut_ptr().cast(),
+                    &mut size,
+                )
+            };
+            match ret {
+                NO_ERROR => break buf,
+                ERROR_BUFFER_OVERFLOW => continue,
+                _ => return Err(io::Error::from_raw_os_error(ret as i32).into()),
+            }
+        };
+
+        let mut interfaces = Vec::new();
+        let mut next = buf.as_ptr() as *const IP_ADAPTER_ADDRESSES_LH;
+        while let Some(adapter) = unsafe { next.as_ref() } {
+            next = adapter.Next;
+            let name = unsafe { wide_str(adapter.FriendlyName) };
+            let mut flags = InterfaceFlags::empty();
+            if adapter.OperStatus == IfOperStatusUp {
+                flags |= InterfaceFlags::UP | InterfaceFlags::RUNNING;
+            }
+            if adapter.IfType == IF_TYPE_SOFTWARE_LOOPBACK {
+                flags |= InterfaceFlags::LOOPBACK;
+            }
+            let mut unicast = adapter.FirstUnicastAddress;
+            while let Some(address) = unsafe { unicast.as_ref() } {
+                unicast = address.Next;
+                if let Some(addr) = unsafe { ip_addr(address.Address.lpSockaddr) } {
+                    interfaces.push(InterfaceInfo {
+                        name: name.clone(),
+                        addr,
+                        netmask: Some(netmask(addr, address.OnLinkPrefixLength)),
+                        flags,
+                    });
+                }
+            }
+        }
+        Ok(interfaces)
+    }
+
+    unsafe fn ip_addr(sa: *const SOCKADDR) -> Option<IpAddr> {
+        match sa.as_ref()?.sa_family {
+            AF_INET => {
+                let sin = &*(sa as *const SOCKADDR_IN);
+                Some(Ipv4Addr::from(u32::from_be(sin.sin_addr.S_un.S_addr)).into())
+            }
+            AF_INET6 => {
+                let sin6 = &*(sa as *const SOCKADDR_IN6);
+                Some(Ipv6Addr::from(sin6.sin6_addr.u.Byte).into())
+            }
+            _ => None,
+        }
+    }
+
+    fn netmask(addr: IpAddr, prefix: u8) -> IpAddr {
+        match addr {
+            IpAddr::V4(_) => {
+                let bits = u32::MAX.checked_shl(32 - u32::from(prefix.min(32)));
+                Ipv4Addr::from(bits.unwrap_or(0)).into()
+            }
+            IpAddr::V6(_) => {
+                let bits = u128::MAX.checked_shl(128 - u32::from(prefix.min(128)));
+                Ipv6Addr::from(bits.unwrap_or(0)).into()
+            }
+        }
+    }
+
+    unsafe fn wide_str(s: *const u16) -> String {
+        if s.is_null() {
+            return String::new();
+        }
+        let len = (0..).take_while(|&i| *s.add(i) != 0).count();
+        OsString::from_wide(std::slice::from_raw_parts(s, len))
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Per-socket read and write timeouts.
+///
+/// These are enforced by racing the reactor's readiness notification against
+/// a timer rather than with `SO_RCVTIMEO`/`SO_SNDTIMEO`, which have no effect
+/// on non-blocking sockets.
+#[derive(Default)]
+struct Timeouts {
+    read: Mutex<Option<Duration>>,
+    write: Mutex<Option<Duration>>,
+}
+
+impl Timeouts {
+    fn set(slot: &Mutex<Option<Duration>>, timeout: Option<Duration>) -> Result<(), Error> {
+        if cfg!(windows) {
+            return Err(Error::not_supported().context("socket timeouts require AsyncFd"));
+        }
+        if timeout == Some(Duration::ZERO) {
+            return Err(Error::invalid_argument().context("cannot set a zero duration timeout"));
+        }
+        *slot.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    // Wait for `ready` to resolve, giving up with `ETIMEDOUT` once `timeout`
+    // has elapsed. Without a timeout there is nothing to wait for: the
+    // operation itself will block, or return `EAGAIN`, as usual.
+    async fn wait(
+        timeout: Option<Duration>,
+        ready: impl Future<Output = Result<(), Error>>,
+    ) -> Result<(), Error> {
+        match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, ready).await {
+                Ok(r) => r,
+                Err(_elapsed) => Err(Error::from(Errno::Timedout)),
+            },
+            None => Ok(()),
+        }
+    }
+}
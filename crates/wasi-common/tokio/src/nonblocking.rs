@@ -0,0 +1,112 @@
+use crate::file::File;
+use crate::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use crate::net::{UnixListener, UnixStream};
+use wasi_common::{Error, ErrorExt};
+
+impl File {
+    /// Whether the file's descriptor is in non-blocking mode, as with
+    /// `fcntl(F_GETFL)`. Not supported on Windows.
+    pub fn nonblocking(&self) -> Result<bool, Error> {
+        sys::nonblocking(&self.inner)
+    }
+
+    /// Put the file's descriptor into or out of non-blocking mode directly,
+    /// without going through `set_fdflags`. Not supported on Windows, which
+    /// has no non-blocking mode for files.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        sys::set_file_nonblocking(&self.inner, nonblocking)
+    }
+}
+
+// Sockets wait for the reactor to report them ready before each read or
+// write, but readiness can be spurious, and a blocking socket would then hold
+// up a runtime thread. So they can be made non-blocking, but never made
+// blocking again.
+macro_rules! socket_nonblocking_impl {
+    ($ty:ty, $field:tt) => {
+        impl $ty {
+            /// Whether the socket is in non-blocking mode. Not supported on
+            /// Windows, which can't query this.
+            pub fn nonblocking(&self) -> Result<bool, Error> {
+                sys::nonblocking(&self.$field)
+            }
+
+            /// Put the socket into non-blocking mode. Passing `false` fails
+            /// with `ENOTSUP`: the runtime relies on sockets not blocking
+            /// once they've been switched over.
+            pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+                if !nonblocking {
+                    return Err(Error::not_supported()
+                        .context("sockets can't be returned to blocking mode"));
+                }
+                sys::set_socket_nonblocking(&self.$field)
+            }
+        }
+    };
+}
+
+socket_nonblocking_impl!(TcpListener, 0);
+socket_nonblocking_impl!(TcpStream, inner);
+#[cfg(unix)]
+socket_nonblocking_impl!(UnixListener, 0);
+#[cfg(unix)]
+socket_nonblocking_impl!(UnixStream, inner);
+
+#[cfg(unix)]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::Error;
+
+    fn getfl(fd: impl AsFd) -> Result<libc::c_int, Error> {
+        let flags = unsafe { libc::fcntl(fd.as_fd().as_raw_fd(), libc::F_GETFL) };
+        if flags == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(flags)
+    }
+
+    pub(super) fn nonblocking(fd: impl AsFd) -> Result<bool, Error> {
+        Ok(getfl(fd)? & libc::O_NONBLOCK != 0)
+    }
+
+    pub(super) fn set_file_nonblocking(fd: impl AsFd, nonblocking: bool) -> Result<(), Error> {
+        let flags = getfl(fd.as_fd())?;
+        let flags = if nonblocking {
+            flags | libc::O_NONBLOCK
+        } else {
+            flags & !libc::O_NONBLOCK
+        };
+        if unsafe { libc::fcntl(fd.as_fd().as_raw_fd(), libc::F_SETFL, flags) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_socket_nonblocking(fd: impl AsFd) -> Result<(), Error> {
+        set_file_nonblocking(fd, true)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use io_lifetimes::{AsHandle, AsSocket, AsSocketlike};
+    use wasi_common::{Error, ErrorExt};
+
+    pub(super) fn nonblocking<T>(_handle: T) -> Result<bool, Error> {
+        Err(Error::not_supported().context("Windows can't query non-blocking mode"))
+    }
+
+    pub(super) fn set_file_nonblocking(_handle: impl AsHandle, _: bool) -> Result<(), Error> {
+        Err(Error::not_supported().context("Windows files have no non-blocking mode"))
+    }
+
+    pub(super) fn set_socket_nonblocking(socket: impl AsSocket) -> Result<(), Error> {
+        socket
+            .as_socketlike_view::<std::net::TcpStream>()
+            .set_nonblocking(true)?;
+        Ok(())
+    }
+}
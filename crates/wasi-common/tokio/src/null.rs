@@ -0,0 +1,175 @@
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use wasi_common::{
+    file::{FileType, WasiFile},
+    Error,
+};
+
+/// An in-process equivalent of `/dev/null`: reads are always at EOF, and
+/// writes succeed and are discarded.
+///
+/// Unlike opening the device node, this works when the host's filesystem is
+/// sandboxed away or on platforms without one.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullFile;
+
+impl NullFile {
+    pub fn new() -> Self {
+        NullFile
+    }
+}
+
+/// An in-process equivalent of `/dev/zero`: reads fill every buffer with
+/// zeros, and writes succeed and are discarded.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZeroFile;
+
+impl ZeroFile {
+    pub fn new() -> Self {
+        ZeroFile
+    }
+}
+
+fn discard(bufs: &[io::IoSlice<'_>]) -> Result<u64, Error> {
+    Ok(iovec_len(bufs.iter().map(|b| b.len()))? as u64)
+}
+
+fn zero_fill(bufs: &mut [io::IoSliceMut<'_>]) -> Result<u64, Error> {
+    let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+    for b in bufs {
+        b.fill(0);
+    }
+    Ok(len as u64)
+}
+
+#[wiggle::async_trait]
+impl WasiFile for NullFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+    async fn read_vectored<'a>(&self, _bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        discard(bufs)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        discard(bufs)
+    }
+    async fn seek(&self, _pos: io::SeekFrom) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn peek(&self, _buf: &mut [u8]) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for ZeroFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        zero_fill(bufs)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        zero_fill(bufs)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        discard(bufs)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        discard(bufs)
+    }
+    async fn seek(&self, _pos: io::SeekFrom) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        buf.fill(0);
+        Ok(buf.len() as u64)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NullFile, ZeroFile};
+    use std::io::{IoSlice, IoSliceMut};
+    use wasi_common::{file::FileType, WasiFile};
+
+    #[tokio::test]
+    async fn null_file_reads_eof_and_discards_writes() {
+        let f = NullFile::new();
+        assert_eq!(f.get_filetype().await.unwrap(), FileType::CharacterDevice);
+        assert!(!f.isatty());
+
+        let mut buf = [7; 8];
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(n, 0);
+        assert_eq!(buf, [7; 8]);
+
+        let n = f
+            .write_vectored(&[IoSlice::new(b"abc"), IoSlice::new(b"de")])
+            .await
+            .unwrap();
+        assert_eq!(n, 5);
+    }
+
+    #[tokio::test]
+    async fn zero_file_fills_every_buffer() {
+        let f = ZeroFile::new();
+        assert_eq!(f.get_filetype().await.unwrap(), FileType::CharacterDevice);
+        assert!(!f.isatty());
+
+        let (mut a, mut b) = ([7; 3], [7; 5]);
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)])
+            .await
+            .unwrap();
+        assert_eq!(n, 8);
+        assert_eq!((a, b), ([0; 3], [0; 5]));
+
+        let n = f.write_vectored(&[IoSlice::new(b"abc")]).await.unwrap();
+        assert_eq!(n, 3);
+    }
+}
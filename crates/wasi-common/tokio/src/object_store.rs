@@ -0,0 +1,323 @@
+use crate::file::seek_target;
+use crate::iovec::iovec_len;
+use bytes::Bytes;
+use std::any::Any;
+use std::io;
+use std::sync::Mutex;
+use wasi_common::{
+    file::{FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+/// Where an [`ObjectStoreFile`]'s object lives: a store such as S3, which
+/// reads ranges of an object but only takes it back whole.
+///
+/// Errors are passed to the guest as they are, so a backend should map its
+/// transport's failures onto errnos, conventionally `EIO`. Implementations
+/// need `#[wiggle::async_trait]`, as the trait's definition has.
+#[wiggle::async_trait]
+pub trait ObjectBackend: Send + Sync {
+    /// Read from `offset` into `buf`, returning how many bytes were read.
+    /// Fewer than `buf.len()`, including none, means the object ends there.
+    /// An object which doesn't exist yet reads as empty.
+    async fn get_range(&self, buf: &mut [u8], offset: u64) -> Result<usize, Error>;
+
+    /// The object's size, or zero if it doesn't exist yet.
+    async fn size(&self) -> Result<u64, Error>;
+
+    /// Replace the whole object with `data`.
+    async fn put(&self, data: Bytes) -> Result<(), Error>;
+}
+
+/// A `WasiFile` backed by one object in an [`ObjectBackend`], for a host
+/// keeping a guest's output in an object store rather than on local disk.
+///
+/// Until the guest first changes the file, reads are served by ranged gets
+/// from the backend. The first write, or truncation, stages a copy of the
+/// whole object in memory, fetching it unless it's being truncated to
+/// nothing, and from then on reads and writes go to that copy. `datasync`
+/// and `sync` upload the staged copy with one `put`, if it has changed since
+/// the last one. The object is only ever replaced whole, so other readers
+/// see either the old contents or everything the guest had written by the
+/// time it synced.
+///
+/// A copy with changes not yet uploaded when the file is dropped is uploaded
+/// on a best-effort basis, which only succeeds if the backend's `put`
+/// completes without yielding. Call `sync` before dropping to observe
+/// errors.
+pub struct ObjectStoreFile<B: ObjectBackend> {
+    backend: B,
+    staged: tokio::sync::Mutex<Option<Staged>>,
+    position: Mutex<u64>,
+}
+
+// The object's contents as the guest has changed them.
+struct Staged {
+    data: Vec<u8>,
+    // Whether `data` has changed since it was last uploaded.
+    dirty: bool,
+}
+
+impl<B: ObjectBackend> ObjectStoreFile<B> {
+    pub fn new(backend: B) -> Self {
+        ObjectStoreFile {
+            backend,
+            staged: tokio::sync::Mutex::new(None),
+            position: Mutex::new(0),
+        }
+    }
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    // The staged copy, fetched from the backend if there isn't one yet.
+    async fn stage<'s>(&self, staged: &'s mut Option<Staged>) -> Result<&'s mut Staged, Error> {
+        if staged.is_none() {
+            let size = usize::try_from(self.backend.size().await?)
+                .map_err(|_| Error::overflow().context("object is too large to stage"))?;
+            let mut data = vec![0; size];
+            let mut filled = 0;
+            while filled < size {
+                let n = self
+                    .backend
+                    .get_range(&mut data[filled..], filled as u64)
+                    .await?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            data.truncate(filled);
+            *staged = Some(Staged { data, dirty: false });
+        }
+        Ok(staged.as_mut().unwrap())
+    }
+
+    async fn upload(&self, staged: &mut Option<Staged>) -> Result<(), Error> {
+        if let Some(staged) = staged.as_mut().filter(|s| s.dirty) {
+            self.backend
+                .put(Bytes::copy_from_slice(&staged.data))
+                .await?;
+            staged.dirty = false;
+        }
+        Ok(())
+    }
+
+    async fn read_at(&self, bufs: &mut [io::IoSliceMut<'_>], offset: u64) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        let staged = self.staged.lock().await.as_ref().map(|staged| {
+            let start = usize::try_from(offset)
+                .unwrap_or(usize::MAX)
+                .min(staged.data.len());
+            let end = start + len.min(staged.data.len() - start);
+            staged.data[start..end].to_vec()
+        });
+        let buf = match staged {
+            Some(buf) => buf,
+            None => {
+                let mut buf = vec![0; len];
+                let n = self.backend.get_range(&mut buf, offset).await?;
+                buf.truncate(n.min(len));
+                buf
+            }
+        };
+        let mut rest = &buf[..];
+        for b in bufs {
+            let n = b.len().min(rest.len());
+            b[..n].copy_from_slice(&rest[..n]);
+            rest = &rest[n..];
+        }
+        Ok(buf.len() as u64)
+    }
+
+    async fn write_at(&self, bufs: &[io::IoSlice<'_>], offset: u64) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        let start = usize::try_from(offset)
+            .ok()
+            .filter(|start| start.checked_add(len).is_some())
+            .ok_or_else(|| Error::overflow().context("write extends past usize::MAX"))?;
+        let mut staged = self.staged.lock().await;
+        let staged = self.stage(&mut staged).await?;
+        if staged.data.len() < start + len {
+            staged.data.resize(start + len, 0);
+        }
+        let mut at = start;
+        for b in bufs {
+            staged.data[at..at + b.len()].copy_from_slice(b);
+            at += b.len();
+        }
+        staged.dirty = true;
+        Ok(len as u64)
+    }
+}
+
+#[wiggle::async_trait]
+impl<B: ObjectBackend + 'static> WasiFile for ObjectStoreFile<B> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        let mut staged = self.staged.lock().await;
+        self.upload(&mut staged).await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        let mut staged = self.staged.lock().await;
+        self.upload(&mut staged).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        let size = match &*self.staged.lock().await {
+            Some(staged) => staged.data.len() as u64,
+            None => self.backend.size().await?,
+        };
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::RegularFile,
+            nlink: 1,
+            size,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        let size = usize::try_from(size)
+            .map_err(|_| Error::overflow().context("size is too large to stage"))?;
+        let mut staged = self.staged.lock().await;
+        // Truncating to nothing doesn't need the old contents.
+        if size == 0 && staged.is_none() {
+            *staged = Some(Staged {
+                data: Vec::new(),
+                dirty: false,
+            });
+        }
+        let staged = self.stage(&mut staged).await?;
+        staged.data.resize(size, 0);
+        staged.dirty = true;
+        Ok(())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.read_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.read_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.write_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.write_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let current = *self.position.lock().unwrap();
+        let new = seek_target(pos, current, || async {
+            Ok(self.get_filestat().await?.size)
+        })
+        .await?;
+        *self.position.lock().unwrap() = new;
+        Ok(new)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<B: ObjectBackend> Drop for ObjectStoreFile<B> {
+    fn drop(&mut self) {
+        let mut staged = self.staged.get_mut().take();
+        if staged.as_ref().map_or(false, |s| s.dirty) {
+            let _ = wiggle::run_in_dummy_executor(self.upload(&mut staged));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ObjectBackend, ObjectStoreFile};
+    use bytes::Bytes;
+    use std::io::{IoSlice, IoSliceMut, SeekFrom};
+    use std::sync::Mutex;
+    use wasi_common::{Error, WasiFile};
+
+    // A backend keeping the object in memory, as a stand-in for a store.
+    #[derive(Default)]
+    struct MemoryBackend {
+        object: Mutex<Bytes>,
+        puts: Mutex<usize>,
+    }
+
+    #[wiggle::async_trait]
+    impl ObjectBackend for MemoryBackend {
+        async fn get_range(&self, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+            let object = self.object.lock().unwrap();
+            let start = (offset as usize).min(object.len());
+            let n = buf.len().min(object.len() - start);
+            buf[..n].copy_from_slice(&object[start..start + n]);
+            Ok(n)
+        }
+        async fn size(&self) -> Result<u64, Error> {
+            Ok(self.object.lock().unwrap().len() as u64)
+        }
+        async fn put(&self, data: Bytes) -> Result<(), Error> {
+            *self.object.lock().unwrap() = data;
+            *self.puts.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trips_an_object() {
+        let f = ObjectStoreFile::new(MemoryBackend::default());
+        f.write_vectored(&[IoSlice::new(b"hello "), IoSlice::new(b"world")])
+            .await
+            .unwrap();
+        f.write_vectored_at(&[IoSlice::new(b"W")], 6).await.unwrap();
+        // Nothing is uploaded until the file is synced.
+        assert!(f.backend().object.lock().unwrap().is_empty());
+        f.sync().await.unwrap();
+        f.sync().await.unwrap();
+        assert_eq!(&f.backend().object.lock().unwrap()[..], b"hello World");
+        assert_eq!(*f.backend().puts.lock().unwrap(), 1);
+
+        // A fresh file over the same object reads it back in ranges.
+        let backend = MemoryBackend::default();
+        *backend.object.lock().unwrap() = Bytes::from_static(b"hello World");
+        let f = ObjectStoreFile::new(backend);
+        assert_eq!(f.seek(SeekFrom::End(-5)).await.unwrap(), 6);
+        let (mut a, mut b) = ([0; 3], [0; 8]);
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)])
+            .await
+            .unwrap();
+        assert_eq!(n, 5);
+        assert_eq!((&a, &b[..2]), (b"Wor", &b"ld"[..]));
+
+        // Changing it stages the whole object, and replaces it on sync.
+        f.write_vectored_at(&[IoSlice::new(b"J")], 0).await.unwrap();
+        f.datasync().await.unwrap();
+        assert_eq!(&f.backend().object.lock().unwrap()[..], b"Jello World");
+        f.set_filestat_size(0).await.unwrap();
+        f.sync().await.unwrap();
+        assert!(f.backend().object.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::iovec::gather;
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// A `WasiFile` wrapper which makes each write atomic with respect to the
+/// others, so a guest writing to one descriptor from several threads never
+/// sees its output torn mid-buffer.
+///
+/// A write holds the file until the inner file has taken all of it, looping
+/// over short writes, and writes take turns in the order they were
+/// submitted: tokio's mutex queues waiters first in, first out, so a busy
+/// writer can't starve the others. If the inner file fails partway, the
+/// write returns how much got through, or the error if nothing did.
+/// `write_vectored` and `write_vectored_at` are serialized this way;
+/// everything else is forwarded as is.
+pub struct Ordered<F: WasiFile> {
+    inner: F,
+    turn: Mutex<()>,
+}
+
+impl<F: WasiFile> Ordered<F> {
+    pub fn new(inner: F) -> Self {
+        Ordered {
+            inner,
+            turn: Mutex::new(()),
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for Ordered<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.inner.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.inner.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.inner.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let data = gather(bufs)?;
+        let _turn = self.turn.lock().await;
+        let mut written = 0;
+        while written < data.len() {
+            match self
+                .inner
+                .write_vectored(&[io::IoSlice::new(&data[written..])])
+                .await
+            {
+                Ok(0) => return Err(Error::io().context("inner file accepted no bytes")),
+                Ok(n) => written += n as usize,
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+        Ok(written as u64)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let data = gather(bufs)?;
+        let _turn = self.turn.lock().await;
+        let mut written = 0;
+        while written < data.len() {
+            let at = offset
+                .checked_add(written as u64)
+                .ok_or_else(|| Error::overflow().context("write extends past u64::MAX"))?;
+            match self
+                .inner
+                .write_vectored_at(&[io::IoSlice::new(&data[written..])], at)
+                .await
+            {
+                Ok(0) => return Err(Error::io().context("inner file accepted no bytes")),
+                Ok(n) => written += n as usize,
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+        Ok(written as u64)
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ordered;
+    use std::any::Any;
+    use std::io::{self, IoSlice};
+    use std::sync::{Arc, Mutex};
+    use wasi_common::{
+        file::{FileType, WasiFile},
+        Error,
+    };
+
+    // Takes at most three bytes per write, yielding in between, so that
+    // unserialized writers would interleave.
+    #[derive(Default)]
+    struct Trickle(Mutex<Vec<u8>>);
+
+    #[wiggle::async_trait]
+    impl WasiFile for Trickle {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        async fn get_filetype(&self) -> Result<FileType, Error> {
+            Ok(FileType::Pipe)
+        }
+        async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+            tokio::task::yield_now().await;
+            let buf = bufs
+                .iter()
+                .find(|b| !b.is_empty())
+                .map_or(&[][..], |b| &b[..]);
+            let n = buf.len().min(3);
+            self.0.lock().unwrap().extend_from_slice(&buf[..n]);
+            Ok(n as u64)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_writes_are_not_torn() {
+        let f = Arc::new(Ordered::new(Trickle::default()));
+        let writers = (b'a'..=b'h')
+            .map(|c| {
+                let f = f.clone();
+                tokio::spawn(async move {
+                    for _ in 0..10 {
+                        let n = f.write_vectored(&[IoSlice::new(&[c; 16])]).await.unwrap();
+                        assert_eq!(n, 16);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for w in writers {
+            w.await.unwrap();
+        }
+
+        let out = f.get_ref().0.lock().unwrap();
+        assert_eq!(out.len(), 8 * 10 * 16);
+        for chunk in out.chunks(16) {
+            assert!(
+                chunk.iter().all(|b| *b == chunk[0]),
+                "torn write: {:?}",
+                chunk
+            );
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use crate::file::seek_target;
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use wasi_common::{
+    file::{FileType, Filestat, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error,
+};
+
+const DEFAULT_PAGE_SIZE: usize = 4096;
+const DEFAULT_CACHE_PAGES: usize = 1024;
+
+/// Where a [`PagedFile`]'s pages come from and go back to, such as a
+/// database's page store.
+///
+/// Errors are passed to the guest as they are, so a source should map its
+/// own failures onto errnos, conventionally `EIO`. Implementations need
+/// `#[wiggle::async_trait]`, as the trait's definition has.
+#[wiggle::async_trait]
+pub trait PageSource: Send + Sync {
+    /// Fill `buf`, which is one page long and zeroed, with page `index`. A
+    /// source backing a sparse file can leave the pages it has no data for
+    /// as they are.
+    async fn fetch(&self, index: u64, buf: &mut [u8]) -> Result<(), Error>;
+
+    /// Store page `index`, which writes have changed since it was fetched.
+    async fn flush(&self, index: u64, data: &[u8]) -> Result<(), Error>;
+}
+
+/// A `WasiFile` of a fixed size, which may be far larger than the host
+/// could hold, whose contents are fetched a page at a time from a
+/// [`PageSource`] as the guest touches them.
+///
+/// Fetched pages are cached, up to 1024 of them by default, and the least
+/// recently used one is evicted to make room for another. Writes change the
+/// cached page, fetching it first unless the write covers all of it, and
+/// mark it dirty; dirty pages are handed to [`PageSource::flush`] when
+/// they're evicted and on `datasync` and `sync`. The size can't change, so
+/// writes past the end fail with `EFBIG`, as does `set_filestat_size`.
+///
+/// Dirty pages still cached when the file is dropped are flushed on a
+/// best-effort basis, which only succeeds if the source's `flush` completes
+/// without yielding. Call `datasync` before dropping to observe errors.
+pub struct PagedFile<S: PageSource> {
+    source: S,
+    size: u64,
+    page_size: usize,
+    cache_pages: usize,
+    cache: tokio::sync::Mutex<Cache>,
+    position: Mutex<u64>,
+}
+
+#[derive(Default)]
+struct Cache {
+    pages: HashMap<u64, Page>,
+    // Bumped on every use of a page, to find the least recently used one.
+    clock: u64,
+}
+
+struct Page {
+    data: Box<[u8]>,
+    dirty: bool,
+    last_used: u64,
+}
+
+impl<S: PageSource> PagedFile<S> {
+    /// A file of `size` bytes, backed by `source`.
+    pub fn new(source: S, size: u64) -> Self {
+        PagedFile {
+            source,
+            size,
+            page_size: DEFAULT_PAGE_SIZE,
+            cache_pages: DEFAULT_CACHE_PAGES,
+            cache: tokio::sync::Mutex::new(Cache::default()),
+            position: Mutex::new(0),
+        }
+    }
+    /// Use pages of `page_size` bytes rather than the default of 4096.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        assert!(page_size > 0, "page size must be nonzero");
+        self.page_size = page_size;
+        self
+    }
+    /// Cache up to `pages` pages rather than the default of 1024.
+    pub fn with_cache_pages(mut self, pages: usize) -> Self {
+        assert!(pages > 0, "the cache must hold at least one page");
+        self.cache_pages = pages;
+        self
+    }
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    // The cached page `index`, fetched if it isn't cached, unless `whole`
+    // says the caller is about to overwrite all of it.
+    async fn page<'c>(
+        &self,
+        cache: &'c mut Cache,
+        index: u64,
+        whole: bool,
+    ) -> Result<&'c mut Page, Error> {
+        cache.clock += 1;
+        if !cache.pages.contains_key(&index) {
+            if cache.pages.len() >= self.cache_pages {
+                self.evict(cache).await?;
+            }
+            let mut data = vec![0; self.page_size].into_boxed_slice();
+            if !whole {
+                self.source.fetch(index, &mut data).await?;
+            }
+            cache.pages.insert(
+                index,
+                Page {
+                    data,
+                    dirty: false,
+                    last_used: 0,
+                },
+            );
+        }
+        let page = cache.pages.get_mut(&index).unwrap();
+        page.last_used = cache.clock;
+        Ok(page)
+    }
+
+    async fn evict(&self, cache: &mut Cache) -> Result<(), Error> {
+        let (&index, page) = cache
+            .pages
+            .iter()
+            .min_by_key(|(_, page)| page.last_used)
+            .expect("the cache is full, so not empty");
+        if page.dirty {
+            self.source.flush(index, &page.data).await?;
+        }
+        cache.pages.remove(&index);
+        Ok(())
+    }
+
+    async fn flush_locked(&self, cache: &mut Cache) -> Result<(), Error> {
+        let mut dirty = cache
+            .pages
+            .iter_mut()
+            .filter(|(_, page)| page.dirty)
+            .collect::<Vec<_>>();
+        dirty.sort_by_key(|(index, _)| **index);
+        for (index, page) in dirty {
+            self.source.flush(*index, &page.data).await?;
+            page.dirty = false;
+        }
+        Ok(())
+    }
+
+    async fn read_at(&self, bufs: &mut [io::IoSliceMut<'_>], offset: u64) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        let len = len.min(usize::try_from(self.size.saturating_sub(offset)).unwrap_or(usize::MAX));
+        let mut buf = vec![0; len];
+        let mut cache = self.cache.lock().await;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let within = (pos % self.page_size as u64) as usize;
+            let n = (self.page_size - within).min(len - done);
+            let page = self
+                .page(&mut cache, pos / self.page_size as u64, false)
+                .await?;
+            buf[done..done + n].copy_from_slice(&page.data[within..within + n]);
+            done += n;
+        }
+        drop(cache);
+
+        let mut rest = &buf[..];
+        for b in bufs {
+            let n = b.len().min(rest.len());
+            b[..n].copy_from_slice(&rest[..n]);
+            rest = &rest[n..];
+        }
+        Ok(len as u64)
+    }
+
+    async fn write_at(&self, bufs: &[io::IoSlice<'_>], offset: u64) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if offset
+            .checked_add(len as u64)
+            .map_or(true, |end| end > self.size)
+        {
+            return Err(Error::from(Errno::Fbig).context("write past the end of a paged file"));
+        }
+        let mut buf = Vec::with_capacity(len);
+        for b in bufs {
+            buf.extend_from_slice(b);
+        }
+        let mut cache = self.cache.lock().await;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let within = (pos % self.page_size as u64) as usize;
+            let n = (self.page_size - within).min(len - done);
+            let whole = n == self.page_size;
+            let page = self
+                .page(&mut cache, pos / self.page_size as u64, whole)
+                .await?;
+            page.data[within..within + n].copy_from_slice(&buf[done..done + n]);
+            page.dirty = true;
+            done += n;
+        }
+        Ok(len as u64)
+    }
+}
+
+#[wiggle::async_trait]
+impl<S: PageSource + 'static> WasiFile for PagedFile<S> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        let mut cache = self.cache.lock().await;
+        self.flush_locked(&mut cache).await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        let mut cache = self.cache.lock().await;
+        self.flush_locked(&mut cache).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::RegularFile,
+            nlink: 1,
+            size: self.size,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::from(Errno::Fbig).context("paged files can't be resized"))
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.read_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.read_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.write_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.write_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let current = *self.position.lock().unwrap();
+        let new = seek_target(pos, current, || async { Ok(self.size) }).await?;
+        *self.position.lock().unwrap() = new;
+        Ok(new)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<S: PageSource> Drop for PagedFile<S> {
+    fn drop(&mut self) {
+        let mut cache = std::mem::take(self.cache.get_mut());
+        if cache.pages.values().any(|page| page.dirty) {
+            let _ = wiggle::run_in_dummy_executor(self.flush_locked(&mut cache));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PageSource, PagedFile};
+    use std::collections::HashMap;
+    use std::io::{IoSlice, IoSliceMut};
+    use std::sync::Mutex;
+    use wasi_common::{snapshots::preview_1::types::Errno, Error, WasiFile};
+
+    // A source keeping the pages which have any data in memory, and
+    // recording each call made to it.
+    #[derive(Default)]
+    struct MemoryPageSource {
+        pages: Mutex<HashMap<u64, Vec<u8>>>,
+        calls: Mutex<Vec<(&'static str, u64)>>,
+    }
+
+    #[wiggle::async_trait]
+    impl PageSource for MemoryPageSource {
+        async fn fetch(&self, index: u64, buf: &mut [u8]) -> Result<(), Error> {
+            self.calls.lock().unwrap().push(("fetch", index));
+            if let Some(page) = self.pages.lock().unwrap().get(&index) {
+                buf.copy_from_slice(page);
+            }
+            Ok(())
+        }
+        async fn flush(&self, index: u64, data: &[u8]) -> Result<(), Error> {
+            self.calls.lock().unwrap().push(("flush", index));
+            self.pages.lock().unwrap().insert(index, data.to_vec());
+            Ok(())
+        }
+    }
+
+    const TIB: u64 = 1 << 40;
+
+    #[tokio::test]
+    async fn reads_across_pages_of_a_sparse_file() {
+        let source = MemoryPageSource::default();
+        let far = TIB / 8;
+        source
+            .pages
+            .lock()
+            .unwrap()
+            .extend([(far, b"abcdefgh".to_vec()), (far + 1, b"ijklmnop".to_vec())]);
+        let f = PagedFile::new(source, 4 * TIB).with_page_size(8);
+
+        let (mut a, mut b) = ([0; 3], [0; 10]);
+        let n = f
+            .read_vectored_at(
+                &mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)],
+                TIB - 2,
+            )
+            .await
+            .unwrap();
+        assert_eq!(n, 13);
+        assert_eq!((&a, &b), (b"\0\0a", b"bcdefghijk"));
+        // Each page was fetched once, and is cached from then on.
+        let mut c = [0; 4];
+        f.read_vectored_at(&mut [IoSliceMut::new(&mut c)], TIB + 4)
+            .await
+            .unwrap();
+        assert_eq!(&c, b"efgh");
+        assert_eq!(
+            *f.source().calls.lock().unwrap(),
+            [("fetch", far - 1), ("fetch", far), ("fetch", far + 1)]
+        );
+
+        // Reads stop at the end.
+        let n = f
+            .read_vectored_at(&mut [IoSliceMut::new(&mut c)], 4 * TIB - 1)
+            .await
+            .unwrap();
+        assert_eq!(n, 1);
+    }
+
+    #[tokio::test]
+    async fn dirty_pages_are_flushed() {
+        let f = PagedFile::new(MemoryPageSource::default(), 64)
+            .with_page_size(8)
+            .with_cache_pages(2);
+        // A whole page isn't fetched before being overwritten.
+        f.write_vectored_at(&[IoSlice::new(b"01234567")], 8)
+            .await
+            .unwrap();
+        f.write_vectored_at(&[IoSlice::new(b"xy")], 20)
+            .await
+            .unwrap();
+        // A third page evicts the least recently used one, flushing it.
+        let mut buf = [0; 1];
+        f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 40)
+            .await
+            .unwrap();
+        f.datasync().await.unwrap();
+        assert_eq!(
+            *f.source().calls.lock().unwrap(),
+            [("fetch", 2), ("flush", 1), ("fetch", 5), ("flush", 2),]
+        );
+        assert_eq!(
+            f.source().pages.lock().unwrap()[&2],
+            b"\0\0\0\0xy\0\0".to_vec()
+        );
+
+        let err = f
+            .write_vectored_at(&[IoSlice::new(b"xy")], 63)
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Fbig);
+    }
+}
@@ -0,0 +1,239 @@
+use std::any::Any;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags, WasiFile},
+    Error, SystemTimeSpec,
+};
+
+/// A handle a host can use to hold up a guest's I/O, for flow control or to
+/// share I/O fairly between many guests.
+///
+/// While [`PauseSwitch::pause`] is in effect, every [`Pausable`] file the
+/// switch was given to waits before starting a read or write, until
+/// [`PauseSwitch::resume`] is called. Clones of a switch share its state, so
+/// one switch can cover all of a guest's files.
+#[derive(Clone, Default)]
+pub struct PauseSwitch(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    paused: AtomicBool,
+    notify: Notify,
+}
+
+impl PauseSwitch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pause(&self) {
+        self.0.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.0.paused.store(false, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.paused.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the switch isn't paused.
+    pub async fn resumed(&self) {
+        loop {
+            // Register for the wakeup before checking, so a `resume` in
+            // between the two isn't missed.
+            let notified = self.0.notify.notified();
+            if !self.is_paused() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// A `WasiFile` wrapper whose reads and writes wait while its
+/// [`PauseSwitch`] is paused, without the descriptor being closed or the
+/// guest seeing an error.
+///
+/// `read_vectored`, `read_vectored_at`, `write_vectored`,
+/// `write_vectored_at`, `sock_recv`, and `sock_send` wait; an operation which
+/// has already started runs to completion. Everything else, including
+/// readiness, is forwarded as is.
+pub struct Pausable<F: WasiFile> {
+    inner: F,
+    switch: PauseSwitch,
+}
+
+impl<F: WasiFile> Pausable<F> {
+    pub fn new(inner: F, switch: PauseSwitch) -> Self {
+        Pausable { inner, switch }
+    }
+    pub fn switch(&self) -> &PauseSwitch {
+        &self.switch
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for Pausable<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.inner.sock_accept(fdflags).await
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        self.switch.resumed().await;
+        self.inner.sock_recv(ri_data, ri_flags).await
+    }
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[io::IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        self.switch.resumed().await;
+        self.inner.sock_send(si_data, si_flags).await
+    }
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.inner.sock_shutdown(how).await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.inner.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.inner.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.switch.resumed().await;
+        self.inner.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.switch.resumed().await;
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.switch.resumed().await;
+        self.inner.write_vectored(bufs).await
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.switch.resumed().await;
+        self.inner.write_vectored_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Pausable, PauseSwitch};
+    use crate::BytesFile;
+    use std::io::IoSliceMut;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use wasi_common::WasiFile;
+
+    #[tokio::test]
+    async fn paused_read_waits_for_resume() {
+        let switch = PauseSwitch::new();
+        let f = Arc::new(Pausable::new(
+            BytesFile::new(Arc::from(&b"hello"[..])),
+            switch.clone(),
+        ));
+
+        switch.pause();
+        let mut reader = tokio::spawn({
+            let f = f.clone();
+            async move {
+                let mut buf = [0; 8];
+                let n = f
+                    .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                    .await
+                    .unwrap();
+                buf[..n as usize].to_vec()
+            }
+        });
+        let waited = tokio::time::timeout(Duration::from_millis(50), &mut reader).await;
+        assert!(waited.is_err(), "read went ahead while paused");
+
+        switch.resume();
+        let read = tokio::time::timeout(Duration::from_secs(5), reader)
+            .await
+            .expect("read resumes")
+            .unwrap();
+        assert_eq!(read, b"hello");
+    }
+}
@@ -0,0 +1,200 @@
+use crate::file::seek_target;
+use bytes::Bytes;
+use std::any::Any;
+use std::io;
+use std::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+/// A read-only `WasiFile` presenting `header` followed by the whole of the
+/// inner file, such as generated HTTP response headers ahead of a static
+/// body, without copying the body or writing the header into it.
+///
+/// Offsets, seeks, and the size from `get_filestat` cover the header and
+/// body together. A read which spans the two is served from both, using
+/// the inner file's positional reads, so the inner file's own offset is left
+/// alone; if the body's part of such a read fails, the read returns the
+/// header's part, and the error comes from the next one. Writes fail with
+/// `EBADF`.
+pub struct PrefixedFile<F: WasiFile> {
+    header: Bytes,
+    inner: F,
+    position: Mutex<u64>,
+}
+
+impl<F: WasiFile> PrefixedFile<F> {
+    pub fn new(header: impl Into<Bytes>, inner: F) -> Self {
+        PrefixedFile {
+            header: header.into(),
+            inner,
+            position: Mutex::new(0),
+        }
+    }
+    pub fn header(&self) -> &Bytes {
+        &self.header
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    async fn read_at(&self, bufs: &mut [io::IoSliceMut<'_>], offset: u64) -> Result<u64, Error> {
+        let header_len = self.header.len() as u64;
+        if offset >= header_len {
+            return self.inner.read_vectored_at(bufs, offset - header_len).await;
+        }
+
+        // Copy out what's left of the header, then read the start of the
+        // body into whatever room remains.
+        let mut header = &self.header[offset as usize..];
+        let mut copied = 0;
+        let mut rest = Vec::with_capacity(bufs.len());
+        for buf in bufs.iter_mut() {
+            let n = buf.len().min(header.len());
+            buf[..n].copy_from_slice(&header[..n]);
+            header = &header[n..];
+            copied += n as u64;
+            if n < buf.len() {
+                rest.push(io::IoSliceMut::new(&mut buf[n..]));
+            }
+        }
+        if rest.is_empty() {
+            return Ok(copied);
+        }
+        match self.inner.read_vectored_at(&mut rest, 0).await {
+            Ok(n) => Ok(copied + n),
+            Err(_) if copied > 0 => Ok(copied),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_only() -> Error {
+        Error::badf().context("prefixed files are read-only")
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for PrefixedFile<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        let stat = self.inner.get_filestat().await?;
+        let size = stat
+            .size
+            .checked_add(self.header.len() as u64)
+            .ok_or_else(|| Error::overflow().context("header and body exceed u64::MAX"))?;
+        Ok(Filestat { size, ..stat })
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Self::read_only())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.read_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.read_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, _bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        Err(Self::read_only())
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Self::read_only())
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let current = *self.position.lock().unwrap();
+        let new = seek_target(pos, current, || async {
+            Ok(self.get_filestat().await?.size)
+        })
+        .await?;
+        *self.position.lock().unwrap() = new;
+        Ok(new)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Err(Self::read_only())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PrefixedFile;
+    use crate::BytesFile;
+    use std::io::{IoSlice, IoSliceMut, SeekFrom};
+    use std::sync::Arc;
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    fn response() -> PrefixedFile<BytesFile> {
+        let body = BytesFile::new(Arc::from(&b"<html></html>"[..]));
+        PrefixedFile::new(&b"HTTP/1.1 200 OK\r\n\r\n"[..], body)
+    }
+
+    async fn read(f: &PrefixedFile<BytesFile>, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        buf.truncate(n as usize);
+        buf
+    }
+
+    #[tokio::test]
+    async fn reads_span_header_and_body() {
+        let f = response();
+        assert_eq!(f.get_filestat().await.unwrap().size, 19 + 13);
+        assert_eq!(read(&f, 8).await, b"HTTP/1.1");
+        assert_eq!(read(&f, 4).await, b" 200");
+
+        // A read across the boundary takes from both, over several buffers.
+        let (mut a, mut b) = ([0; 5], [0; 6]);
+        let n = f
+            .read_vectored_at(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)], 15)
+            .await
+            .unwrap();
+        assert_eq!(n, 11);
+        assert_eq!((&a, &b), (b"\r\n\r\n<", b"html><"));
+
+        let err = f
+            .write_vectored(&[IoSlice::new(b"x")])
+            .await
+            .expect_err("read-only");
+        assert_eq!(err.downcast().unwrap(), Errno::Badf);
+    }
+
+    #[tokio::test]
+    async fn seeks_into_the_body() {
+        let f = response();
+        assert_eq!(f.seek(SeekFrom::Start(25)).await.unwrap(), 25);
+        assert_eq!(read(&f, 16).await, b"</html>");
+        assert_eq!(read(&f, 16).await, b"");
+
+        assert_eq!(f.seek(SeekFrom::End(-13)).await.unwrap(), 19);
+        assert_eq!(read(&f, 6).await, b"<html>");
+        assert_eq!(f.seek(SeekFrom::Current(-8)).await.unwrap(), 17);
+        assert_eq!(read(&f, 3).await, b"\r\n<");
+    }
+}
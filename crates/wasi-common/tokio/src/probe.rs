@@ -0,0 +1,63 @@
+use crate::net::TcpStream;
+use wasi_common::Error;
+
+impl TcpStream {
+    /// Check, without blocking or consuming any data, whether the connection
+    /// still looks alive, for a pool deciding whether a socket is worth
+    /// reusing.
+    ///
+    /// This peeks at one byte with `MSG_PEEK | MSG_DONTWAIT`. Pending data or
+    /// nothing to read yet count as alive; end-of-stream, or a reset or
+    /// timed-out connection, count as dead. Other errors are returned.
+    ///
+    /// This is only a staleness check, not a guarantee: a peer which vanished
+    /// without a FIN or RST still looks alive, and a live connection can die
+    /// right after the probe. Callers must still handle errors on the next
+    /// read or write. Not supported on Windows.
+    pub fn probe(&self) -> Result<bool, Error> {
+        sys::probe(&self.inner)
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::Error;
+
+    pub(super) fn probe(fd: impl AsFd) -> Result<bool, Error> {
+        let mut byte = 0u8;
+        loop {
+            let ret = unsafe {
+                libc::recv(
+                    fd.as_fd().as_raw_fd(),
+                    (&mut byte as *mut u8).cast(),
+                    1,
+                    libc::MSG_PEEK | libc::MSG_DONTWAIT,
+                )
+            };
+            if ret >= 0 {
+                // Zero is end-of-stream: the peer has shut down its side.
+                return Ok(ret > 0);
+            }
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::ECONNRESET | libc::ETIMEDOUT | libc::EPIPE) => return Ok(false),
+                _ if err.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+                _ => return Err(err.into()),
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use io_lifetimes::AsSocket;
+    use wasi_common::{Error, ErrorExt};
+
+    pub(super) fn probe(_socket: impl AsSocket) -> Result<bool, Error> {
+        Err(Error::not_supported().context("connection probing is not supported on Windows"))
+    }
+}
@@ -0,0 +1,193 @@
+use crate::file::File;
+use std::any::Any;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+// Distinguishes temporary files made by one process at the same moment.
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A `WasiFile` which only appears at its path once it's complete, so that
+/// readers never see it half-written and a crash never leaves a torn file.
+///
+/// Writes go to a temporary file next to the target, in the same directory.
+/// When the `PublishOnClose` is dropped, if it has been synced since its last
+/// write and nothing has failed, the temporary file is renamed over the
+/// target; otherwise it's removed, leaving any previous target in place.
+/// [`PublishOnClose::abort`] discards it explicitly. Both the rename and the
+/// cleanup go through the given `Dir`, so they stay inside its sandbox.
+///
+/// Errors from the rename or cleanup in `drop` can't be reported, and leave
+/// the temporary file behind.
+pub struct PublishOnClose {
+    // Only `None` while being dropped, so it's closed before the rename.
+    file: Option<File>,
+    dir: cap_std::fs::Dir,
+    temp: PathBuf,
+    target: PathBuf,
+    // Whether the contents have been synced since they were last changed.
+    synced: AtomicBool,
+    failed: AtomicBool,
+}
+
+impl PublishOnClose {
+    /// Start writing a new file which will be published at `target`, a path
+    /// relative to `dir`.
+    pub fn create(dir: cap_std::fs::Dir, target: impl AsRef<Path>) -> Result<Self, Error> {
+        let target = target.as_ref().to_path_buf();
+        let name = target
+            .file_name()
+            .ok_or_else(|| Error::invalid_argument().context("target has no file name"))?;
+        let temp = target.with_file_name(format!(
+            ".{}.{}.{}.tmp",
+            name.to_string_lossy(),
+            std::process::id(),
+            NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = dir.open_with(
+            &temp,
+            cap_std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true),
+        )?;
+        Ok(PublishOnClose {
+            file: Some(File::from_cap_std(file)),
+            dir,
+            temp,
+            target,
+            synced: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+        })
+    }
+
+    fn file(&self) -> &File {
+        self.file.as_ref().unwrap()
+    }
+
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// Discard everything written, leaving the target as it was.
+    pub fn abort(mut self) -> Result<(), Error> {
+        self.failed.store(true, Ordering::SeqCst);
+        drop(self.file.take());
+        self.dir.remove_file(&self.temp)?;
+        Ok(())
+    }
+
+    // Note the outcome of something which changes the contents.
+    fn note_change<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        self.synced.store(false, Ordering::SeqCst);
+        if result.is_err() {
+            self.failed.store(true, Ordering::SeqCst);
+        }
+        result
+    }
+
+    fn note_sync(&self, result: Result<(), Error>) -> Result<(), Error> {
+        match &result {
+            Ok(()) => self.synced.store(true, Ordering::SeqCst),
+            Err(_) => self.failed.store(true, Ordering::SeqCst),
+        }
+        result
+    }
+}
+
+impl Drop for PublishOnClose {
+    fn drop(&mut self) {
+        drop(self.file.take());
+        let publish = self.synced.load(Ordering::SeqCst) && !self.failed.load(Ordering::SeqCst);
+        if publish && self.dir.rename(&self.temp, &self.dir, &self.target).is_ok() {
+            return;
+        }
+        let _ = self.dir.remove_file(&self.temp);
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for PublishOnClose {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.file().get_filetype().await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        let result = self.file().datasync().await;
+        self.note_sync(result)
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        let result = self.file().sync().await;
+        self.note_sync(result)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.file().get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.file.as_mut().unwrap().set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.file().get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        let result = self.file().set_filestat_size(size).await;
+        self.note_change(result)
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.file().advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        let result = self.file().allocate(offset, len).await;
+        self.note_change(result)
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.file().set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.file().read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.file().read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let result = self.file().write_vectored(bufs).await;
+        self.note_change(result)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let result = self.file().write_vectored_at(bufs, offset).await;
+        self.note_change(result)
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.file().seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.file().peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.file().num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.file().readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.file().writable().await
+    }
+}
@@ -0,0 +1,356 @@
+use crate::iovec::{capped, capped_mut};
+use std::any::Any;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags, WasiFile},
+    Error, SystemTimeSpec,
+};
+
+/// A token bucket holding a host-wide bandwidth budget, for any number of
+/// [`RateLimited`] files to share, so that together they move no more than
+/// `bytes_per_sec` on average, in bursts of at most `burst` bytes.
+///
+/// Files waiting for the bucket to refill are served in the order they
+/// started waiting, so a busy file can't starve a quiet one. Clones share the
+/// same bucket.
+#[derive(Clone)]
+pub struct SharedRateLimiter(Arc<Limiter>);
+
+struct Limiter {
+    bytes_per_sec: u64,
+    burst: u64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl SharedRateLimiter {
+    /// A limiter whose bucket starts full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` or `burst` is zero, since nothing could
+    /// ever get through.
+    pub fn new(bytes_per_sec: u64, burst: u64) -> Self {
+        assert!(bytes_per_sec > 0, "rate must be positive");
+        assert!(burst > 0, "burst must be positive");
+        SharedRateLimiter(Arc::new(Limiter {
+            bytes_per_sec,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst as f64,
+                refilled: Instant::now(),
+            }),
+        }))
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.0.bytes_per_sec
+    }
+
+    pub fn burst(&self) -> u64 {
+        self.0.burst
+    }
+
+    // Wait for, and take, up to `want` bytes' worth of tokens, returning how
+    // many were taken.
+    async fn acquire(&self, want: usize) -> usize {
+        let want = (want as u64).min(self.0.burst);
+        // Waiters hold the lock while they sleep, and tokio's mutex is
+        // fair, so they're served in turn.
+        let mut bucket = self.0.bucket.lock().await;
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens =
+                (bucket.tokens + elapsed * self.0.bytes_per_sec as f64).min(self.0.burst as f64);
+            bucket.refilled = now;
+            if bucket.tokens >= want as f64 {
+                bucket.tokens -= want as f64;
+                return want as usize;
+            }
+            let short = want as f64 - bucket.tokens;
+            tokio::time::sleep(Duration::from_secs_f64(short / self.0.bytes_per_sec as f64)).await;
+        }
+    }
+
+    // Return tokens taken for bytes which weren't moved after all.
+    async fn refund(&self, unused: usize) {
+        if unused > 0 {
+            let mut bucket = self.0.bucket.lock().await;
+            bucket.tokens = (bucket.tokens + unused as f64).min(self.0.burst as f64);
+        }
+    }
+}
+
+/// A `WasiFile` wrapper whose reads and writes draw on a
+/// [`SharedRateLimiter`], waiting when it has run dry.
+///
+/// Each read or write takes as much of the budget as it asks for, up to the
+/// limiter's burst, and is shortened to what it got, so a guest sees short
+/// reads and writes rather than a long stall. Budget a read didn't use, at
+/// EOF say, is handed back. `read_vectored`, `read_vectored_at`,
+/// `write_vectored`, `write_vectored_at`, `sock_recv`, and `sock_send` are
+/// limited; everything else is forwarded as is.
+pub struct RateLimited<F: WasiFile> {
+    inner: F,
+    limiter: SharedRateLimiter,
+}
+
+impl<F: WasiFile> RateLimited<F> {
+    pub fn new(inner: F, limiter: SharedRateLimiter) -> Self {
+        RateLimited { inner, limiter }
+    }
+    pub fn limiter(&self) -> &SharedRateLimiter {
+        &self.limiter
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+fn total_len(lens: impl Iterator<Item = usize>) -> usize {
+    lens.fold(0, usize::saturating_add)
+}
+
+// Run `$op` on no more than the budget granted for `$len` bytes, handing back
+// what it didn't use. `$moved` extracts the byte count from its result.
+macro_rules! limited {
+    ($self:ident, $len:expr, |$granted:ident| $op:expr, |$ok:ident| $moved:expr) => {{
+        let $granted = $self.limiter.acquire($len).await;
+        let result = $op.await;
+        let used = match &result {
+            Ok($ok) => $moved as usize,
+            Err(_) => 0,
+        };
+        $self.limiter.refund($granted - used.min($granted)).await;
+        result
+    }};
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for RateLimited<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.inner.sock_accept(fdflags).await
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        let len = total_len(ri_data.iter().map(|b| b.len()));
+        if len == 0 {
+            return self.inner.sock_recv(ri_data, ri_flags).await;
+        }
+        limited!(
+            self,
+            len,
+            |granted| self
+                .inner
+                .sock_recv(&mut capped_mut(ri_data, granted), ri_flags),
+            |ok| ok.0
+        )
+    }
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[io::IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        let len = total_len(si_data.iter().map(|b| b.len()));
+        if len == 0 {
+            return self.inner.sock_send(si_data, si_flags).await;
+        }
+        limited!(
+            self,
+            len,
+            |granted| self.inner.sock_send(&capped(si_data, granted), si_flags),
+            |n| *n
+        )
+    }
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.inner.sock_shutdown(how).await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.inner.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.inner.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let len = total_len(bufs.iter().map(|b| b.len()));
+        if len == 0 {
+            return self.inner.read_vectored(bufs).await;
+        }
+        limited!(
+            self,
+            len,
+            |granted| self.inner.read_vectored(&mut capped_mut(bufs, granted)),
+            |n| *n
+        )
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let len = total_len(bufs.iter().map(|b| b.len()));
+        if len == 0 {
+            return self.inner.read_vectored_at(bufs, offset).await;
+        }
+        limited!(
+            self,
+            len,
+            |granted| self
+                .inner
+                .read_vectored_at(&mut capped_mut(bufs, granted), offset),
+            |n| *n
+        )
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let len = total_len(bufs.iter().map(|b| b.len()));
+        if len == 0 {
+            return self.inner.write_vectored(bufs).await;
+        }
+        limited!(
+            self,
+            len,
+            |granted| self.inner.write_vectored(&capped(bufs, granted)),
+            |n| *n
+        )
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let len = total_len(bufs.iter().map(|b| b.len()));
+        if len == 0 {
+            return self.inner.write_vectored_at(bufs, offset).await;
+        }
+        limited!(
+            self,
+            len,
+            |granted| self.inner.write_vectored_at(&capped(bufs, granted), offset),
+            |n| *n
+        )
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RateLimited, SharedRateLimiter};
+    use crate::BytesFile;
+    use std::io::IoSliceMut;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use wasi_common::WasiFile;
+
+    async fn drain(f: Arc<RateLimited<BytesFile>>) -> usize {
+        let mut total = 0;
+        loop {
+            let mut buf = [0; 512];
+            let n = f
+                .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                .await
+                .unwrap();
+            if n == 0 {
+                return total;
+            }
+            total += n as usize;
+        }
+    }
+
+    #[tokio::test]
+    async fn shared_limit_bounds_combined_throughput() {
+        let limiter = SharedRateLimiter::new(10_000, 1_000);
+        let data: Arc<[u8]> = Arc::from(vec![0; 3_000]);
+        let file = || {
+            Arc::new(RateLimited::new(
+                BytesFile::new(data.clone()),
+                limiter.clone(),
+            ))
+        };
+
+        let start = Instant::now();
+        let a = tokio::spawn(drain(file()));
+        let b = tokio::spawn(drain(file()));
+        let (a, b) = (a.await.unwrap(), b.await.unwrap());
+        let elapsed = start.elapsed();
+        assert_eq!((a, b), (3_000, 3_000));
+        // 6,000 bytes at 10,000 a second, of which the first 1,000 were
+        // already in the bucket, take at least half a second however they're
+        // split between the files.
+        assert!(
+            elapsed >= Duration::from_millis(450),
+            "took only {:?}",
+            elapsed
+        );
+    }
+}
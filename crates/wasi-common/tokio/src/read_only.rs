@@ -0,0 +1,164 @@
+use std::any::Any;
+use std::io;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// A `WasiFile` wrapper which refuses every operation that could modify the
+/// underlying file.
+///
+/// Reads, seeks, and stats are forwarded to the inner file. Writes,
+/// truncation, allocation, and timestamp updates all fail with `EBADF`, the
+/// same error POSIX gives for writing to a descriptor opened read-only. This
+/// holds regardless of the rights the guest was granted for the descriptor,
+/// which makes it suitable for handing out shared, immutable assets.
+pub struct ReadOnly<F: WasiFile> {
+    inner: F,
+}
+
+impl<F: WasiFile> ReadOnly<F> {
+    pub fn new(inner: F) -> Self {
+        ReadOnly { inner }
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for ReadOnly<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        self.inner.sock_recv(ri_data, ri_flags).await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::badf().context("file is read-only"))
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::badf().context("file is read-only"))
+    }
+    async fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        Err(Error::badf().context("file is read-only"))
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.inner.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, _bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        Err(Error::badf().context("file is read-only"))
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::badf().context("file is read-only"))
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReadOnly;
+    use crate::File;
+    use std::io::{IoSlice, IoSliceMut, Write};
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_writes_allows_reads() {
+        let mut file = tempfile::tempfile().expect("create temporary file");
+        file.write_all(b"immutable").expect("write contents");
+        let file = ReadOnly::new(File::from_cap_std(cap_std::fs::File::from_std(file)));
+
+        file.seek(std::io::SeekFrom::Start(0))
+            .await
+            .expect("seek is forwarded");
+        let mut buf = [0; 16];
+        let n = file
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .expect("read is forwarded");
+        assert_eq!(&buf[..n as usize], b"immutable");
+
+        for err in [
+            file.write_vectored(&[IoSlice::new(b"x")]).await,
+            file.write_vectored_at(&[IoSlice::new(b"x")], 0).await,
+        ] {
+            let err = err.expect_err("write is rejected");
+            assert_eq!(err.downcast().expect("errno"), Errno::Badf);
+        }
+        let err = file
+            .set_filestat_size(0)
+            .await
+            .expect_err("truncate is rejected");
+        assert_eq!(err.downcast().expect("errno"), Errno::Badf);
+        let err = file
+            .allocate(0, 4096)
+            .await
+            .expect_err("allocate is rejected");
+        assert_eq!(err.downcast().expect("errno"), Errno::Badf);
+
+        let stat = file.get_filestat().await.expect("stat is forwarded");
+        assert_eq!(stat.size, 9);
+    }
+}
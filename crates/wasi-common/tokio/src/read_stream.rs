@@ -0,0 +1,187 @@
+use bytes::Bytes;
+use std::any::Any;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use tokio::sync::{mpsc, Mutex};
+use wasi_common::{
+    file::{FileType, WasiFile},
+    Error,
+};
+
+// How much to read ahead when a guest polls for readability before reading.
+const READ_AHEAD: usize = 8 * 1024;
+
+/// A read-only `WasiFile` fed by a host-side [`AsyncRead`], such as the body
+/// of a host-mediated HTTP response.
+///
+/// Reads wait on the reader asynchronously, so a slow producer never blocks a
+/// runtime thread, and nothing is pulled from the reader until the guest asks
+/// for it. A read of zero bytes means the reader reached EOF.
+pub struct AsyncReadStream {
+    state: Mutex<State>,
+}
+
+struct State {
+    reader: Box<dyn AsyncRead + Send + Unpin>,
+    // Bytes read ahead to answer `readable`, which must be handed out before
+    // anything else is read.
+    buffered: Bytes,
+    eof: bool,
+}
+
+impl AsyncReadStream {
+    pub fn new(reader: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        AsyncReadStream {
+            state: Mutex::new(State {
+                reader: Box::new(reader),
+                buffered: Bytes::new(),
+                eof: false,
+            }),
+        }
+    }
+
+    /// Create a stream fed by chunks sent over a channel, which is the glue
+    /// for body types that are a `Stream<Item = io::Result<Bytes>>`: spawn a
+    /// task which forwards each item into the sender.
+    ///
+    /// Chunks may be any size, and empty ones are skipped. The channel's
+    /// bound provides the backpressure: the producer can only get that many
+    /// chunks ahead of the guest. An `Err` item is reported by the guest's
+    /// next read, and dropping the sender is EOF.
+    pub fn from_channel(rx: mpsc::Receiver<io::Result<Bytes>>) -> Self {
+        Self::new(ChannelReader {
+            rx,
+            chunk: Bytes::new(),
+        })
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for AsyncReadStream {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::Pipe)
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut state = self.state.lock().await;
+        let buf = match bufs.iter_mut().find(|b| !b.is_empty()) {
+            Some(buf) => buf,
+            None => return Ok(0),
+        };
+        if !state.buffered.is_empty() {
+            let n = buf.len().min(state.buffered.len());
+            buf[..n].copy_from_slice(&state.buffered.split_to(n));
+            return Ok(n as u64);
+        }
+        if state.eof {
+            return Ok(0);
+        }
+        let n = state.reader.read(&mut buf[..]).await?;
+        if n == 0 {
+            state.eof = true;
+        }
+        Ok(n as u64)
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self
+            .state
+            .try_lock()
+            .map_or(0, |state| state.buffered.len() as u64))
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        if !state.buffered.is_empty() || state.eof {
+            return Ok(());
+        }
+        let mut chunk = vec![0; READ_AHEAD];
+        let n = state.reader.read(&mut chunk).await?;
+        chunk.truncate(n);
+        state.buffered = chunk.into();
+        state.eof = n == 0;
+        Ok(())
+    }
+}
+
+struct ChannelReader {
+    rx: mpsc::Receiver<io::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl AsyncRead for ChannelReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.chunk.is_empty() {
+            match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.chunk = chunk,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                // The sender is gone, so this is EOF.
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = buf.remaining().min(self.chunk.len());
+        buf.put_slice(&self.chunk.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncReadStream;
+    use bytes::Bytes;
+    use std::io::{self, IoSliceMut};
+    use tokio::sync::mpsc;
+    use wasi_common::WasiFile;
+
+    async fn read(stream: &AsyncReadStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        let n = stream
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        buf.truncate(n as usize);
+        buf
+    }
+
+    #[tokio::test]
+    async fn reads_across_chunks_until_eof() {
+        let (tx, rx) = mpsc::channel(1);
+        let stream = AsyncReadStream::from_channel(rx);
+        tokio::spawn(async move {
+            for chunk in [&b"hel"[..], b"", b"lo world"] {
+                tx.send(Ok(Bytes::from_static(chunk))).await.unwrap();
+            }
+        });
+
+        assert_eq!(read(&stream, 2).await, b"he");
+        assert_eq!(read(&stream, 16).await, b"l");
+        stream.readable().await.unwrap();
+        assert_eq!(stream.num_ready_bytes().unwrap(), 8);
+        assert_eq!(read(&stream, 5).await, b"lo wo");
+        assert_eq!(read(&stream, 16).await, b"rld");
+        assert_eq!(read(&stream, 16).await, b"");
+        stream.readable().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_producer_errors() {
+        let (tx, rx) = mpsc::channel(1);
+        let stream = AsyncReadStream::from_channel(rx);
+        tx.send(Err(io::Error::from(io::ErrorKind::ConnectionReset)))
+            .await
+            .unwrap();
+
+        let mut buf = [0; 4];
+        stream
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .expect_err("producer failed");
+    }
+}
@@ -0,0 +1,70 @@
+use crate::file::File;
+use wasi_common::Error;
+
+impl File {
+    /// Ask the host to start reading `[offset, offset + len)` into its page
+    /// cache, so a guest about to stream through a large file finds it
+    /// already in memory.
+    ///
+    /// This is `readahead(2)` on Linux and `fcntl(F_RDADVISE)` on macOS, both
+    /// of which begin the I/O rather than merely noting the access pattern the
+    /// way `advise` with `Advice::WillNeed` may. Elsewhere it does nothing
+    /// and returns `Ok`, since it's only ever a hint.
+    pub async fn readahead(&self, offset: u64, len: usize) -> Result<(), Error> {
+        tokio::task::block_in_place(|| sys::readahead(&self.inner, offset, len))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::convert::TryInto;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::Error;
+
+    pub(super) fn readahead(fd: impl AsFd, offset: u64, len: usize) -> Result<(), Error> {
+        let offset = offset.try_into()?;
+        if unsafe { libc::readahead(fd.as_fd().as_raw_fd(), offset, len) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::convert::TryInto;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::Error;
+
+    pub(super) fn readahead(fd: impl AsFd, offset: u64, len: usize) -> Result<(), Error> {
+        let mut advisory = libc::radvisory {
+            ra_offset: offset.try_into()?,
+            // The count is only an `int`, so a longer range is advised in part.
+            ra_count: len.try_into().unwrap_or(libc::c_int::MAX),
+        };
+        let ret = unsafe {
+            libc::fcntl(
+                fd.as_fd().as_raw_fd(),
+                libc::F_RDADVISE,
+                &mut advisory as *mut libc::radvisory,
+            )
+        };
+        if ret == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+mod sys {
+    use wasi_common::Error;
+
+    pub(super) fn readahead<F>(_fd: F, _offset: u64, _len: usize) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use std::any::Any;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FileType, RiFlags, RoFlags, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error,
+};
+
+type Connect<F> = Box<dyn FnMut() -> Pin<Box<dyn Future<Output = Result<F, Error>> + Send>> + Send>;
+
+/// A `WasiFile` wrapper for a streaming source which may drop, such as a
+/// long-poll connection: when a read fails because the connection was reset
+/// or aborted, it makes a new one with a host-supplied closure and reads
+/// from that instead.
+///
+/// Each read reconnects at most once; if the new connection fails too, or
+/// the closure does, that error goes to the guest, and the next read starts
+/// over. Whatever was in flight on the dropped connection is lost, and the
+/// new one starts wherever the source does: picking up where the guest left
+/// off is up to the source and the guest. Only `read_vectored` and
+/// `sock_recv` reconnect; writes and readiness are forwarded to the current
+/// connection as they are.
+pub struct Reconnecting<F: WasiFile> {
+    state: Mutex<State<F>>,
+}
+
+struct State<F> {
+    inner: F,
+    connect: Connect<F>,
+}
+
+impl<F: WasiFile> Reconnecting<F> {
+    /// Wrap `inner`, replacing it with the result of `connect` whenever it
+    /// drops.
+    pub fn new<C, Fut>(inner: F, mut connect: C) -> Self
+    where
+        C: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<F, Error>> + Send + 'static,
+    {
+        Reconnecting {
+            state: Mutex::new(State {
+                inner,
+                connect: Box::new(move || Box::pin(connect())),
+            }),
+        }
+    }
+    pub fn into_inner(self) -> F {
+        self.state.into_inner().inner
+    }
+}
+
+fn is_dropped(e: &Error) -> bool {
+    matches!(
+        e.downcast_ref(),
+        Some(Errno::Connreset | Errno::Connaborted)
+    )
+}
+
+// Run `$op` on the current connection, and if it dropped, once more on a
+// new one.
+macro_rules! reconnecting {
+    ($self:ident, |$inner:ident| $op:expr) => {{
+        let mut state = $self.state.lock().await;
+        let result = {
+            let $inner = &state.inner;
+            $op.await
+        };
+        match result {
+            Err(e) if is_dropped(&e) => {
+                state.inner = (state.connect)().await?;
+                let $inner = &state.inner;
+                $op.await
+            }
+            result => result,
+        }
+    }};
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for Reconnecting<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.state.lock().await.inner.get_filetype().await
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        reconnecting!(self, |inner| inner.sock_recv(ri_data, ri_flags))
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        reconnecting!(self, |inner| inner.read_vectored(bufs))
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.state.lock().await.inner.write_vectored(bufs).await
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.state.lock().await.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.state.lock().await.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Reconnecting;
+    use crate::BytesFile;
+    use std::any::Any;
+    use std::io::{self, IoSliceMut};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use wasi_common::{
+        file::{FileType, WasiFile},
+        snapshots::preview_1::types::Errno,
+        Error,
+    };
+
+    // A source which is either up, serving its data, or has dropped.
+    struct Source(Option<BytesFile>);
+
+    #[wiggle::async_trait]
+    impl WasiFile for Source {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        async fn get_filetype(&self) -> Result<FileType, Error> {
+            Ok(FileType::SocketStream)
+        }
+        async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+            match &self.0 {
+                Some(data) => data.read_vectored(bufs).await,
+                None => Err(Error::from(Errno::Connreset)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn read_resumes_after_reconnect() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let f = Reconnecting::new(Source(None), {
+            let connects = connects.clone();
+            move || {
+                connects.fetch_add(1, Ordering::SeqCst);
+                async { Ok(Source(Some(BytesFile::new(Arc::from(&b"fresh"[..]))))) }
+            }
+        });
+
+        let mut buf = [0; 8];
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(&buf[..n as usize], b"fresh");
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+        // The new connection is kept for later reads.
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(n, 0);
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::file::seek_target;
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use std::sync::Mutex;
+use wasi_common::{
+    file::{FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+/// The other end of a [`RemoteFile`]: a file served by some other machine,
+/// over whatever transport the host has, such as gRPC or HTTP range
+/// requests.
+///
+/// Errors are passed to the guest as they are, so a backend should map its
+/// transport's failures onto errnos, conventionally `EIO`. Implementations
+/// need `#[wiggle::async_trait]`, as the trait's definition has.
+#[wiggle::async_trait]
+pub trait RemoteBackend: Send + Sync {
+    /// Read from `offset` into `buf`, returning how many bytes were read.
+    /// Fewer than `buf.len()`, including none, means the file ends there.
+    async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize, Error>;
+
+    /// Write `buf` at `offset`, returning how many bytes were written.
+    async fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize, Error>;
+
+    /// The file's metadata. Only `size` is relied on here, for seeks from the
+    /// end; the rest is passed to the guest as is.
+    async fn stat(&self) -> Result<Filestat, Error>;
+}
+
+/// A `WasiFile` whose contents live behind a [`RemoteBackend`], for
+/// distributed setups where a guest's files aren't on the host running it.
+///
+/// Reads and writes each become one call to the backend, at the file's own
+/// offset or at the guest's for positional ones; a guest's iovecs are
+/// gathered into a single buffer for the call, so a backend never sees them.
+/// `get_filestat` is the backend's `stat`. Truncation, syncing, and other
+/// operations the backend has no call for fail with `ENOTSUP`.
+pub struct RemoteFile<B: RemoteBackend> {
+    backend: B,
+    position: Mutex<u64>,
+}
+
+impl<B: RemoteBackend> RemoteFile<B> {
+    pub fn new(backend: B) -> Self {
+        RemoteFile {
+            backend,
+            position: Mutex::new(0),
+        }
+    }
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+    pub fn into_backend(self) -> B {
+        self.backend
+    }
+
+    async fn read_at(&self, bufs: &mut [io::IoSliceMut<'_>], offset: u64) -> Result<u64, Error> {
+        let mut buf = vec![0; iovec_len(bufs.iter().map(|b| b.len()))?];
+        let n = self.backend.read_at(&mut buf, offset).await?;
+        let mut rest = &buf[..n.min(buf.len())];
+        for b in bufs {
+            let len = b.len().min(rest.len());
+            b[..len].copy_from_slice(&rest[..len]);
+            rest = &rest[len..];
+        }
+        Ok(n as u64)
+    }
+
+    async fn write_at(&self, bufs: &[io::IoSlice<'_>], offset: u64) -> Result<u64, Error> {
+        let mut buf = Vec::with_capacity(iovec_len(bufs.iter().map(|b| b.len()))?);
+        for b in bufs {
+            buf.extend_from_slice(b);
+        }
+        let n = self.backend.write_at(&buf, offset).await?;
+        Ok(n as u64)
+    }
+}
+
+#[wiggle::async_trait]
+impl<B: RemoteBackend + 'static> WasiFile for RemoteFile<B> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Err(Error::not_supported().context("remote files can't be synced"))
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Err(Error::not_supported().context("remote files can't be synced"))
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.backend.stat().await
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::not_supported().context("remote files can't be resized"))
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.read_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.read_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.write_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.write_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let current = *self.position.lock().unwrap();
+        let new = seek_target(pos, current, || async {
+            Ok(self.backend.stat().await?.size)
+        })
+        .await?;
+        *self.position.lock().unwrap() = new;
+        Ok(new)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RemoteBackend, RemoteFile};
+    use std::io::{IoSlice, IoSliceMut, SeekFrom};
+    use std::sync::Mutex;
+    use wasi_common::{
+        file::{FileType, Filestat, WasiFile},
+        Error,
+    };
+
+    // A backend keeping the file in memory, as a stand-in for a server.
+    #[derive(Default)]
+    struct MemoryBackend {
+        data: Mutex<Vec<u8>>,
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    #[wiggle::async_trait]
+    impl RemoteBackend for MemoryBackend {
+        async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+            self.calls.lock().unwrap().push("read_at");
+            let data = self.data.lock().unwrap();
+            let start = (offset as usize).min(data.len());
+            let n = buf.len().min(data.len() - start);
+            buf[..n].copy_from_slice(&data[start..start + n]);
+            Ok(n)
+        }
+        async fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize, Error> {
+            self.calls.lock().unwrap().push("write_at");
+            let mut data = self.data.lock().unwrap();
+            let end = offset as usize + buf.len();
+            if data.len() < end {
+                data.resize(end, 0);
+            }
+            data[offset as usize..end].copy_from_slice(buf);
+            Ok(buf.len())
+        }
+        async fn stat(&self) -> Result<Filestat, Error> {
+            self.calls.lock().unwrap().push("stat");
+            Ok(Filestat {
+                device_id: 0,
+                inode: 0,
+                filetype: FileType::RegularFile,
+                nlink: 1,
+                size: self.data.lock().unwrap().len() as u64,
+                atim: None,
+                mtim: None,
+                ctim: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn operations_become_backend_calls() {
+        let f = RemoteFile::new(MemoryBackend::default());
+        let n = f
+            .write_vectored(&[IoSlice::new(b"hello "), IoSlice::new(b"world")])
+            .await
+            .unwrap();
+        assert_eq!(n, 11);
+        f.write_vectored_at(&[IoSlice::new(b"W")], 6).await.unwrap();
+        assert_eq!(f.get_filestat().await.unwrap().size, 11);
+
+        assert_eq!(f.seek(SeekFrom::End(-5)).await.unwrap(), 6);
+        let (mut a, mut b) = ([0; 3], [0; 8]);
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)])
+            .await
+            .unwrap();
+        assert_eq!(n, 5);
+        assert_eq!((&a, &b[..2]), (b"Wor", &b"ld"[..]));
+        assert_eq!(f.seek(SeekFrom::Current(0)).await.unwrap(), 11);
+
+        assert_eq!(
+            *f.backend().calls.lock().unwrap(),
+            ["write_at", "write_at", "stat", "stat", "read_at"]
+        );
+    }
+}
@@ -0,0 +1,438 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error, ErrorExt,
+};
+
+// Every entry starts with its time in nanoseconds since recording began
+// (u64), the operation (u8), its argument (u64), the errno it failed with,
+// or 0 (u16), the value it returned (u64), and the length of the data it
+// returned (u32), all little-endian. The data follows.
+const HEADER_LEN: usize = 8 + 1 + 8 + 2 + 8 + 4;
+
+/// The operations a [`Recording`] records, and a [`Replay`] serves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+enum Op {
+    Read = 0,
+    ReadAt = 1,
+    Peek = 2,
+    Write = 3,
+    WriteAt = 4,
+    Seek = 5,
+    Readable = 6,
+    Writable = 7,
+    Sync = 8,
+    Datasync = 9,
+}
+
+impl Op {
+    fn from_u8(op: u8) -> Option<Op> {
+        const OPS: [Op; 10] = [
+            Op::Read,
+            Op::ReadAt,
+            Op::Peek,
+            Op::Write,
+            Op::WriteAt,
+            Op::Seek,
+            Op::Readable,
+            Op::Writable,
+            Op::Sync,
+            Op::Datasync,
+        ];
+        OPS.get(op as usize).copied()
+    }
+}
+
+/// A `WasiFile` wrapper which writes a trace of a guest's operations on the
+/// inner file to a sink, for a [`Replay`] to serve back to the guest later,
+/// when debugging behaviour which doesn't reproduce on its own.
+///
+/// Each entry holds when the operation finished, relative to when the
+/// recording started, which operation it was, its offset or seek position
+/// where it has one, and its result: the errno it failed with, or the value
+/// it returned and, for reads, the bytes read. Reads (`read_vectored`,
+/// `read_vectored_at`, and `peek`), writes (`write_vectored` and
+/// `write_vectored_at`), `seek`, `readable`, `writable`, `sync`, and
+/// `datasync` are recorded; everything else is forwarded unrecorded.
+///
+/// Entries are written in the order operations finish, which is the order
+/// the guest made them in as long as it makes them one at a time. If the
+/// sink fails, the operation fails with `EIO`, though it has already been
+/// carried out on the inner file.
+pub struct Recording<F: WasiFile> {
+    inner: F,
+    start: Instant,
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl<F: WasiFile> Recording<F> {
+    pub fn new(inner: F, sink: impl Write + Send + 'static) -> Self {
+        Recording {
+            inner,
+            start: Instant::now(),
+            sink: Mutex::new(Box::new(sink)),
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn record(
+        &self,
+        op: Op,
+        arg: u64,
+        result: &Result<u64, Error>,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let (errno, value, data) = match result {
+            Ok(value) => (Errno::Success, *value, data),
+            Err(e) => (e.downcast_ref().copied().unwrap_or(Errno::Io), 0, &[][..]),
+        };
+        let len = u32::try_from(data.len())
+            .map_err(|_| Error::overflow().context("too much data to record"))?;
+        let time = u64::try_from(self.start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+
+        let mut entry = Vec::with_capacity(HEADER_LEN + data.len());
+        entry.extend_from_slice(&time.to_le_bytes());
+        entry.push(op as u8);
+        entry.extend_from_slice(&arg.to_le_bytes());
+        entry.extend_from_slice(&u16::from(errno).to_le_bytes());
+        entry.extend_from_slice(&value.to_le_bytes());
+        entry.extend_from_slice(&len.to_le_bytes());
+        entry.extend_from_slice(data);
+        self.sink
+            .lock()
+            .unwrap()
+            .write_all(&entry)
+            .map_err(|e| Error::io().context(format!("writing to the trace: {}", e)))
+    }
+
+    // Record a read, with the bytes it read out of `bufs`.
+    fn record_read(
+        &self,
+        op: Op,
+        arg: u64,
+        result: Result<u64, Error>,
+        bufs: &[&[u8]],
+    ) -> Result<u64, Error> {
+        let mut data = Vec::new();
+        if let Ok(n) = result {
+            let mut left = n as usize;
+            for buf in bufs {
+                let take = buf.len().min(left);
+                data.extend_from_slice(&buf[..take]);
+                left -= take;
+            }
+        }
+        self.record(op, arg, &result, &data)?;
+        result
+    }
+
+    fn record_unit(&self, op: Op, result: Result<(), Error>) -> Result<(), Error> {
+        let as_value = result.map(|()| 0);
+        self.record(op, 0, &as_value, &[])?;
+        as_value.map(|_| ())
+    }
+}
+
+fn seek_arg(pos: io::SeekFrom) -> u64 {
+    match pos {
+        io::SeekFrom::Start(n) => n,
+        io::SeekFrom::End(n) | io::SeekFrom::Current(n) => n as u64,
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for Recording<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        let result = self.inner.datasync().await;
+        self.record_unit(Op::Datasync, result)
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        let result = self.inner.sync().await;
+        self.record_unit(Op::Sync, result)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let result = self.inner.read_vectored(bufs).await;
+        let bufs = bufs.iter().map(|b| &b[..]).collect::<Vec<_>>();
+        self.record_read(Op::Read, 0, result, &bufs)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let result = self.inner.read_vectored_at(bufs, offset).await;
+        let bufs = bufs.iter().map(|b| &b[..]).collect::<Vec<_>>();
+        self.record_read(Op::ReadAt, offset, result, &bufs)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let result = self.inner.write_vectored(bufs).await;
+        self.record(Op::Write, 0, &result, &[])?;
+        result
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let result = self.inner.write_vectored_at(bufs, offset).await;
+        self.record(Op::WriteAt, offset, &result, &[])?;
+        result
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let result = self.inner.seek(pos).await;
+        self.record(Op::Seek, seek_arg(pos), &result, &[])?;
+        result
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        let result = self.inner.peek(buf).await;
+        self.record_read(Op::Peek, 0, result, &[&*buf])
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        let result = self.inner.readable().await;
+        self.record_unit(Op::Readable, result)
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        let result = self.inner.writable().await;
+        self.record_unit(Op::Writable, result)
+    }
+}
+
+struct Entry {
+    op: Op,
+    result: Result<u64, Errno>,
+    data: Vec<u8>,
+}
+
+/// A `WasiFile` which serves a guest the results of the operations in a
+/// trace written by a [`Recording`], in order, whatever the guest passes
+/// them.
+///
+/// Each operation takes the trace's next entry. If the guest makes a
+/// different operation than the one recorded, reads into less space than
+/// the recorded data needs, or carries on past the end of the trace, the
+/// replay has diverged from the recording, and the operation fails with
+/// `EIO`. Operations a `Recording` doesn't record aren't supported, except
+/// `get_filetype`, which returns the type given to [`Replay::new`]. Entries'
+/// times are ignored: results are served as soon as they're asked for.
+pub struct Replay {
+    filetype: FileType,
+    entries: Mutex<VecDeque<Entry>>,
+}
+
+impl Replay {
+    /// Parse a trace, failing with `EINVAL` if it's malformed.
+    pub fn new(trace: &[u8], filetype: FileType) -> Result<Self, Error> {
+        let malformed = || Error::invalid_argument().context("malformed replay trace");
+        let mut entries = VecDeque::new();
+        let mut rest = trace;
+        while !rest.is_empty() {
+            if rest.len() < HEADER_LEN {
+                return Err(malformed());
+            }
+            let (header, tail) = rest.split_at(HEADER_LEN);
+            let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+            let op = Op::from_u8(header[8]).ok_or_else(malformed)?;
+            let errno = u16::from_le_bytes(header[17..19].try_into().unwrap());
+            let errno = Errno::try_from(errno).map_err(|_| malformed())?;
+            let value = u64_at(19);
+            let len = u32::from_le_bytes(header[27..31].try_into().unwrap()) as usize;
+            if tail.len() < len {
+                return Err(malformed());
+            }
+            let (data, tail) = tail.split_at(len);
+            entries.push_back(Entry {
+                op,
+                result: match errno {
+                    Errno::Success => Ok(value),
+                    errno => Err(errno),
+                },
+                data: data.to_vec(),
+            });
+            rest = tail;
+        }
+        Ok(Replay {
+            filetype,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// The number of entries not yet replayed.
+    pub fn remaining(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    fn next(&self, op: Op) -> Result<Entry, Error> {
+        let entry = self.entries.lock().unwrap().pop_front().ok_or_else(|| {
+            Error::io().context(format!(
+                "replay diverged: {:?} past the end of the trace",
+                op
+            ))
+        })?;
+        if entry.op != op {
+            return Err(Error::io().context(format!(
+                "replay diverged: guest called {:?}, trace has {:?}",
+                op, entry.op
+            )));
+        }
+        Ok(entry)
+    }
+
+    fn value(&self, op: Op) -> Result<u64, Error> {
+        self.next(op)?.result.map_err(Error::from)
+    }
+
+    fn read(&self, op: Op, bufs: &mut [&mut [u8]]) -> Result<u64, Error> {
+        let entry = self.next(op)?;
+        let n = entry.result.map_err(Error::from)?;
+        let mut data = &entry.data[..];
+        for buf in bufs.iter_mut() {
+            let take = buf.len().min(data.len());
+            buf[..take].copy_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+        if !data.is_empty() {
+            return Err(Error::io()
+                .context("replay diverged: read buffers too small for the recorded data"));
+        }
+        Ok(n)
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Replay {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(self.filetype)
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.value(Op::Datasync).map(|_| ())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.value(Op::Sync).map(|_| ())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut bufs = bufs.iter_mut().map(|b| &mut b[..]).collect::<Vec<_>>();
+        self.read(Op::Read, &mut bufs)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        let mut bufs = bufs.iter_mut().map(|b| &mut b[..]).collect::<Vec<_>>();
+        self.read(Op::ReadAt, &mut bufs)
+    }
+    async fn write_vectored<'a>(&self, _bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.value(Op::Write)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        self.value(Op::WriteAt)
+    }
+    async fn seek(&self, _pos: io::SeekFrom) -> Result<u64, Error> {
+        self.value(Op::Seek)
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.read(Op::Peek, &mut [buf])
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.value(Op::Readable).map(|_| ())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.value(Op::Writable).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Recording, Replay};
+    use crate::BytesFile;
+    use std::io::{self, IoSlice, IoSliceMut};
+    use std::sync::{Arc, Mutex};
+    use wasi_common::{file::FileType, snapshots::preview_1::types::Errno, WasiFile};
+
+    #[derive(Clone, Default)]
+    struct Trace(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Trace {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // What the guest sees of a short session.
+    async fn session(f: &dyn WasiFile) -> Vec<Result<Vec<u8>, Errno>> {
+        let mut seen = Vec::new();
+        let mut buf = [0; 4];
+        let n = f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await;
+        seen.push(n.map(|n| buf[..n as usize].to_vec()).map_err(errno));
+        let pos = f.seek(io::SeekFrom::Start(1)).await;
+        seen.push(pos.map(|p| p.to_le_bytes().to_vec()).map_err(errno));
+        let mut buf = [0; 3];
+        let n = f.peek(&mut buf).await;
+        seen.push(n.map(|n| buf[..n as usize].to_vec()).map_err(errno));
+        let n = f.write_vectored(&[IoSlice::new(b"no")]).await;
+        seen.push(n.map(|n| n.to_le_bytes().to_vec()).map_err(errno));
+        seen
+    }
+
+    fn errno(e: wasi_common::Error) -> Errno {
+        e.downcast().unwrap()
+    }
+
+    #[tokio::test]
+    async fn replay_matches_recording() {
+        let trace = Trace::default();
+        let recording = Recording::new(BytesFile::new(Arc::from(&b"recorded"[..])), trace.clone());
+        let recorded = session(&recording).await;
+        // The write fails, and that's replayed too.
+        assert!(recorded[3].is_err());
+
+        let trace = trace.0.lock().unwrap().clone();
+        let replay = Replay::new(&trace, FileType::RegularFile).unwrap();
+        assert_eq!(replay.remaining(), 4);
+        assert_eq!(session(&replay).await, recorded);
+        assert_eq!(replay.remaining(), 0);
+
+        let err = replay.readable().await.expect_err("past the end");
+        assert_eq!(errno(err), Errno::Io);
+    }
+}
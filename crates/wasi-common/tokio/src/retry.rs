@@ -0,0 +1,247 @@
+use std::any::Any;
+use std::io;
+use std::time::Duration;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error, SystemTimeSpec,
+};
+
+/// How [`RetryEagain`] waits between attempts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryBackoff {
+    /// Yield to the runtime's other tasks, then try again.
+    Yield,
+    /// Sleep for a fixed time, then try again.
+    Sleep(Duration),
+}
+
+impl RetryBackoff {
+    async fn wait(self) {
+        match self {
+            RetryBackoff::Yield => tokio::task::yield_now().await,
+            RetryBackoff::Sleep(duration) => tokio::time::sleep(duration).await,
+        }
+    }
+}
+
+/// A `WasiFile` wrapper which retries reads and writes that fail with
+/// `EAGAIN`, up to a fixed number of times, before passing the error on.
+///
+/// This is for non-blocking descriptors which are only momentarily full or
+/// empty, such as a socket whose send buffer drains a moment later: rather
+/// than bouncing each such `EAGAIN` back to a guest which may busy-loop on it,
+/// the host absorbs a few. More retries trade latency on a descriptor that
+/// really isn't ready for fewer wasted round trips through the guest.
+///
+/// Only `read_vectored`, `read_vectored_at`, `write_vectored`,
+/// `write_vectored_at`, `sock_recv`, and `sock_send` are retried; everything
+/// else is forwarded as is.
+pub struct RetryEagain<F: WasiFile> {
+    inner: F,
+    max_retries: u32,
+    backoff: RetryBackoff,
+}
+
+impl<F: WasiFile> RetryEagain<F> {
+    pub fn new(inner: F, max_retries: u32, backoff: RetryBackoff) -> Self {
+        RetryEagain {
+            inner,
+            max_retries,
+            backoff,
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+fn is_again(e: &Error) -> bool {
+    e.downcast_ref() == Some(&Errno::Again)
+}
+
+// Evaluate `$op`, a future, again for as long as it fails with `EAGAIN` and
+// retries remain.
+macro_rules! retry {
+    ($self:ident, $op:expr) => {{
+        let mut retries = 0;
+        loop {
+            match $op.await {
+                Err(e) if retries < $self.max_retries && is_again(&e) => {
+                    retries += 1;
+                    $self.backoff.wait().await;
+                }
+                result => break result,
+            }
+        }
+    }};
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for RetryEagain<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.inner.sock_accept(fdflags).await
+    }
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        retry!(self, self.inner.sock_recv(ri_data, ri_flags))
+    }
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[io::IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        retry!(self, self.inner.sock_send(si_data, si_flags))
+    }
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.inner.sock_shutdown(how).await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.inner.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.inner.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        retry!(self, self.inner.read_vectored(bufs))
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        retry!(self, self.inner.read_vectored_at(bufs, offset))
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        retry!(self, self.inner.write_vectored(bufs))
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        retry!(self, self.inner.write_vectored_at(bufs, offset))
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RetryBackoff, RetryEagain};
+    use std::any::Any;
+    use std::io::IoSlice;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+    use wasi_common::{file::FileType, snapshots::preview_1::types::Errno, Error, WasiFile};
+
+    // A socket whose send buffer is full for the first few writes.
+    struct MomentarilyFull {
+        full_for: u32,
+        attempts: AtomicU32,
+    }
+
+    #[wiggle::async_trait]
+    impl WasiFile for MomentarilyFull {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        async fn get_filetype(&self) -> Result<FileType, Error> {
+            Ok(FileType::SocketStream)
+        }
+        async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.full_for {
+                return Err(Errno::Again.into());
+            }
+            Ok(bufs.iter().map(|b| b.len() as u64).sum())
+        }
+    }
+
+    fn momentarily_full(full_for: u32) -> MomentarilyFull {
+        MomentarilyFull {
+            full_for,
+            attempts: AtomicU32::new(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_write_goes_through() {
+        let f = RetryEagain::new(momentarily_full(2), 3, RetryBackoff::Yield);
+        let n = f.write_vectored(&[IoSlice::new(b"hello")]).await.unwrap();
+        assert_eq!(n, 5);
+        assert_eq!(f.get_ref().attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_retry_budget() {
+        let backoff = RetryBackoff::Sleep(Duration::from_millis(1));
+        let f = RetryEagain::new(momentarily_full(10), 3, backoff);
+        let err = f
+            .write_vectored(&[IoSlice::new(b"hello")])
+            .await
+            .expect_err("still full");
+        assert_eq!(err.downcast().unwrap(), Errno::Again);
+        assert_eq!(f.get_ref().attempts.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use wasi_common::{
+    file::{FileType, WasiFile},
+    Error,
+};
+
+/// A `WasiFile` which keeps only the most recent output written to it, such
+/// as the last few KiB of a guest's stdout to attach to a crash report.
+///
+/// Writes always succeed in full, overwriting the oldest bytes once the
+/// buffer holds `capacity` of them, so memory use stays bounded however much
+/// the guest writes. Reads are always at EOF. Clones share the same buffer,
+/// so the host can keep one to call [`RingCapture::contents`] on after
+/// handing another to the guest.
+#[derive(Clone)]
+pub struct RingCapture {
+    buf: Arc<Mutex<VecDeque<u8>>>,
+    capacity: usize,
+}
+
+impl RingCapture {
+    pub fn new(capacity: usize) -> Self {
+        RingCapture {
+            buf: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// A copy of the retained output, oldest byte first.
+    pub fn contents(&self) -> Vec<u8> {
+        self.buf.lock().unwrap().iter().copied().collect()
+    }
+
+    fn push(&self, data: &[u8]) {
+        // Only the tail of an oversized write can survive it.
+        let data = &data[data.len().saturating_sub(self.capacity)..];
+        let mut buf = self.buf.lock().unwrap();
+        let overflow = (buf.len() + data.len()).saturating_sub(self.capacity);
+        buf.drain(..overflow);
+        buf.extend(data);
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for RingCapture {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::Pipe)
+    }
+    async fn read_vectored<'a>(&self, _bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let n = iovec_len(bufs.iter().map(|b| b.len()))?;
+        for b in bufs {
+            self.push(b);
+        }
+        Ok(n as u64)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RingCapture;
+    use std::io::{IoSlice, IoSliceMut};
+    use wasi_common::WasiFile;
+
+    #[tokio::test]
+    async fn keeps_only_the_tail() {
+        let capture = RingCapture::new(8);
+        let guest = capture.clone();
+
+        guest
+            .write_vectored(&[IoSlice::new(b"hello "), IoSlice::new(b"wor")])
+            .await
+            .unwrap();
+        assert_eq!(capture.contents(), b"ello wor");
+        let n = guest.write_vectored(&[IoSlice::new(b"ld!")]).await.unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(capture.contents(), b"o world!");
+
+        // A single write larger than the buffer keeps just its own tail.
+        guest
+            .write_vectored(&[IoSlice::new(b"0123456789abcdef")])
+            .await
+            .unwrap();
+        assert_eq!(capture.contents(), b"89abcdef");
+
+        let mut buf = [0; 4];
+        let n = guest
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(n, 0);
+    }
+}
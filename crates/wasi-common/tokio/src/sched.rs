@@ -2,34 +2,67 @@
 mod unix;
 #[cfg(unix)]
 pub use unix::poll_oneoff;
+#[cfg(unix)]
+use unix::poll_oneoff_from;
 
 #[cfg(windows)]
 mod windows;
 #[cfg(windows)]
 pub use windows::poll_oneoff;
+#[cfg(windows)]
+use windows::poll_oneoff_from;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use wasi_common::{
     sched::{Duration, Poll, WasiSched},
     Error,
 };
 
-pub fn sched_ctx() -> Box<dyn wasi_common::WasiSched> {
-    struct AsyncSched;
+struct AsyncSched {
+    // In fair mode, where the next `poll_oneoff` starts checking
+    // subscriptions.
+    next_start: Option<AtomicUsize>,
+}
 
-    #[wiggle::async_trait]
-    impl WasiSched for AsyncSched {
-        async fn poll_oneoff<'a>(&self, poll: &mut Poll<'a>) -> Result<(), Error> {
-            poll_oneoff(poll).await
-        }
-        async fn sched_yield(&self) -> Result<(), Error> {
-            tokio::task::yield_now().await;
-            Ok(())
-        }
-        async fn sleep(&self, duration: Duration) -> Result<(), Error> {
-            tokio::time::sleep(duration).await;
-            Ok(())
+#[wiggle::async_trait]
+impl WasiSched for AsyncSched {
+    async fn poll_oneoff<'a>(&self, poll: &mut Poll<'a>) -> Result<(), Error> {
+        match &self.next_start {
+            Some(next_start) => {
+                let start = next_start.fetch_add(1, Ordering::Relaxed);
+                poll_oneoff_from(poll, start).await
+            }
+            None => poll_oneoff(poll).await,
         }
     }
+    async fn sched_yield(&self) -> Result<(), Error> {
+        tokio::task::yield_now().await;
+        Ok(())
+    }
+    async fn sleep(&self, duration: Duration) -> Result<(), Error> {
+        tokio::time::sleep(duration).await;
+        Ok(())
+    }
+}
+
+pub fn sched_ctx() -> Box<dyn wasi_common::WasiSched> {
+    Box::new(AsyncSched { next_start: None })
+}
 
-    Box::new(AsyncSched)
+/// A scheduler like [`sched_ctx`]'s, but which takes turns over which
+/// subscription it checks first, for a guest multiplexing many equally busy
+/// descriptors.
+///
+/// Every subscription which is ready is reported either way, but one which
+/// becomes ready while the others are being checked is only seen by the next
+/// `poll_oneoff` if it was checked first. Each `poll_oneoff` through this
+/// scheduler starts one subscription further along than the last, wrapping
+/// around, so no subscription is always checked last.
+///
+/// On Windows, where `poll_oneoff` checks every subscription at once, this is
+/// the same as [`sched_ctx`].
+pub fn fair_sched_ctx() -> Box<dyn wasi_common::WasiSched> {
+    Box::new(AsyncSched {
+        next_start: Some(AtomicUsize::new(0)),
+    })
 }
@@ -18,6 +18,13 @@ impl<'a, T> FirstReady<'a, T> {
     fn push(&mut self, f: impl Future<Output = T> + Send + 'a) {
         self.0.push(Box::pin(f));
     }
+    // Poll the futures starting from the `start`th, wrapping around.
+    fn rotate(&mut self, start: usize) {
+        if !self.0.is_empty() {
+            let len = self.0.len();
+            self.0.rotate_left(start % len);
+        }
+    }
 }
 
 impl<'a, T> Future for FirstReady<'a, T> {
@@ -43,6 +50,12 @@ impl<'a, T> Future for FirstReady<'a, T> {
 }
 
 pub async fn poll_oneoff<'a>(poll: &mut Poll<'a>) -> Result<(), Error> {
+    poll_oneoff_from(poll, 0).await
+}
+
+// Like `poll_oneoff`, but checking subscriptions from the `start`th onward,
+// wrapping around.
+pub(crate) async fn poll_oneoff_from<'a>(poll: &mut Poll<'a>, start: usize) -> Result<(), Error> {
     if poll.is_empty() {
         return Ok(());
     }
@@ -83,6 +96,7 @@ pub async fn poll_oneoff<'a>(poll: &mut Poll<'a>) -> Result<(), Error> {
             Subscription::MonotonicClock { .. } => unreachable!(),
         }
     }
+    futures.rotate(start);
     if let Some(Some(remaining_duration)) = duration {
         match tokio::time::timeout(remaining_duration, futures).await {
             Ok(r) => r?,
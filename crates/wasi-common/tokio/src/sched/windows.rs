@@ -10,6 +10,11 @@ pub async fn poll_oneoff<'a>(poll: &mut Poll<'a>) -> Result<(), Error> {
     block_on_dummy_executor(move || poll_oneoff_(poll, wasi_file_is_stdin))
 }
 
+// Every subscription is checked at once, so there's no order to rotate.
+pub(crate) async fn poll_oneoff_from<'a>(poll: &mut Poll<'a>, _start: usize) -> Result<(), Error> {
+    poll_oneoff(poll).await
+}
+
 pub fn wasi_file_is_stdin(f: &dyn WasiFile) -> bool {
     f.as_any().is::<crate::stdio::Stdin>()
 }
@@ -0,0 +1,225 @@
+use crate::iovec::iovec_len;
+use std::any::Any;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, WasiFile},
+    Error,
+};
+
+/// An output sink shared between many guests, such as a single log which
+/// aggregates the stdout of every instance in a multi-tenant host.
+///
+/// Each guest gets its own [`SharedSinkWriter`], created with
+/// [`SharedSink::writer`] or [`SharedSink::line_writer`], to use as its stdout
+/// or stderr.
+pub struct SharedSink<W> {
+    sink: Arc<Mutex<W>>,
+}
+
+impl<W> Clone for SharedSink<W> {
+    fn clone(&self) -> Self {
+        Self {
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send + Sync + 'static> SharedSink<W> {
+    pub fn new(sink: W) -> Self {
+        Self {
+            sink: Arc::new(Mutex::new(sink)),
+        }
+    }
+
+    /// Create a writer which holds the sink for the duration of each
+    /// `write_vectored` call, so a single write is never interleaved with
+    /// another guest's output.
+    pub fn writer(&self) -> SharedSinkWriter<W> {
+        SharedSinkWriter {
+            sink: self.sink.clone(),
+            pending: None,
+        }
+    }
+
+    /// Create a writer which buffers output until a newline and writes only
+    /// whole lines to the sink, so lines are never interleaved even when the
+    /// guest writes them piecemeal.
+    ///
+    /// A trailing unterminated line is written when the guest syncs the file,
+    /// or when the writer is dropped inside a tokio runtime.
+    pub fn line_writer(&self) -> SharedSinkWriter<W> {
+        SharedSinkWriter {
+            sink: self.sink.clone(),
+            pending: Some(std::sync::Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Try to convert this `SharedSink<W>` back to the underlying `W` type.
+    ///
+    /// This will fail with `Err(self)` if any other handle or writer still
+    /// refers to the sink.
+    pub fn try_into_inner(mut self) -> Result<W, Self> {
+        match Arc::try_unwrap(self.sink) {
+            Ok(sink) => Ok(sink.into_inner()),
+            Err(sink) => {
+                self.sink = sink;
+                Err(self)
+            }
+        }
+    }
+}
+
+/// One guest's handle on a [`SharedSink`].
+pub struct SharedSinkWriter<W: AsyncWrite + Unpin + Send + Sync + 'static> {
+    sink: Arc<Mutex<W>>,
+    // The unterminated tail of the output, when line buffering.
+    pending: Option<std::sync::Mutex<Vec<u8>>>,
+}
+
+impl<W: AsyncWrite + Unpin + Send + Sync + 'static> SharedSinkWriter<W> {
+    async fn write_all(&self, bufs: &[&[u8]]) -> Result<(), Error> {
+        let mut sink = self.sink.lock().await;
+        for buf in bufs {
+            sink.write_all(buf).await?;
+        }
+        sink.flush().await?;
+        Ok(())
+    }
+
+    async fn flush_pending(&self) -> Result<(), Error> {
+        let rest = match &self.pending {
+            Some(pending) => std::mem::take(&mut *pending.lock().unwrap()),
+            None => return Ok(()),
+        };
+        if !rest.is_empty() {
+            self.write_all(&[&rest]).await?;
+        }
+        Ok(())
+    }
+}
+
+#[wiggle::async_trait]
+impl<W: AsyncWrite + Unpin + Send + Sync + 'static> WasiFile for SharedSinkWriter<W> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::Pipe)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::APPEND)
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.flush_pending().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.flush_pending().await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let len = iovec_len(bufs.iter().map(|b| b.len()))?;
+        match &self.pending {
+            None => {
+                let bufs = bufs.iter().map(|b| &**b).collect::<Vec<_>>();
+                self.write_all(&bufs).await?;
+            }
+            Some(pending) => {
+                // Split off every complete line while holding the pending
+                // buffer's lock, but don't hold it across the write itself.
+                let lines = {
+                    let mut pending = pending.lock().unwrap();
+                    for buf in bufs {
+                        pending.extend_from_slice(buf);
+                    }
+                    match pending.iter().rposition(|b| *b == b'\n') {
+                        Some(ix) => {
+                            let rest = pending.split_off(ix + 1);
+                            std::mem::replace(&mut *pending, rest)
+                        }
+                        None => Vec::new(),
+                    }
+                };
+                if !lines.is_empty() {
+                    self.write_all(&[&lines]).await?;
+                }
+            }
+        }
+        Ok(len as u64)
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send + Sync + 'static> Drop for SharedSinkWriter<W> {
+    fn drop(&mut self) {
+        let rest = match self.pending.as_mut() {
+            Some(pending) => std::mem::take(pending.get_mut().unwrap()),
+            None => return,
+        };
+        if rest.is_empty() {
+            return;
+        }
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let sink = self.sink.clone();
+            handle.spawn(async move {
+                let mut sink = sink.lock().await;
+                let _ = sink.write_all(&rest).await;
+                let _ = sink.flush().await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SharedSink;
+    use std::io::IoSlice;
+    use wasi_common::WasiFile;
+
+    #[tokio::test]
+    async fn line_writers_do_not_interleave_lines() {
+        let sink = SharedSink::new(Vec::new());
+        let a = sink.line_writer();
+        let b = sink.line_writer();
+
+        a.write_vectored(&[IoSlice::new(b"a: one, ")])
+            .await
+            .unwrap();
+        b.write_vectored(&[IoSlice::new(b"b: first\nb: sec")])
+            .await
+            .unwrap();
+        a.write_vectored(&[IoSlice::new(b"two"), IoSlice::new(b"\n")])
+            .await
+            .unwrap();
+        b.write_vectored(&[IoSlice::new(b"ond\n")]).await.unwrap();
+        drop((a, b));
+
+        let out = match sink.try_into_inner() {
+            Ok(out) => out,
+            Err(_) => panic!("writers have been dropped"),
+        };
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "b: first\na: one, two\nb: second\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn unbuffered_writer_writes_each_call_whole() {
+        let sink = SharedSink::new(Vec::new());
+        let a = sink.writer();
+        a.write_vectored(&[IoSlice::new(b"par"), IoSlice::new(b"tial")])
+            .await
+            .unwrap();
+        drop(a);
+
+        let out = match sink.try_into_inner() {
+            Ok(out) => out,
+            Err(_) => panic!("writer has been dropped"),
+        };
+        assert_eq!(out, b"partial");
+    }
+}
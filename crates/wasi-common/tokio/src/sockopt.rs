@@ -0,0 +1,774 @@
+use crate::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use crate::net::{UnixListener, UnixStream};
+use std::time::Duration;
+use wasi_common::{snapshots::preview_1::types::Errno, Error, ErrorExt};
+
+// Linux's `TCP_CA_NAME_MAX`, the size of a congestion control algorithm's
+// name including its terminating NUL.
+const TCP_CA_NAME_MAX: usize = 16;
+
+/// The largest option value accepted by [`SocketControl`]. This comfortably
+/// covers every fixed-size option, including `TCP_INFO`, while keeping a
+/// guest from handing the kernel an arbitrarily large buffer.
+pub const MAX_SOCKOPT_LEN: usize = 512;
+
+/// How a socket discovers the path MTU, as set by
+/// [`TcpStream::set_mtu_discover`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MtuDiscover {
+    /// Set the don't-fragment bit and track the path MTU
+    /// (`IP_PMTUDISC_DO`).
+    Do,
+    /// Let packets be fragmented, and don't track the path MTU
+    /// (`IP_PMTUDISC_DONT`).
+    Dont,
+    /// Track the path MTU, fragmenting packets larger than it
+    /// (`IP_PMTUDISC_WANT`), which is the default.
+    Want,
+    /// Set the don't-fragment bit but ignore the path MTU, for probing it
+    /// (`IP_PMTUDISC_PROBE`).
+    Probe,
+}
+
+/// One instruction of a classic BPF program, as attached to a socket with
+/// `attach_filter`, laid out as Linux's `struct sock_filter`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// Raw access to socket options, for embedders implementing a generic
+/// `sock_getsockopt`/`sock_setsockopt` shim on top of the tokio socket types.
+///
+/// `level` and `name` are passed through to the host's `getsockopt` and
+/// `setsockopt` unchanged, so they use the host's constants (for example
+/// `libc::SOL_SOCKET` and `libc::SO_RCVBUF`), and option values use the
+/// host's layout.
+///
+/// Only options on a fixed list are passed through: those whose values are
+/// plain integers, and which only affect the socket's own behavior, such as
+/// `SO_KEEPALIVE`, `SO_RCVBUF`, `TCP_NODELAY`, and `IP_TTL`. Anything else
+/// is refused with `EPERM` regardless of the host process's own privileges,
+/// including options which take pointers or descriptors, like
+/// `SO_ATTACH_FILTER`, ones which bypass the host's network setup, like
+/// `SO_BINDTODEVICE` and `SO_MARK`, and ones which need privileges, like
+/// `SO_RCVBUFFORCE`. The typed methods on the socket types cover some of
+/// these for the host.
+///
+/// Not supported on Windows.
+pub trait SocketControl {
+    /// Read the value of an option into `buf`, returning the number of bytes
+    /// the host wrote. `buf` must be non-empty and at most
+    /// [`MAX_SOCKOPT_LEN`] bytes.
+    fn get_sockopt(&self, level: i32, name: i32, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Set an option to `value`, which must be a native-endian `int`.
+    fn set_sockopt(&self, level: i32, name: i32, value: &[u8]) -> Result<(), Error>;
+}
+
+fn check_value(value: &[u8]) -> Result<(), Error> {
+    if value.len() != std::mem::size_of::<i32>() {
+        return Err(Error::invalid_argument().context("socket option value must be an int"));
+    }
+    Ok(())
+}
+
+fn check_len(len: usize) -> Result<(), Error> {
+    if len == 0 {
+        return Err(Error::invalid_argument().context("socket option buffer is empty"));
+    }
+    if len > MAX_SOCKOPT_LEN {
+        return Err(Error::invalid_argument().context("socket option buffer is too large"));
+    }
+    Ok(())
+}
+
+macro_rules! socket_control_impl {
+    ($ty:ty, $field:tt) => {
+        impl SocketControl for $ty {
+            fn get_sockopt(&self, level: i32, name: i32, buf: &mut [u8]) -> Result<usize, Error> {
+                check_len(buf.len())?;
+                sys::check_allowed(level, name)?;
+                sys::get_sockopt(&self.$field, level, name, buf)
+            }
+            fn set_sockopt(&self, level: i32, name: i32, value: &[u8]) -> Result<(), Error> {
+                sys::check_allowed(level, name)?;
+                check_value(value)?;
+                sys::set_sockopt(&self.$field, level, name, value)
+            }
+        }
+    };
+}
+
+socket_control_impl!(TcpListener, 0);
+socket_control_impl!(TcpStream, inner);
+#[cfg(unix)]
+socket_control_impl!(UnixListener, 0);
+#[cfg(unix)]
+socket_control_impl!(UnixStream, inner);
+
+macro_rules! socket_filter_impl {
+    ($ty:ty, $field:tt) => {
+        impl $ty {
+            /// Attach a classic BPF program to the socket, which the kernel
+            /// runs on each packet arriving for it, dropping those it returns
+            /// zero for, for a guest which only wants to see some of its
+            /// traffic. This replaces any filter already attached.
+            ///
+            /// This is `SO_ATTACH_FILTER`, and only supported on Linux. The
+            /// program must have from 1 to 4096 instructions, and fails the
+            /// kernel's checks with `EINVAL`. Once a filter is locked with
+            /// `SO_LOCK_FILTER`, replacing it fails with `EPERM`. Guests can't
+            /// attach filters themselves through
+            /// [`SocketControl::set_sockopt`], since the option's value points
+            /// into the host's memory; this builds it on the host instead.
+            pub fn attach_filter(&self, program: &[SockFilter]) -> Result<(), Error> {
+                sys::attach_filter(&self.$field, program)
+                    .map_err(|e| permission_context(e, "the socket's filter is locked"))
+            }
+
+            /// Detach the socket's BPF filter, as attached by
+            /// `attach_filter`, so it sees all its traffic again.
+            ///
+            /// This is `SO_DETACH_FILTER`, and only supported on Linux. Fails
+            /// with `ENOENT` if no filter is attached, and `EPERM` if it's
+            /// locked.
+            pub fn detach_filter(&self) -> Result<(), Error> {
+                sys::detach_filter(&self.$field)
+                    .map_err(|e| permission_context(e, "the socket's filter is locked"))
+            }
+        }
+    };
+}
+
+socket_filter_impl!(TcpListener, 0);
+socket_filter_impl!(TcpStream, inner);
+#[cfg(unix)]
+socket_filter_impl!(UnixListener, 0);
+#[cfg(unix)]
+socket_filter_impl!(UnixStream, inner);
+
+impl TcpListener {
+    /// The number of connections waiting to be accepted, for an accept loop
+    /// which wants to shed load before its backlog overflows.
+    ///
+    /// This reads `TCP_INFO`, which Linux fills in for a listening socket
+    /// with the accept queue's length. Not supported elsewhere.
+    pub fn accept_queue_len(&self) -> Result<usize, Error> {
+        sys::accept_queue_len(&self.0)
+    }
+
+    /// Steer connections to this listener to the one whose receive CPU is
+    /// `cpu`, for a server running a listener per CPU with `SO_REUSEPORT`,
+    /// so that each connection is handled where its packets arrive.
+    ///
+    /// This is `SO_INCOMING_CPU`, and only supported on Linux.
+    pub fn set_incoming_cpu(&self, cpu: u32) -> Result<(), Error> {
+        let (level, name) = incoming_cpu_option()?;
+        let cpu = i32::try_from(cpu).map_err(|_| Error::invalid_argument())?;
+        sys::set_sockopt(&self.0, level, name, &cpu.to_ne_bytes())
+    }
+}
+
+impl TcpStream {
+    /// Cork the connection, holding back partial segments until it's
+    /// uncorked or a full segment's worth of data is queued. This lets a
+    /// header and body written separately go out together.
+    ///
+    /// This is `TCP_CORK` on Linux and `TCP_NOPUSH` on macOS and FreeBSD, and
+    /// not supported elsewhere. Corking overrides `TCP_NODELAY` for as long as
+    /// it's set. On Linux, uncorking sends whatever is pending right away; on
+    /// the BSDs, pending data may not go out until the next write.
+    pub fn set_cork(&self, cork: bool) -> Result<(), Error> {
+        let (level, name) = sys::TCP_CORK.ok_or_else(|| {
+            Error::not_supported().context("TCP corking is not supported on this platform")
+        })?;
+        sys::set_sockopt(&self.inner, level, name, &i32::from(cork).to_ne_bytes())
+    }
+
+    /// Acknowledge received data right away rather than delaying the ACK in
+    /// the hope of piggybacking it on a reply, for a request/response
+    /// protocol where the delay adds latency.
+    ///
+    /// This is `TCP_QUICKACK`, and only supported on Linux. The setting
+    /// doesn't stick: the kernel falls back to delayed ACKs on its own as
+    /// the connection's traffic pattern changes, typically after the next
+    /// read, so a host relying on it should set it again after each read.
+    pub fn set_quickack(&self, quickack: bool) -> Result<(), Error> {
+        let (level, name) = sys::TCP_QUICKACK.ok_or_else(|| {
+            Error::not_supported().context("TCP_QUICKACK is only available on Linux")
+        })?;
+        sys::set_sockopt(&self.inner, level, name, &i32::from(quickack).to_ne_bytes())
+    }
+
+    /// Only report the connection readable once at least `bytes` bytes are
+    /// waiting, for a guest reading fixed-size frames which would rather be
+    /// woken once per frame. Reads and `poll_oneoff` wait for the same
+    /// readiness, so both wait for the low-water mark; EOF and errors still
+    /// wake them early. The default is 1.
+    ///
+    /// This is `SO_RCVLOWAT`, and not supported on Windows. Linux caps the
+    /// low-water mark at half the receive buffer, `SO_RCVBUF`, since more
+    /// could never arrive, so a larger value takes effect as that; other
+    /// systems cap it at the whole receive buffer.
+    pub fn set_recv_lowat(&self, bytes: usize) -> Result<(), Error> {
+        let (level, name) = recv_lowat_option()?;
+        let bytes = i32::try_from(bytes).map_err(|_| {
+            Error::invalid_argument().context("receive low-water mark is too large")
+        })?;
+        sys::set_sockopt(&self.inner, level, name, &bytes.to_ne_bytes())
+    }
+
+    /// The connection's receive low-water mark, as set by
+    /// [`TcpStream::set_recv_lowat`]. Not supported on Windows.
+    pub fn recv_lowat(&self) -> Result<usize, Error> {
+        let (level, name) = recv_lowat_option()?;
+        let mut buf = [0; 4];
+        sys::get_sockopt(&self.inner, level, name, &mut buf)?;
+        Ok(i32::from_ne_bytes(buf).max(0) as usize)
+    }
+
+    /// Switch the connection to the named congestion control algorithm, such
+    /// as `"bbr"` for bulk transfers over high-latency links.
+    ///
+    /// This is `TCP_CONGESTION`, and only supported on Linux. The algorithm
+    /// must be built into the host's kernel or loaded as a module; if it
+    /// isn't, this fails with `ENOENT`. Algorithms outside
+    /// `net.ipv4.tcp_allowed_congestion_control` additionally need
+    /// `CAP_NET_ADMIN`, and fail with `EPERM` without it.
+    pub fn set_congestion_control(&self, algo: &str) -> Result<(), Error> {
+        let (level, name) = sys::TCP_CONGESTION.ok_or_else(|| {
+            Error::not_supported().context("congestion control is not supported on this platform")
+        })?;
+        // The kernel would quietly truncate the name at an embedded NUL or
+        // at its maximum length, possibly selecting some other algorithm.
+        if algo.is_empty() || algo.len() >= TCP_CA_NAME_MAX || algo.contains('\0') {
+            return Err(Error::invalid_argument()
+                .context(format!("invalid congestion control algorithm {:?}", algo)));
+        }
+        sys::set_sockopt(&self.inner, level, name, algo.as_bytes()).map_err(|e| {
+            if e.downcast_ref() == Some(&Errno::Noent) {
+                e.context(format!(
+                    "congestion control algorithm {:?} is not available",
+                    algo
+                ))
+            } else {
+                e
+            }
+        })
+    }
+
+    /// The name of the connection's congestion control algorithm. Only
+    /// supported on Linux.
+    pub fn congestion_control(&self) -> Result<String, Error> {
+        let (level, name) = sys::TCP_CONGESTION.ok_or_else(|| {
+            Error::not_supported().context("congestion control is not supported on this platform")
+        })?;
+        let mut buf = [0; TCP_CA_NAME_MAX];
+        let len = sys::get_sockopt(&self.inner, level, name, &mut buf)?;
+        let buf = &buf[..len];
+        let end = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+        String::from_utf8(buf[..end].to_vec()).map_err(|_| Error::illegal_byte_sequence())
+    }
+
+    /// The CPU the connection's packets were last received on, for routing
+    /// an accepted connection to the worker on that CPU. `None` if nothing
+    /// has been received yet.
+    ///
+    /// This is `SO_INCOMING_CPU`, and only supported on Linux.
+    pub fn incoming_cpu(&self) -> Result<Option<i32>, Error> {
+        let (level, name) = incoming_cpu_option()?;
+        let mut buf = [0; 4];
+        sys::get_sockopt(&self.inner, level, name, &mut buf)?;
+        let cpu = i32::from_ne_bytes(buf);
+        Ok(if cpu < 0 { None } else { Some(cpu) })
+    }
+
+    /// Set the mark the kernel attaches to the connection's packets, for
+    /// policy routing and firewall rules to match on.
+    ///
+    /// This is `SO_MARK`, and only supported on Linux. It needs
+    /// `CAP_NET_ADMIN`, and fails with `EPERM` without it. Guests can't set
+    /// the mark themselves through [`SocketControl::set_sockopt`]; this is for
+    /// the host to classify a guest's traffic.
+    pub fn set_mark(&self, mark: u32) -> Result<(), Error> {
+        let (level, name) = sys::SO_MARK.ok_or_else(|| {
+            Error::not_supported().context("socket marks are not supported on this platform")
+        })?;
+        sys::set_sockopt(&self.inner, level, name, &mark.to_ne_bytes())
+            .map_err(|e| permission_context(e, "setting a socket mark needs CAP_NET_ADMIN"))
+    }
+
+    /// Set the priority of the connection's packets, which selects the queue
+    /// they go out on under the host's traffic control setup.
+    ///
+    /// This is `SO_PRIORITY`, and only supported on Linux. Priorities 0 to 6
+    /// can be set freely; higher ones need `CAP_NET_ADMIN`, and fail with
+    /// `EPERM` without it.
+    pub fn set_priority(&self, priority: u32) -> Result<(), Error> {
+        let (level, name) = sys::SO_PRIORITY.ok_or_else(|| {
+            Error::not_supported().context("socket priorities are not supported on this platform")
+        })?;
+        sys::set_sockopt(&self.inner, level, name, &priority.to_ne_bytes())
+            .map_err(|e| permission_context(e, "socket priorities above 6 need CAP_NET_ADMIN"))
+    }
+
+    /// Forcibly close the connection once data it sent has gone
+    /// unacknowledged for `timeout`, for a guest which needs to notice a dead
+    /// peer and fail over sooner than retransmission would give up on its
+    /// own. Unlike keepalive, this applies while there's data in flight.
+    /// Zero restores the system default.
+    ///
+    /// This is `TCP_USER_TIMEOUT`, which has millisecond granularity, and
+    /// only supported on Linux. A timeout too long for it fails with
+    /// `EINVAL`.
+    pub fn set_user_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let (level, name) = user_timeout_option()?;
+        let ms = u32::try_from(timeout.as_millis())
+            .map_err(|_| Error::invalid_argument().context("TCP user timeout is too long"))?;
+        sys::set_sockopt(&self.inner, level, name, &ms.to_ne_bytes())
+    }
+
+    /// The connection's TCP user timeout, as set by
+    /// [`TcpStream::set_user_timeout`]; zero means the system default. Only
+    /// supported on Linux.
+    pub fn user_timeout(&self) -> Result<Duration, Error> {
+        let (level, name) = user_timeout_option()?;
+        let mut buf = [0; 4];
+        sys::get_sockopt(&self.inner, level, name, &mut buf)?;
+        Ok(Duration::from_millis(u32::from_ne_bytes(buf).into()))
+    }
+
+    /// An estimate of how many bytes can be written without blocking, for a
+    /// writer sizing its writes to avoid `EAGAIN`.
+    ///
+    /// This is the send buffer's size, `SO_SNDBUF`, less what's queued in
+    /// it, `SIOCOUTQ`, and only supported on Linux. The kernel doubles
+    /// `SO_SNDBUF` to leave room for its own bookkeeping, so only half of it
+    /// is counted: the estimate errs low, and a write of that size should go
+    /// through, but a larger one may too.
+    pub fn send_buffer_available(&self) -> Result<usize, Error> {
+        sys::send_buffer_available(&self.inner)
+    }
+
+    /// The path MTU the kernel has found for the connection's peer, for
+    /// sizing writes so the segments don't need fragmenting.
+    ///
+    /// This is `IP_MTU`, or `IPV6_MTU` for an IPv6 connection, and only
+    /// supported on Linux.
+    pub fn path_mtu(&self) -> Result<u32, Error> {
+        let (level, mtu, _) = self.mtu_options()?;
+        let mut buf = [0; 4];
+        sys::get_sockopt(&self.inner, level, mtu, &mut buf)?;
+        Ok(i32::from_ne_bytes(buf).max(0) as u32)
+    }
+
+    /// Choose how the connection discovers the path MTU, and whether its
+    /// packets may be fragmented.
+    ///
+    /// This is `IP_MTU_DISCOVER`, or `IPV6_MTU_DISCOVER` for an IPv6
+    /// connection, and only supported on Linux.
+    pub fn set_mtu_discover(&self, mode: MtuDiscover) -> Result<(), Error> {
+        let (level, _, discover) = self.mtu_options()?;
+        sys::set_sockopt(
+            &self.inner,
+            level,
+            discover,
+            &sys::mtu_discover_value(mode).to_ne_bytes(),
+        )
+    }
+
+    // The level, and the MTU and MTU discovery options, for the connection's
+    // address family.
+    fn mtu_options(&self) -> Result<(i32, i32, i32), Error> {
+        let options = if self.local_addr()?.is_ipv6() {
+            sys::IPV6_MTU_OPTIONS
+        } else {
+            sys::IP_MTU_OPTIONS
+        };
+        options.ok_or_else(|| {
+            Error::not_supported().context("path MTU control is only available on Linux")
+        })
+    }
+}
+
+fn recv_lowat_option() -> Result<(i32, i32), Error> {
+    sys::SO_RCVLOWAT.ok_or_else(|| {
+        Error::not_supported().context("SO_RCVLOWAT is not supported on this platform")
+    })
+}
+
+fn user_timeout_option() -> Result<(i32, i32), Error> {
+    sys::TCP_USER_TIMEOUT.ok_or_else(|| {
+        Error::not_supported().context("TCP_USER_TIMEOUT is only available on Linux")
+    })
+}
+
+fn incoming_cpu_option() -> Result<(i32, i32), Error> {
+    sys::SO_INCOMING_CPU
+        .ok_or_else(|| Error::not_supported().context("SO_INCOMING_CPU is only available on Linux"))
+}
+
+fn permission_context(e: Error, why: &'static str) -> Error {
+    if e.downcast_ref() == Some(&Errno::Perm) {
+        e.context(why)
+    } else {
+        e
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::{Error, ErrorExt};
+
+    // The options `SocketControl` passes through. Each takes a plain `int`,
+    // and only affects how this socket behaves. Anything else could hand the
+    // kernel a pointer into host memory or a host descriptor, as the filter
+    // attaching options do, bypass the routing and filtering the host has
+    // set up, or lend a guest the host's privileges, so it's refused rather
+    // than vetted one by one.
+    const ALLOWED: &[(libc::c_int, libc::c_int)] = &[
+        (libc::SOL_SOCKET, libc::SO_ERROR),
+        (libc::SOL_SOCKET, libc::SO_KEEPALIVE),
+        (libc::SOL_SOCKET, libc::SO_OOBINLINE),
+        (libc::SOL_SOCKET, libc::SO_RCVBUF),
+        (libc::SOL_SOCKET, libc::SO_RCVLOWAT),
+        (libc::SOL_SOCKET, libc::SO_REUSEADDR),
+        (libc::SOL_SOCKET, libc::SO_SNDBUF),
+        (libc::SOL_SOCKET, libc::SO_SNDLOWAT),
+        (libc::SOL_SOCKET, libc::SO_TYPE),
+        (libc::IPPROTO_TCP, libc::TCP_NODELAY),
+        (libc::IPPROTO_IP, libc::IP_TOS),
+        (libc::IPPROTO_IP, libc::IP_TTL),
+        (libc::IPPROTO_IPV6, libc::IPV6_UNICAST_HOPS),
+        (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY),
+    ];
+    #[cfg(target_os = "linux")]
+    const ALLOWED_HERE: &[(libc::c_int, libc::c_int)] = &[
+        (libc::SOL_SOCKET, libc::SO_ACCEPTCONN),
+        (libc::SOL_SOCKET, libc::SO_INCOMING_CPU),
+        (libc::IPPROTO_TCP, libc::TCP_CORK),
+        (libc::IPPROTO_TCP, libc::TCP_KEEPCNT),
+        (libc::IPPROTO_TCP, libc::TCP_KEEPIDLE),
+        (libc::IPPROTO_TCP, libc::TCP_KEEPINTVL),
+        (libc::IPPROTO_TCP, libc::TCP_MAXSEG),
+        (libc::IPPROTO_TCP, libc::TCP_NOTSENT_LOWAT),
+        (libc::IPPROTO_TCP, libc::TCP_QUICKACK),
+        (libc::IPPROTO_TCP, libc::TCP_USER_TIMEOUT),
+        (libc::IPPROTO_IP, libc::IP_MTU),
+        (libc::IPPROTO_IP, libc::IP_MTU_DISCOVER),
+        (libc::IPPROTO_IPV6, libc::IPV6_MTU),
+        (libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER),
+        (libc::IPPROTO_IPV6, libc::IPV6_TCLASS),
+    ];
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    const ALLOWED_HERE: &[(libc::c_int, libc::c_int)] = &[(libc::IPPROTO_TCP, libc::TCP_NOPUSH)];
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd"
+    )))]
+    const ALLOWED_HERE: &[(libc::c_int, libc::c_int)] = &[];
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(super) const TCP_CORK: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::IPPROTO_TCP, libc::TCP_CORK));
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    pub(super) const TCP_CORK: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::IPPROTO_TCP, libc::TCP_NOPUSH));
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd"
+    )))]
+    pub(super) const TCP_CORK: Option<(libc::c_int, libc::c_int)> = None;
+
+    pub(super) const SO_RCVLOWAT: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::SOL_SOCKET, libc::SO_RCVLOWAT));
+
+    #[cfg(target_os = "linux")]
+    pub(super) const TCP_QUICKACK: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::IPPROTO_TCP, libc::TCP_QUICKACK));
+    #[cfg(not(target_os = "linux"))]
+    pub(super) const TCP_QUICKACK: Option<(libc::c_int, libc::c_int)> = None;
+
+    #[cfg(target_os = "linux")]
+    pub(super) const TCP_CONGESTION: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::IPPROTO_TCP, libc::TCP_CONGESTION));
+    #[cfg(not(target_os = "linux"))]
+    pub(super) const TCP_CONGESTION: Option<(libc::c_int, libc::c_int)> = None;
+
+    #[cfg(target_os = "linux")]
+    pub(super) const IP_MTU_OPTIONS: Option<(libc::c_int, libc::c_int, libc::c_int)> =
+        Some((libc::IPPROTO_IP, libc::IP_MTU, libc::IP_MTU_DISCOVER));
+    #[cfg(target_os = "linux")]
+    pub(super) const IPV6_MTU_OPTIONS: Option<(libc::c_int, libc::c_int, libc::c_int)> =
+        Some((libc::IPPROTO_IPV6, libc::IPV6_MTU, libc::IPV6_MTU_DISCOVER));
+    #[cfg(not(target_os = "linux"))]
+    pub(super) const IP_MTU_OPTIONS: Option<(libc::c_int, libc::c_int, libc::c_int)> = None;
+    #[cfg(not(target_os = "linux"))]
+    pub(super) const IPV6_MTU_OPTIONS: Option<(libc::c_int, libc::c_int, libc::c_int)> = None;
+
+    // The `IPV6_PMTUDISC_*` values are the same as these.
+    #[cfg(target_os = "linux")]
+    pub(super) fn mtu_discover_value(mode: super::MtuDiscover) -> libc::c_int {
+        match mode {
+            super::MtuDiscover::Do => libc::IP_PMTUDISC_DO,
+            super::MtuDiscover::Dont => libc::IP_PMTUDISC_DONT,
+            super::MtuDiscover::Want => libc::IP_PMTUDISC_WANT,
+            super::MtuDiscover::Probe => libc::IP_PMTUDISC_PROBE,
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn mtu_discover_value(_mode: super::MtuDiscover) -> libc::c_int {
+        unreachable!("path MTU control is only available on Linux")
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(super) const SO_MARK: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::SOL_SOCKET, libc::SO_MARK));
+    #[cfg(not(target_os = "linux"))]
+    pub(super) const SO_MARK: Option<(libc::c_int, libc::c_int)> = None;
+
+    #[cfg(target_os = "linux")]
+    pub(super) const SO_PRIORITY: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::SOL_SOCKET, libc::SO_PRIORITY));
+    #[cfg(not(target_os = "linux"))]
+    pub(super) const SO_PRIORITY: Option<(libc::c_int, libc::c_int)> = None;
+
+    #[cfg(target_os = "linux")]
+    pub(super) const TCP_USER_TIMEOUT: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::IPPROTO_TCP, libc::TCP_USER_TIMEOUT));
+    #[cfg(not(target_os = "linux"))]
+    pub(super) const TCP_USER_TIMEOUT: Option<(libc::c_int, libc::c_int)> = None;
+
+    #[cfg(target_os = "linux")]
+    pub(super) const SO_INCOMING_CPU: Option<(libc::c_int, libc::c_int)> =
+        Some((libc::SOL_SOCKET, libc::SO_INCOMING_CPU));
+    #[cfg(not(target_os = "linux"))]
+    pub(super) const SO_INCOMING_CPU: Option<(libc::c_int, libc::c_int)> = None;
+
+    pub(super) fn check_allowed(level: i32, name: i32) -> Result<(), Error> {
+        if !ALLOWED.contains(&(level, name)) && !ALLOWED_HERE.contains(&(level, name)) {
+            return Err(Error::perm().context("socket option is not permitted"));
+        }
+        Ok(())
+    }
+
+    pub(super) fn get_sockopt(
+        fd: impl AsFd,
+        level: i32,
+        name: i32,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut len = buf.len() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd.as_fd().as_raw_fd(),
+                level,
+                name,
+                buf.as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // The kernel reports the option's full size even if it truncated it.
+        Ok((len as usize).min(buf.len()))
+    }
+
+    // On a listening socket, Linux reports the accept queue's length as
+    // `tcpi_unacked` and its limit as `tcpi_sacked`.
+    #[cfg(target_os = "linux")]
+    pub(super) fn accept_queue_len(fd: impl AsFd) -> Result<usize, Error> {
+        let mut info = unsafe { std::mem::zeroed::<libc::tcp_info>() };
+        let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd.as_fd().as_raw_fd(),
+                libc::IPPROTO_TCP,
+                libc::TCP_INFO,
+                (&mut info as *mut libc::tcp_info).cast(),
+                &mut len,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(info.tcpi_unacked as usize)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn accept_queue_len(_fd: impl AsFd) -> Result<usize, Error> {
+        Err(Error::not_supported().context("the accept queue length is only available on Linux"))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(super) fn send_buffer_available(fd: impl AsFd) -> Result<usize, Error> {
+        let mut sndbuf = [0; 4];
+        get_sockopt(&fd, libc::SOL_SOCKET, libc::SO_SNDBUF, &mut sndbuf)?;
+        let sndbuf = i32::from_ne_bytes(sndbuf).max(0) as usize;
+        let mut queued: libc::c_int = 0;
+        // `SIOCOUTQ` is the same request as `TIOCOUTQ`.
+        let ret = unsafe { libc::ioctl(fd.as_fd().as_raw_fd(), libc::TIOCOUTQ, &mut queued) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok((sndbuf / 2).saturating_sub(queued.max(0) as usize))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn send_buffer_available(_fd: impl AsFd) -> Result<usize, Error> {
+        Err(Error::not_supported().context("send buffer space is only available on Linux"))
+    }
+
+    // Linux's `BPF_MAXINSNS`, the longest program a socket takes.
+    #[cfg(target_os = "linux")]
+    const BPF_MAXINSNS: usize = 4096;
+
+    #[cfg(target_os = "linux")]
+    pub(super) fn attach_filter(fd: impl AsFd, program: &[super::SockFilter]) -> Result<(), Error> {
+        if program.is_empty() || program.len() > BPF_MAXINSNS {
+            return Err(Error::invalid_argument()
+                .context("socket filters must have from 1 to 4096 instructions"));
+        }
+        let prog = libc::sock_fprog {
+            len: program.len() as libc::c_ushort,
+            // `SockFilter` has the same layout, and the kernel only reads
+            // the program.
+            filter: program.as_ptr() as *mut libc::sock_filter,
+        };
+        let ret = unsafe {
+            libc::setsockopt(
+                fd.as_fd().as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ATTACH_FILTER,
+                (&prog as *const libc::sock_fprog).cast(),
+                std::mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(super) fn detach_filter(fd: impl AsFd) -> Result<(), Error> {
+        // The kernel ignores the value, but wants one.
+        set_sockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_DETACH_FILTER,
+            &0i32.to_ne_bytes(),
+        )
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn attach_filter(
+        _fd: impl AsFd,
+        _program: &[super::SockFilter],
+    ) -> Result<(), Error> {
+        Err(Error::not_supported().context("socket filters are only available on Linux"))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn detach_filter(_fd: impl AsFd) -> Result<(), Error> {
+        Err(Error::not_supported().context("socket filters are only available on Linux"))
+    }
+
+    pub(super) fn set_sockopt(
+        fd: impl AsFd,
+        level: i32,
+        name: i32,
+        value: &[u8],
+    ) -> Result<(), Error> {
+        let ret = unsafe {
+            libc::setsockopt(
+                fd.as_fd().as_raw_fd(),
+                level,
+                name,
+                value.as_ptr().cast(),
+                value.len() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use io_lifetimes::AsSocket;
+    use wasi_common::{Error, ErrorExt};
+
+    pub(super) const TCP_CORK: Option<(i32, i32)> = None;
+    pub(super) const TCP_QUICKACK: Option<(i32, i32)> = None;
+    pub(super) const SO_RCVLOWAT: Option<(i32, i32)> = None;
+    pub(super) const TCP_CONGESTION: Option<(i32, i32)> = None;
+    pub(super) const SO_MARK: Option<(i32, i32)> = None;
+    pub(super) const SO_PRIORITY: Option<(i32, i32)> = None;
+    pub(super) const TCP_USER_TIMEOUT: Option<(i32, i32)> = None;
+    pub(super) const SO_INCOMING_CPU: Option<(i32, i32)> = None;
+    pub(super) const IP_MTU_OPTIONS: Option<(i32, i32, i32)> = None;
+    pub(super) const IPV6_MTU_OPTIONS: Option<(i32, i32, i32)> = None;
+
+    pub(super) fn mtu_discover_value(_mode: super::MtuDiscover) -> i32 {
+        unreachable!("path MTU control is only available on Linux")
+    }
+
+    pub(super) fn check_allowed(_level: i32, _name: i32) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub(super) fn get_sockopt(
+        _socket: impl AsSocket,
+        _level: i32,
+        _name: i32,
+        _buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        Err(Error::not_supported().context("raw socket options are not supported on Windows"))
+    }
+
+    pub(super) fn accept_queue_len(_socket: impl AsSocket) -> Result<usize, Error> {
+        Err(Error::not_supported().context("the accept queue length is only available on Linux"))
+    }
+
+    pub(super) fn send_buffer_available(_socket: impl AsSocket) -> Result<usize, Error> {
+        Err(Error::not_supported().context("send buffer space is only available on Linux"))
+    }
+
+    pub(super) fn attach_filter(
+        _socket: impl AsSocket,
+        _program: &[super::SockFilter],
+    ) -> Result<(), Error> {
+        Err(Error::not_supported().context("socket filters are only available on Linux"))
+    }
+
+    pub(super) fn detach_filter(_socket: impl AsSocket) -> Result<(), Error> {
+        Err(Error::not_supported().context("socket filters are only available on Linux"))
+    }
+
+    pub(super) fn set_sockopt(
+        _socket: impl AsSocket,
+        _level: i32,
+        _name: i32,
+        _value: &[u8],
+    ) -> Result<(), Error> {
+        Err(Error::not_supported().context("raw socket options are not supported on Windows"))
+    }
+}
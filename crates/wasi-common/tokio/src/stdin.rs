@@ -0,0 +1,484 @@
+use crate::block_on_dummy_executor;
+#[cfg(windows)]
+use io_extras::os::windows::{AsRawHandleOrSocket, RawHandleOrSocket};
+#[cfg(not(windows))]
+use io_lifetimes::AsFd;
+use std::any::Any;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(windows))]
+use std::sync::Arc;
+use std::sync::Mutex;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, WasiFile},
+    Error,
+};
+
+/// The host process's stdin.
+///
+/// How to wait for input depends on what stdin turns out to be, which is
+/// checked once, when it's created. Pipes, sockets, and terminals are
+/// registered with the runtime's reactor, which reports a pipe whose writer
+/// has gone away as readable so that the guest sees EOF as soon as it
+/// happens. Anything the reactor can't watch, such as a regular file or
+/// `/dev/null`, or a terminal on macOS where kqueue doesn't support them, is
+/// waited on with `poll(2)` on a blocking thread instead. That thread wakes
+/// up every so often to check whether anyone is still waiting, so a wait the
+/// guest abandons gives its thread back to the runtime's blocking pool.
+///
+/// `peek` works even when stdin is a pipe, by reading ahead into a buffer
+/// which subsequent reads drain before reading any more from stdin, so a
+/// guest can sniff its input's format without losing any of it. A peek for
+/// more bytes than have been read ahead waits for more input, or EOF.
+///
+/// Once a read has returned EOF, whether because a pipe's writer closed it
+/// or because the user typed the EOF character at a terminal, stdin stays at
+/// EOF, as C's stdio does: later reads return 0 without touching stdin, and
+/// `readable` is immediately ready, so a guest polling stdin is woken to see
+/// the EOF rather than waiting for input which will never come.
+pub struct Stdin {
+    inner: wasi_cap_std_sync::stdio::Stdin,
+    peeked: PeekBuffer,
+    eof: AtomicBool,
+    #[cfg(not(windows))]
+    readiness: Readiness,
+}
+
+pub fn stdin() -> Stdin {
+    let inner = wasi_cap_std_sync::stdio::stdin();
+    Stdin {
+        #[cfg(not(windows))]
+        readiness: Readiness::detect(inner.as_fd()),
+        inner,
+        peeked: PeekBuffer::default(),
+        eof: AtomicBool::new(false),
+    }
+}
+
+impl Stdin {
+    // Read from stdin itself, bypassing the peek buffer, and latch EOF.
+    fn read_inner(&self, bufs: &mut [io::IoSliceMut<'_>]) -> Result<u64, Error> {
+        if self.eof.load(Ordering::Acquire) {
+            return Ok(0);
+        }
+        let wanted = bufs.iter().any(|b| !b.is_empty());
+        let n = block_on_dummy_executor(move || self.inner.read_vectored(bufs))?;
+        if n == 0 && wanted {
+            self.eof.store(true, Ordering::Release);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(not(windows))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Readiness {
+    Reactor,
+    Blocking,
+}
+
+#[cfg(not(windows))]
+impl Readiness {
+    fn detect(fd: rustix::fd::BorrowedFd<'_>) -> Self {
+        use rustix::fs::FileType;
+        use std::os::unix::io::AsRawFd;
+        let file_type = match rustix::fs::fstat(fd) {
+            Ok(stat) => FileType::from_raw_mode(stat.st_mode),
+            Err(_) => return Readiness::Blocking,
+        };
+        match file_type {
+            FileType::Fifo | FileType::Socket => Readiness::Reactor,
+            FileType::CharacterDevice
+                if cfg!(not(target_os = "macos"))
+                    && unsafe { libc::isatty(fd.as_raw_fd()) } == 1 =>
+            {
+                Readiness::Reactor
+            }
+            _ => Readiness::Blocking,
+        }
+    }
+
+    async fn wait(self, fd: rustix::fd::BorrowedFd<'_>) -> Result<(), Error> {
+        match self {
+            Readiness::Reactor => crate::file::wait_readable(fd).await,
+            Readiness::Blocking => {
+                use std::os::unix::io::AsRawFd;
+                // The thread can't borrow `fd`, but stdin stays open for as
+                // long as the process does.
+                let raw = fd.as_raw_fd();
+                let abandoned = Arc::new(AtomicBool::new(false));
+                let _abandon = AbandonOnDrop(abandoned.clone());
+                tokio::task::spawn_blocking(move || poll_readable(raw, &abandoned))
+                    .await
+                    .map_err(io::Error::from)?
+            }
+        }
+    }
+}
+
+// How long a blocking poll waits before checking whether it's been abandoned.
+#[cfg(not(windows))]
+const POLL_INTERVAL_MS: libc::c_int = 100;
+
+// Tells a blocking poll to give up once the future waiting on it is dropped.
+#[cfg(not(windows))]
+struct AbandonOnDrop(Arc<AtomicBool>);
+
+#[cfg(not(windows))]
+impl Drop for AbandonOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+// Wait until `fd` can be read without blocking, which includes it being at
+// EOF or in an error state, or until `abandoned` is set, when nobody is left
+// to care which.
+#[cfg(not(windows))]
+fn poll_readable(fd: std::os::unix::io::RawFd, abandoned: &AtomicBool) -> Result<(), Error> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    while !abandoned.load(Ordering::Acquire) {
+        match unsafe { libc::poll(&mut pollfd, 1, POLL_INTERVAL_MS) } {
+            0 => continue,
+            n if n > 0 => return Ok(()),
+            _ => {}
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
+
+// Bytes read from stdin by `peek` which the guest hasn't read yet.
+#[derive(Default)]
+struct PeekBuffer(Mutex<Vec<u8>>);
+
+impl PeekBuffer {
+    // Fill `buf` from the buffer, first topping it up with a single call to
+    // `read` if it holds less than `buf` asks for.
+    fn peek(
+        &self,
+        buf: &mut [u8],
+        read: impl FnOnce(&mut [u8]) -> Result<u64, Error>,
+    ) -> Result<u64, Error> {
+        let mut peeked = self.0.lock().unwrap();
+        let have = peeked.len();
+        if have < buf.len() {
+            peeked.resize(buf.len(), 0);
+            let n = read(&mut peeked[have..]);
+            // Keep what was already buffered even if the read failed.
+            peeked.truncate(have + n.as_ref().map_or(0, |&n| n as usize));
+            n?;
+        }
+        let n = buf.len().min(peeked.len());
+        buf[..n].copy_from_slice(&peeked[..n]);
+        Ok(n as u64)
+    }
+
+    // Move buffered bytes into `bufs`, or return `None` if there are none.
+    fn take(&self, bufs: &mut [io::IoSliceMut<'_>]) -> Option<u64> {
+        let mut peeked = self.0.lock().unwrap();
+        if peeked.is_empty() {
+            return None;
+        }
+        let mut n = 0;
+        for buf in bufs {
+            let len = buf.len().min(peeked.len() - n);
+            buf[..len].copy_from_slice(&peeked[n..n + len]);
+            n += len;
+        }
+        peeked.drain(..n);
+        Some(n as u64)
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Stdin {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        Some(self.inner.as_fd())
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        Some(self.inner.as_raw_handle_or_socket())
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        block_on_dummy_executor(|| self.inner.datasync())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        block_on_dummy_executor(|| self.inner.sync())
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        block_on_dummy_executor(|| self.inner.get_filetype())
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        block_on_dummy_executor(|| self.inner.get_fdflags())
+    }
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        block_on_dummy_executor(|| self.inner.set_fdflags(fdflags))
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        block_on_dummy_executor(|| self.inner.get_filestat())
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        block_on_dummy_executor(move || self.inner.set_filestat_size(size))
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        block_on_dummy_executor(move || self.inner.advise(offset, len, advice))
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        block_on_dummy_executor(move || self.inner.allocate(offset, len))
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        if let Some(n) = self.peeked.take(bufs) {
+            return Ok(n);
+        }
+        self.read_inner(bufs)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        block_on_dummy_executor(move || self.inner.read_vectored_at(bufs, offset))
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        block_on_dummy_executor(move || self.inner.write_vectored(bufs))
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        block_on_dummy_executor(move || self.inner.write_vectored_at(bufs, offset))
+    }
+    async fn seek(&self, pos: std::io::SeekFrom) -> Result<u64, Error> {
+        block_on_dummy_executor(move || self.inner.seek(pos))
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.peeked.peek(buf, |chunk| {
+            self.read_inner(&mut [io::IoSliceMut::new(chunk)])
+        })
+    }
+    async fn set_times(
+        &self,
+        atime: Option<wasi_common::SystemTimeSpec>,
+        mtime: Option<wasi_common::SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        block_on_dummy_executor(move || self.inner.set_times(atime, mtime))
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self.peeked.len() as u64 + self.inner.num_ready_bytes()?)
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+
+    #[cfg(not(windows))]
+    async fn readable(&self) -> Result<(), Error> {
+        if self.peeked.len() > 0 || self.eof.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        self.readiness.wait(self.inner.as_fd()).await
+    }
+
+    #[cfg(not(windows))]
+    async fn writable(&self) -> Result<(), Error> {
+        crate::file::wait_writable(self.inner.as_fd()).await
+    }
+}
+
+#[cfg(windows)]
+impl AsRawHandleOrSocket for Stdin {
+    #[inline]
+    fn as_raw_handle_or_socket(&self) -> RawHandleOrSocket {
+        self.inner.as_raw_handle_or_socket()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::{poll_readable, PeekBuffer, Readiness, Stdin};
+    use io_lifetimes::{AsFd, OwnedFd};
+    use std::io::{IoSliceMut, Write};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use wasi_common::{Error, WasiFile};
+
+    fn pipe() -> (std::fs::File, std::fs::File) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [r, w] = fds.map(|fd| unsafe { FromRawFd::from_raw_fd(fd) });
+        (r, w)
+    }
+
+    async fn wait(readiness: Readiness, fd: &impl AsFd) {
+        tokio::time::timeout(Duration::from_secs(5), readiness.wait(fd.as_fd()))
+            .await
+            .expect("readiness reported promptly")
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pipe_reports_data_and_eof() {
+        for readiness in [Readiness::Reactor, Readiness::Blocking] {
+            let (r, mut w) = pipe();
+            assert_eq!(Readiness::detect(r.as_fd()), Readiness::Reactor);
+            w.write_all(b"x").unwrap();
+            wait(readiness, &r).await;
+
+            // The writer closing, with nothing left to read, is EOF.
+            let (r, w) = pipe();
+            drop(w);
+            wait(readiness, &r).await;
+        }
+    }
+
+    // Nothing ever arrives on the pipe, but the poll still returns once
+    // nobody is waiting for it.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn abandoned_poll_gives_up() {
+        let (r, _w) = pipe();
+        let fd = r.as_raw_fd();
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let poll = tokio::task::spawn_blocking({
+            let abandoned = abandoned.clone();
+            move || poll_readable(fd, &abandoned)
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        abandoned.store(true, Ordering::Release);
+        tokio::time::timeout(Duration::from_secs(5), poll)
+            .await
+            .expect("abandoned poll returns promptly")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tty_uses_the_reactor_except_on_macos() {
+        let fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        if fd < 0 {
+            // No pseudo-terminals available, as in some containers.
+            return;
+        }
+        let tty = unsafe { OwnedFd::from_raw_fd(fd) };
+        let expected = if cfg!(target_os = "macos") {
+            Readiness::Blocking
+        } else {
+            Readiness::Reactor
+        };
+        assert_eq!(Readiness::detect(tty.as_fd()), expected);
+    }
+
+    #[test]
+    fn peeked_bytes_are_read_again() {
+        use std::io::Read;
+        let (mut r, mut w) = pipe();
+        w.write_all(b"\x1f\x8b\x08\x00rest").unwrap();
+        drop(w);
+        let peeked = PeekBuffer::default();
+        let mut read = |chunk: &mut [u8]| -> Result<u64, Error> { Ok(r.read(chunk)? as u64) };
+
+        let mut magic = [0; 4];
+        assert_eq!(peeked.peek(&mut magic, &mut read).unwrap(), 4);
+        assert_eq!(magic, *b"\x1f\x8b\x08\x00");
+        assert_eq!(peeked.peek(&mut magic[..2], &mut read).unwrap(), 2);
+
+        let mut buf = [0; 16];
+        let n = peeked.take(&mut [IoSliceMut::new(&mut buf)]).unwrap();
+        assert_eq!(&buf[..n as usize], b"\x1f\x8b\x08\x00");
+        assert_eq!(peeked.take(&mut [IoSliceMut::new(&mut buf)]), None);
+        let n = read(&mut buf).unwrap();
+        assert_eq!(&buf[..n as usize], b"rest");
+    }
+
+    // Points the process's stdin at another descriptor until dropped.
+    struct Redirect(OwnedFd);
+
+    impl Redirect {
+        fn stdin_to(fd: impl AsFd) -> Self {
+            let saved = unsafe { libc::dup(0) };
+            assert!(saved >= 0);
+            assert_eq!(unsafe { libc::dup2(fd.as_fd().as_raw_fd(), 0) }, 0);
+            Redirect(unsafe { OwnedFd::from_raw_fd(saved) })
+        }
+    }
+
+    impl Drop for Redirect {
+        fn drop(&mut self) {
+            unsafe { libc::dup2(self.0.as_raw_fd(), 0) };
+        }
+    }
+
+    async fn assert_sticky_eof(stdin: &Stdin) {
+        for _ in 0..2 {
+            let mut buf = [0; 8];
+            let read = stdin.read_vectored(&mut [IoSliceMut::new(&mut buf)]);
+            let n = tokio::time::timeout(Duration::from_secs(5), read)
+                .await
+                .expect("EOF is reported promptly")
+                .unwrap();
+            assert_eq!(n, 0);
+            tokio::time::timeout(Duration::from_secs(5), stdin.readable())
+                .await
+                .expect("readable at EOF")
+                .unwrap();
+        }
+    }
+
+    // Both cases use the process's own stdin, so they can't run in parallel.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn eof_from_pipe_close_and_tty() {
+        let (r, w) = pipe();
+        {
+            let _redirect = Redirect::stdin_to(&r);
+            let stdin = super::stdin();
+            drop(w);
+            assert_sticky_eof(&stdin).await;
+        }
+
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        if master < 0 {
+            // No pseudo-terminals available, as in some containers.
+            return;
+        }
+        let master = unsafe { std::fs::File::from_raw_fd(master) };
+        let slave = unsafe {
+            assert_eq!(libc::grantpt(master.as_raw_fd()), 0);
+            assert_eq!(libc::unlockpt(master.as_raw_fd()), 0);
+            let name = libc::ptsname(master.as_raw_fd());
+            assert!(!name.is_null());
+            let fd = libc::open(name, libc::O_RDWR | libc::O_NOCTTY);
+            assert!(fd >= 0);
+            OwnedFd::from_raw_fd(fd)
+        };
+        let _redirect = Redirect::stdin_to(&slave);
+        let stdin = super::stdin();
+        assert!(stdin.isatty());
+        // The terminal's EOF character, Ctrl-D, at the start of a line.
+        (&master).write_all(b"\x04").unwrap();
+        assert_sticky_eof(&stdin).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn regular_file_is_always_ready() {
+        let f = tempfile::tempfile().unwrap();
+        assert_eq!(Readiness::detect(f.as_fd()), Readiness::Blocking);
+        wait(Readiness::Blocking, &f).await;
+    }
+}
@@ -1 +1,2 @@
-pub use crate::file::{stderr, stdin, stdout, Stderr, Stdin, Stdout};
+pub use crate::file::{ignore_sigpipe, stderr, stdout, Stderr, Stdout};
+pub use crate::stdin::{stdin, Stdin};
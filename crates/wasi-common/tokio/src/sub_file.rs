@@ -0,0 +1,238 @@
+use crate::file::seek_target;
+use std::any::Any;
+use std::io;
+use std::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error, ErrorExt,
+};
+
+/// A `WasiFile` wrapper which presents the byte range `[base, base + len)`
+/// of the inner file as a file of its own, for handing a guest one member
+/// of a larger archive without copying it out.
+///
+/// Offsets, seeks, and the size from `get_filestat` are all relative to the
+/// window, and reads stop at its end. The window keeps its own offset and
+/// only uses the inner file's positional reads and writes, so several
+/// windows can share one inner file.
+///
+/// Writes fail with `EBADF` unless enabled with [`SubFile::with_writes`].
+/// Even then they can't grow the window: a write is cut short at its end,
+/// and one starting at or past it fails with `EFBIG`.
+pub struct SubFile<F: WasiFile> {
+    inner: F,
+    base: u64,
+    len: u64,
+    writable: bool,
+    position: Mutex<u64>,
+}
+
+impl<F: WasiFile> SubFile<F> {
+    pub fn new(inner: F, base: u64, len: u64) -> Self {
+        SubFile {
+            inner,
+            base,
+            len,
+            writable: false,
+            position: Mutex::new(0),
+        }
+    }
+    /// Allow writes within the window.
+    pub fn with_writes(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        self
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    // The offset in the inner file of `offset` in the window.
+    fn inner_offset(&self, offset: u64) -> Result<u64, Error> {
+        self.base
+            .checked_add(offset)
+            .ok_or_else(|| Error::overflow().context("offset is past the largest file offset"))
+    }
+
+    async fn read_at(&self, bufs: &mut [io::IoSliceMut<'_>], offset: u64) -> Result<u64, Error> {
+        let remaining = self.len.saturating_sub(offset);
+        if remaining == 0 {
+            return Ok(0);
+        }
+        let mut left = usize::try_from(remaining).unwrap_or(usize::MAX);
+        let mut capped = Vec::with_capacity(bufs.len());
+        for buf in bufs.iter_mut() {
+            if left == 0 {
+                break;
+            }
+            let n = buf.len().min(left);
+            capped.push(io::IoSliceMut::new(&mut buf[..n]));
+            left -= n;
+        }
+        let inner_offset = self.inner_offset(offset)?;
+        self.inner.read_vectored_at(&mut capped, inner_offset).await
+    }
+
+    async fn write_at(&self, bufs: &[io::IoSlice<'_>], offset: u64) -> Result<u64, Error> {
+        if !self.writable {
+            return Err(Error::badf().context("sub-file is read-only"));
+        }
+        let remaining = self.len.saturating_sub(offset);
+        if bufs.iter().all(|b| b.is_empty()) {
+            return Ok(0);
+        }
+        if remaining == 0 {
+            return Err(Error::from(Errno::Fbig).context("write is past the end of the sub-file"));
+        }
+        let mut left = usize::try_from(remaining).unwrap_or(usize::MAX);
+        let mut capped = Vec::with_capacity(bufs.len());
+        for buf in bufs {
+            if left == 0 {
+                break;
+            }
+            let n = buf.len().min(left);
+            capped.push(io::IoSlice::new(&buf[..n]));
+            left -= n;
+        }
+        let inner_offset = self.inner_offset(offset)?;
+        self.inner.write_vectored_at(&capped, inner_offset).await
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for SubFile<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        let stat = self.inner.get_filestat().await?;
+        // An inner file which ends inside the window makes for a shorter one.
+        let size = stat.size.saturating_sub(self.base).min(self.len);
+        Ok(Filestat { size, ..stat })
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::not_supported().context("a sub-file's size is fixed"))
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.read_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.read_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.write_at(bufs, position).await?;
+        *self.position.lock().unwrap() = position + n;
+        Ok(n)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.write_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let current = *self.position.lock().unwrap();
+        let new = seek_target(pos, current, || async { Ok(self.len) }).await?;
+        *self.position.lock().unwrap() = new;
+        Ok(new)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SubFile;
+    use crate::BytesFile;
+    use std::io::{IoSlice, IoSliceMut, SeekFrom};
+    use std::sync::Arc;
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    fn window() -> SubFile<BytesFile> {
+        let archive = BytesFile::new(Arc::from(&b"header|member|trailer"[..]));
+        SubFile::new(archive, 7, 6)
+    }
+
+    async fn read(f: &SubFile<BytesFile>, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        let n = f
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        buf.truncate(n as usize);
+        buf
+    }
+
+    #[tokio::test]
+    async fn seeks_and_reads_stay_in_the_window() {
+        let f = window();
+        assert_eq!(f.get_filestat().await.unwrap().size, 6);
+        assert_eq!(read(&f, 3).await, b"mem");
+        assert_eq!(read(&f, 16).await, b"ber");
+        assert_eq!(read(&f, 16).await, b"");
+
+        assert_eq!(f.seek(SeekFrom::End(-4)).await.unwrap(), 2);
+        assert_eq!(read(&f, 2).await, b"mb");
+        assert_eq!(f.seek(SeekFrom::Start(10)).await.unwrap(), 10);
+        assert_eq!(read(&f, 2).await, b"");
+        let err = f
+            .seek(SeekFrom::Current(-11))
+            .await
+            .expect_err("before start");
+        assert_eq!(err.downcast().unwrap(), Errno::Inval);
+    }
+
+    #[tokio::test]
+    async fn positional_reads_past_the_window() {
+        let f = window();
+        let (mut a, mut b) = ([0; 2], [0; 8]);
+        let n = f
+            .read_vectored_at(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)], 3)
+            .await
+            .unwrap();
+        assert_eq!(n, 3);
+        assert_eq!((&a, &b[..1]), (b"be", &b"r"[..]));
+
+        for offset in [6, 7, u64::MAX] {
+            let n = f
+                .read_vectored_at(&mut [IoSliceMut::new(&mut a)], offset)
+                .await
+                .unwrap();
+            assert_eq!(n, 0);
+        }
+
+        let err = f
+            .write_vectored(&[IoSlice::new(b"x")])
+            .await
+            .expect_err("read-only");
+        assert_eq!(err.downcast().unwrap(), Errno::Badf);
+    }
+}
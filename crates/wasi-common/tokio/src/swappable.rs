@@ -0,0 +1,148 @@
+use std::any::Any;
+use std::io;
+use std::sync::{Arc, RwLock};
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+/// A handle a host keeps to replace the backing file of a [`Swappable`]
+/// once the guest holds it. Clones refer to the same file.
+pub struct SwapHandle<F>(Arc<RwLock<Arc<F>>>);
+
+impl<F> Clone for SwapHandle<F> {
+    fn clone(&self) -> Self {
+        SwapHandle(self.0.clone())
+    }
+}
+
+impl<F: WasiFile> SwapHandle<F> {
+    /// Make `inner` the backing file, returning the one it replaces. The old
+    /// file is shared with any operations still running on it until they
+    /// finish.
+    pub fn swap(&self, inner: F) -> Arc<F> {
+        std::mem::replace(&mut *self.0.write().unwrap(), Arc::new(inner))
+    }
+}
+
+/// A `WasiFile` wrapper whose backing file the host can replace through a
+/// [`SwapHandle`] while the guest holds it, for rotating a log the guest
+/// writes to stdout.
+///
+/// Each operation runs against the file which was current when it started,
+/// so one in flight during a swap finishes on the old file, and everything
+/// after goes to the new one. The guest isn't told: its offset and the like
+/// are simply the new file's from then on. Since the backing file is
+/// shared with the handle, `set_fdflags` fails with `ENOTSUP`.
+pub struct Swappable<F: WasiFile> {
+    current: SwapHandle<F>,
+}
+
+impl<F: WasiFile> Swappable<F> {
+    pub fn new(inner: F) -> Self {
+        Swappable {
+            current: SwapHandle(Arc::new(RwLock::new(Arc::new(inner)))),
+        }
+    }
+
+    pub fn handle(&self) -> SwapHandle<F> {
+        self.current.clone()
+    }
+
+    fn current(&self) -> Arc<F> {
+        self.current.0.read().unwrap().clone()
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static> WasiFile for Swappable<F> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.current().get_filetype().await
+    }
+    fn isatty(&self) -> bool {
+        self.current().isatty()
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.current().datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.current().sync().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.current().get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, _flags: FdFlags) -> Result<(), Error> {
+        Err(Error::not_supported().context("a swappable file's flags can't be changed"))
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.current().get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.current().set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.current().advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.current().allocate(offset, len).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.current().read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.current().read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.current().write_vectored(bufs).await
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.current().write_vectored_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        self.current().seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.current().peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.current().num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.current().readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.current().writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Swappable;
+    use crate::RingCapture;
+    use std::io::IoSlice;
+    use wasi_common::WasiFile;
+
+    #[tokio::test]
+    async fn writes_follow_the_swap() {
+        let (old, new) = (RingCapture::new(64), RingCapture::new(64));
+        let f = Swappable::new(old.clone());
+        f.write_vectored(&[IoSlice::new(b"before\n")])
+            .await
+            .unwrap();
+        f.handle().swap(new.clone());
+        f.write_vectored(&[IoSlice::new(b"after\n")]).await.unwrap();
+        assert_eq!(old.contents(), b"before\n");
+        assert_eq!(new.contents(), b"after\n");
+    }
+}
@@ -0,0 +1,43 @@
+use crate::file::File;
+use wasi_common::Error;
+
+/// Sync the data of every file in `files` concurrently, as a write barrier
+/// for a commit spanning several files: this returns only once every
+/// `fdatasync` has finished, and succeeds only if all of them did.
+///
+/// Each file is synced on its own thread, so the syncs overlap rather than
+/// queueing behind one another. If any of them fail, the error is that of the
+/// first failure, with context naming the index in `files` of every file
+/// which failed.
+pub async fn sync_all(files: &[&File]) -> Result<(), Error> {
+    let results = tokio::task::block_in_place(|| {
+        std::thread::scope(|s| {
+            let threads = files
+                .iter()
+                .map(|f| {
+                    s.spawn(move || {
+                        wiggle::run_in_dummy_executor(f.inner.datasync())
+                            .expect("wrapped operation should be synchronous")
+                    })
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|t| t.join().expect("datasync thread panicked"))
+                .collect::<Vec<_>>()
+        })
+    });
+
+    let mut failed = Vec::new();
+    let mut first_err = None;
+    for (i, result) in results.into_iter().enumerate() {
+        if let Err(e) = result {
+            failed.push(i);
+            first_err.get_or_insert(e);
+        }
+    }
+    match first_err {
+        None => Ok(()),
+        Some(e) => Err(e.context(format!("datasync failed for files at indices {:?}", failed))),
+    }
+}
@@ -0,0 +1,132 @@
+use crate::iovec::iovec_len;
+use bytes::Bytes;
+use std::any::Any;
+use std::io;
+use tokio::sync::mpsc;
+use wasi_common::{
+    file::{FileType, WasiFile},
+    snapshots::preview_1::types::Errno,
+    Error,
+};
+
+/// Which of a guest's output streams a chunk was written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StreamKind {
+    Stdout,
+    Stderr,
+}
+
+/// A `WasiFile` which sends each write, tagged with the stream it was made
+/// to, into a channel shared with the guest's other output stream. This
+/// captures stdout and stderr into a single log which preserves both the
+/// order the guest wrote them in and which stream each chunk came from.
+///
+/// Create a stdout and stderr pair with [`TaggedWriter::pair`]. Each write
+/// becomes one message, so a chunk is never split or merged with another.
+/// The channel's bound provides backpressure: a guest which gets `buffer`
+/// messages ahead of the host waits in its write. Once the receiver is
+/// dropped, writes fail with `EPIPE`.
+pub struct TaggedWriter {
+    kind: StreamKind,
+    tx: mpsc::Sender<(StreamKind, Bytes)>,
+}
+
+impl TaggedWriter {
+    /// Create writers to use as a guest's stdout and stderr, and the receiver
+    /// of their output.
+    pub fn pair(
+        buffer: usize,
+    ) -> (
+        TaggedWriter,
+        TaggedWriter,
+        mpsc::Receiver<(StreamKind, Bytes)>,
+    ) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let stdout = TaggedWriter {
+            kind: StreamKind::Stdout,
+            tx: tx.clone(),
+        };
+        let stderr = TaggedWriter {
+            kind: StreamKind::Stderr,
+            tx,
+        };
+        (stdout, stderr, rx)
+    }
+
+    pub fn kind(&self) -> StreamKind {
+        self.kind
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for TaggedWriter {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::Pipe)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let n = iovec_len(bufs.iter().map(|b| b.len()))?;
+        if n == 0 {
+            return Ok(0);
+        }
+        let mut chunk = Vec::with_capacity(n);
+        for b in bufs {
+            chunk.extend_from_slice(b);
+        }
+        self.tx
+            .send((self.kind, chunk.into()))
+            .await
+            .map_err(|_| Error::from(Errno::Pipe).context("tagged output receiver is gone"))?;
+        Ok(n as u64)
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StreamKind, TaggedWriter};
+    use std::io::IoSlice;
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    #[tokio::test]
+    async fn interleaved_writes_keep_order_and_tags() {
+        let (stdout, stderr, mut rx) = TaggedWriter::pair(8);
+        stdout
+            .write_vectored(&[IoSlice::new(b"one "), IoSlice::new(b"two")])
+            .await
+            .unwrap();
+        stderr
+            .write_vectored(&[IoSlice::new(b"oops")])
+            .await
+            .unwrap();
+        stdout
+            .write_vectored(&[IoSlice::new(b"three")])
+            .await
+            .unwrap();
+
+        let mut log = Vec::new();
+        for _ in 0..3 {
+            let (kind, bytes) = rx.recv().await.unwrap();
+            log.push((kind, bytes.to_vec()));
+        }
+        assert_eq!(
+            log,
+            [
+                (StreamKind::Stdout, b"one two".to_vec()),
+                (StreamKind::Stderr, b"oops".to_vec()),
+                (StreamKind::Stdout, b"three".to_vec()),
+            ]
+        );
+
+        drop(rx);
+        let err = stderr
+            .write_vectored(&[IoSlice::new(b"late")])
+            .await
+            .expect_err("receiver dropped");
+        assert_eq!(err.downcast().unwrap(), Errno::Pipe);
+    }
+}
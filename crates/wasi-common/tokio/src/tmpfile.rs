@@ -0,0 +1,82 @@
+use crate::file::File;
+use std::sync::atomic::{AtomicU64, Ordering};
+use wasi_common::Error;
+
+// Distinguishes fallback temporary files made by one process at the same
+// moment.
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+impl File {
+    /// Open a new, empty, read-write file in `dir` which has no name, so
+    /// nothing else can find it and it disappears once closed, even if the
+    /// host crashes.
+    ///
+    /// On Linux this is `O_TMPFILE`, and a file opened without `O_EXCL` could
+    /// be given a name later with `linkat`. Where `O_TMPFILE` isn't available,
+    /// on other platforms or on filesystems which don't support it, the file
+    /// is created with a unique name and then removed, leaving a brief window
+    /// in which it's visible in `dir`.
+    ///
+    /// The open happens on tokio's blocking thread pool.
+    pub async fn open_tmpfile(dir: &cap_std::fs::Dir) -> Result<File, Error> {
+        let dir = dir.try_clone()?;
+        let file = tokio::task::spawn_blocking(move || match sys::open_tmpfile(&dir)? {
+            Some(file) => Ok(file),
+            None => open_unlinked(&dir),
+        })
+        .await??;
+        Ok(File::from_cap_std(file))
+    }
+}
+
+fn open_unlinked(dir: &cap_std::fs::Dir) -> Result<cap_std::fs::File, Error> {
+    let name = format!(
+        ".tmpfile.{}.{}",
+        std::process::id(),
+        NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+    );
+    let file = dir.open_with(
+        &name,
+        cap_std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true),
+    )?;
+    dir.remove_file(&name)?;
+    Ok(file)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use rustix::fs::{Mode, OFlags};
+    use rustix::io::Errno;
+    use std::io;
+    use wasi_common::Error;
+
+    // `None` if the filesystem, or the kernel, doesn't support `O_TMPFILE`.
+    pub(super) fn open_tmpfile(dir: &cap_std::fs::Dir) -> Result<Option<cap_std::fs::File>, Error> {
+        match rustix::fs::openat(
+            dir,
+            ".",
+            OFlags::TMPFILE | OFlags::RDWR | OFlags::CLOEXEC,
+            Mode::from_bits_truncate(0o600),
+        ) {
+            Ok(fd) => Ok(Some(cap_std::fs::File::from_std(fd.into()))),
+            // Kernels before 3.11 don't know the flag, and take it for
+            // `O_DIRECTORY`, which fails on a directory opened for writing.
+            Err(Errno::OPNOTSUPP | Errno::ISDIR | Errno::INVAL) => Ok(None),
+            Err(e) => Err(io::Error::from(e).into()),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod sys {
+    use wasi_common::Error;
+
+    pub(super) fn open_tmpfile(
+        _dir: &cap_std::fs::Dir,
+    ) -> Result<Option<cap_std::fs::File>, Error> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,111 @@
+use crate::net::UnixStream;
+use wasi_common::{snapshots::preview_1::types::Errno, Error, WasiFile};
+
+/// The credentials of a process, as passed alongside a message on a Unix
+/// socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UCred {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl UnixStream {
+    /// Read into `buf`, along with the credentials sent with what was read,
+    /// for a protocol whose peer vouches for each message.
+    ///
+    /// This turns on `SO_PASSCRED`, and reads with `recvmsg`, picking out
+    /// the `SCM_CREDENTIALS` control message. Linux doesn't merge data sent
+    /// with different credentials, so one read never mixes two senders'.
+    /// With `SO_PASSCRED` on, the kernel attaches the sender's own
+    /// credentials to whatever it sends without any, so `None` only comes
+    /// back for data which was already queued when this was first called.
+    /// Only supported on Linux.
+    pub async fn recv_with_creds(&self, buf: &mut [u8]) -> Result<(u64, Option<UCred>), Error> {
+        loop {
+            match sys::recv_with_creds(&self.inner, buf) {
+                Err(e) if e.downcast_ref() == Some(&Errno::Again) => self.readable().await?,
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use super::UCred;
+    use io_lifetimes::AsFd;
+    use std::io;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+    use wasi_common::Error;
+
+    pub(super) fn recv_with_creds(
+        fd: &impl AsFd,
+        buf: &mut [u8],
+    ) -> Result<(u64, Option<UCred>), Error> {
+        let fd = fd.as_fd().as_raw_fd();
+        let one: libc::c_int = 1;
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_PASSCRED,
+                &one as *const _ as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        // Room for one `ucred` message, aligned as a `cmsghdr` needs.
+        let mut control = [0u64; 8];
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+        let n = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_DONTWAIT | libc::MSG_CMSG_CLOEXEC) };
+        if n < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let mut creds = None;
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET
+                    && (*cmsg).cmsg_type == libc::SCM_CREDENTIALS
+                {
+                    let ucred: libc::ucred = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+                    creds = Some(UCred {
+                        pid: ucred.pid,
+                        uid: ucred.uid,
+                        gid: ucred.gid,
+                    });
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        Ok((n as u64, creds))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod sys {
+    use super::UCred;
+    use io_lifetimes::AsFd;
+    use wasi_common::{Error, ErrorExt};
+
+    pub(super) fn recv_with_creds(
+        _fd: &impl AsFd,
+        _buf: &mut [u8],
+    ) -> Result<(u64, Option<UCred>), Error> {
+        Err(Error::not_supported().context("SCM_CREDENTIALS is only available on Linux"))
+    }
+}
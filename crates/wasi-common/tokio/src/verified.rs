@@ -0,0 +1,200 @@
+use std::any::Any;
+use std::io;
+use tokio::sync::Mutex;
+use wasi_common::{
+    file::{FdFlags, FileType, Filestat, WasiFile},
+    Error, ErrorExt,
+};
+
+/// A running checksum, such as CRC32 or SHA-256, for a [`VerifiedReader`]
+/// to compute over what's read.
+pub trait Checksum: Send {
+    /// Add `data` to the checksum.
+    fn update(&mut self, data: &[u8]);
+    /// The checksum of everything added since it was created or last reset.
+    fn digest(&self) -> Vec<u8>;
+    /// Start over, as if nothing had been added.
+    fn reset(&mut self);
+}
+
+/// A `WasiFile` wrapper which computes a checksum of the bytes read through
+/// it, for the host to check against a known value with
+/// [`VerifiedReader::verify`] once the guest has read to EOF.
+///
+/// Only `read_vectored` is checksummed, in the order reads complete;
+/// positional reads leave the checksum alone. A seek back to the start
+/// resets it, so a guest which rereads the file still verifies, and a seek
+/// to wherever the checksum already covers is harmless. Any other seek
+/// leaves a gap or overlap in what was read, so `verify` fails from then on
+/// until the file is rewound.
+pub struct VerifiedReader<F: WasiFile, C: Checksum> {
+    inner: F,
+    state: Mutex<State<C>>,
+}
+
+struct State<C> {
+    checksum: C,
+    // How many bytes from the start of the file the checksum covers, or
+    // `None` if a seek broke the run.
+    covered: Option<u64>,
+}
+
+impl<F: WasiFile, C: Checksum> VerifiedReader<F, C> {
+    /// Checksum what's read from `inner`, which should be at its start, with
+    /// `checksum`, which should be fresh.
+    pub fn new(inner: F, checksum: C) -> Self {
+        VerifiedReader {
+            inner,
+            state: Mutex::new(State {
+                checksum,
+                covered: Some(0),
+            }),
+        }
+    }
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    /// Check the checksum of what has been read against `expected`. Fails
+    /// with `EIO` on a mismatch, and with `EINVAL` if a seek means the
+    /// checksum doesn't cover a contiguous run from the start of the file.
+    pub async fn verify(&self, expected: &[u8]) -> Result<(), Error> {
+        let state = self.state.lock().await;
+        if state.covered.is_none() {
+            return Err(
+                Error::invalid_argument().context("a seek left a gap in the checksummed data")
+            );
+        }
+        if state.checksum.digest() != expected {
+            return Err(Error::io().context("checksum mismatch"));
+        }
+        Ok(())
+    }
+}
+
+#[wiggle::async_trait]
+impl<F: WasiFile + 'static, C: Checksum + 'static> WasiFile for VerifiedReader<F, C> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.inner.pollable()
+    }
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.inner.pollable()
+    }
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        // Held across the read, so the checksum sees reads in file order.
+        let mut state = self.state.lock().await;
+        let n = self.inner.read_vectored(bufs).await?;
+        let mut left = n as usize;
+        for buf in bufs.iter() {
+            let take = buf.len().min(left);
+            state.checksum.update(&buf[..take]);
+            left -= take;
+        }
+        state.covered = state.covered.and_then(|c| c.checked_add(n));
+        Ok(n)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let mut state = self.state.lock().await;
+        let offset = self.inner.seek(pos).await?;
+        if offset == 0 {
+            state.checksum.reset();
+            state.covered = Some(0);
+        } else if state.covered != Some(offset) {
+            state.covered = None;
+        }
+        Ok(offset)
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Checksum, VerifiedReader};
+    use crate::BytesFile;
+    use std::io::{IoSliceMut, SeekFrom};
+    use std::sync::Arc;
+    use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+    // 32-bit FNV-1a, which is enough to tell contents apart here.
+    struct Fnv(u32);
+
+    impl Checksum for Fnv {
+        fn update(&mut self, data: &[u8]) {
+            for b in data {
+                self.0 = (self.0 ^ u32::from(*b)).wrapping_mul(0x0100_0193);
+            }
+        }
+        fn digest(&self) -> Vec<u8> {
+            self.0.to_be_bytes().to_vec()
+        }
+        fn reset(&mut self) {
+            self.0 = 0x811c_9dc5;
+        }
+    }
+
+    fn fnv(data: &[u8]) -> Vec<u8> {
+        let mut f = Fnv(0x811c_9dc5);
+        f.update(data);
+        f.digest()
+    }
+
+    async fn read_to_end(f: &dyn WasiFile) {
+        let mut buf = [0; 3];
+        while f
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap()
+            > 0
+        {}
+    }
+
+    #[tokio::test]
+    async fn verifies_what_was_read() {
+        let data = b"integrity matters";
+        let f = VerifiedReader::new(BytesFile::new(Arc::from(&data[..])), Fnv(0x811c_9dc5));
+        read_to_end(&f).await;
+        f.verify(&fnv(data)).await.unwrap();
+        let err = f.verify(&fnv(b"something else")).await.unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Io);
+
+        // Skipping ahead leaves a gap, until the file is rewound and reread.
+        f.seek(SeekFrom::Start(4)).await.unwrap();
+        let err = f.verify(&fnv(data)).await.unwrap_err();
+        assert_eq!(err.downcast().unwrap(), Errno::Inval);
+        f.seek(SeekFrom::Start(0)).await.unwrap();
+        read_to_end(&f).await;
+        f.verify(&fnv(data)).await.unwrap();
+    }
+}
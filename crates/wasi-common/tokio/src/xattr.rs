@@ -0,0 +1,184 @@
+use crate::file::File;
+use wasi_common::Error;
+
+impl File {
+    /// Get the value of the extended attribute `name`, such as `user.tag`.
+    ///
+    /// On Linux and macOS this is `fgetxattr`; elsewhere, including Windows,
+    /// this and the other extended attribute methods return
+    /// `Error::not_supported()`.
+    pub async fn getxattr(&self, name: &str) -> Result<Vec<u8>, Error> {
+        tokio::task::block_in_place(|| sys::getxattr(&self.inner, name))
+    }
+
+    /// Set the extended attribute `name` to `value`, creating it if needed.
+    ///
+    /// Changing attributes requires a file opened for writing, as changing its
+    /// contents does; a read-only file fails with `EBADF`. The host's own
+    /// permission checks, such as ownership of the file for `user.*`
+    /// attributes, still apply on top of that.
+    pub async fn setxattr(&self, name: &str, value: &[u8]) -> Result<(), Error> {
+        tokio::task::block_in_place(|| sys::setxattr(&self.inner, name, value))
+    }
+
+    /// List the names of the file's extended attributes.
+    pub async fn listxattr(&self) -> Result<Vec<String>, Error> {
+        tokio::task::block_in_place(|| sys::listxattr(&self.inner))
+    }
+
+    /// Remove the extended attribute `name`. As with
+    /// [`File::setxattr`], the file must be open for writing.
+    pub async fn removexattr(&self, name: &str) -> Result<(), Error> {
+        tokio::task::block_in_place(|| sys::removexattr(&self.inner, name))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+mod sys {
+    use io_lifetimes::AsFd;
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::io::{AsRawFd, RawFd};
+    use wasi_common::{snapshots::preview_1::types::Errno, Error, ErrorExt};
+
+    #[cfg(target_os = "macos")]
+    use self::macos::{fgetxattr, flistxattr, fremovexattr, fsetxattr};
+    #[cfg(not(target_os = "macos"))]
+    use libc::{fgetxattr, flistxattr, fremovexattr, fsetxattr};
+
+    // macOS takes an extra position argument, used only for resource forks,
+    // and an options argument. Adapt them to the Linux signatures.
+    #[cfg(target_os = "macos")]
+    mod macos {
+        use libc::{c_char, c_int, c_void, size_t, ssize_t};
+
+        pub unsafe fn fgetxattr(
+            fd: c_int,
+            name: *const c_char,
+            value: *mut c_void,
+            size: size_t,
+        ) -> ssize_t {
+            libc::fgetxattr(fd, name, value, size, 0, 0)
+        }
+
+        pub unsafe fn fsetxattr(
+            fd: c_int,
+            name: *const c_char,
+            value: *const c_void,
+            size: size_t,
+            flags: c_int,
+        ) -> c_int {
+            libc::fsetxattr(fd, name, value, size, 0, flags)
+        }
+
+        pub unsafe fn flistxattr(fd: c_int, list: *mut c_char, size: size_t) -> ssize_t {
+            libc::flistxattr(fd, list, size, 0)
+        }
+
+        pub unsafe fn fremovexattr(fd: c_int, name: *const c_char) -> c_int {
+            libc::fremovexattr(fd, name, 0)
+        }
+    }
+
+    fn c_name(name: &str) -> Result<CString, Error> {
+        CString::new(name)
+            .map_err(|_| Error::invalid_argument().context("attribute name contains a NUL"))
+    }
+
+    fn check_writable(fd: RawFd) -> Result<(), Error> {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        if flags & libc::O_ACCMODE == libc::O_RDONLY {
+            return Err(Error::from(Errno::Badf).context("file is not open for writing"));
+        }
+        Ok(())
+    }
+
+    // Call `f` with a buffer until it's large enough for the result, asking
+    // for the size first and retrying if the attribute grew in between.
+    fn read_sized(mut f: impl FnMut(*mut libc::c_void, usize) -> isize) -> Result<Vec<u8>, Error> {
+        loop {
+            let size = f(std::ptr::null_mut(), 0);
+            if size < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let mut buf = vec![0u8; size as usize];
+            let n = f(buf.as_mut_ptr().cast(), buf.len());
+            if n >= 0 {
+                buf.truncate(n as usize);
+                return Ok(buf);
+            }
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::ERANGE) {
+                return Err(err.into());
+            }
+        }
+    }
+
+    fn check(ret: libc::c_int) -> Result<(), Error> {
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn getxattr(fd: impl AsFd, name: &str) -> Result<Vec<u8>, Error> {
+        let fd = fd.as_fd().as_raw_fd();
+        let name = c_name(name)?;
+        read_sized(|value, size| unsafe { fgetxattr(fd, name.as_ptr(), value, size) })
+    }
+
+    pub(super) fn setxattr(fd: impl AsFd, name: &str, value: &[u8]) -> Result<(), Error> {
+        let fd = fd.as_fd().as_raw_fd();
+        check_writable(fd)?;
+        let name = c_name(name)?;
+        let (ptr, len) = (value.as_ptr().cast(), value.len());
+        check(unsafe { fsetxattr(fd, name.as_ptr(), ptr, len, 0) })
+    }
+
+    pub(super) fn listxattr(fd: impl AsFd) -> Result<Vec<String>, Error> {
+        let fd = fd.as_fd().as_raw_fd();
+        let list = read_sized(|list, size| unsafe { flistxattr(fd, list.cast(), size) })?;
+        // The names come back NUL-terminated, one after another.
+        list.split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                String::from_utf8(name.to_vec()).map_err(|_| Error::illegal_byte_sequence())
+            })
+            .collect()
+    }
+
+    pub(super) fn removexattr(fd: impl AsFd, name: &str) -> Result<(), Error> {
+        let fd = fd.as_fd().as_raw_fd();
+        check_writable(fd)?;
+        let name = c_name(name)?;
+        check(unsafe { fremovexattr(fd, name.as_ptr()) })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+mod sys {
+    use wasi_common::{Error, ErrorExt};
+
+    fn unsupported() -> Error {
+        Error::not_supported().context("extended attributes are not supported on this platform")
+    }
+
+    pub(super) fn getxattr<F>(_fd: F, _name: &str) -> Result<Vec<u8>, Error> {
+        Err(unsupported())
+    }
+
+    pub(super) fn setxattr<F>(_fd: F, _name: &str, _value: &[u8]) -> Result<(), Error> {
+        Err(unsupported())
+    }
+
+    pub(super) fn listxattr<F>(_fd: F) -> Result<Vec<String>, Error> {
+        Err(unsupported())
+    }
+
+    pub(super) fn removexattr<F>(_fd: F, _name: &str) -> Result<(), Error> {
+        Err(unsupported())
+    }
+}
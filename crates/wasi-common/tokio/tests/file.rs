@@ -0,0 +1,1031 @@
+use anyhow::{Context, Error};
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use wasi_common::WasiFile;
+use wasi_tokio::File;
+
+fn open_scratch_file(workspace: &cap_tempfile::TempDir, name: &str) -> Result<File, Error> {
+    let f = workspace
+        .open_with(
+            name,
+            cap_std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true),
+        )
+        .with_context(|| format!("open {}", name))?;
+    Ok(File::from_cap_std(f))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn try_clone_shares_offset() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    f.write_vectored(&[IoSlice::new(b"hello world")])
+        .await
+        .context("write to f")?;
+
+    let dup = f.try_clone().context("dup f")?;
+    // The dup shares the open file description, so it observes the offset
+    // left behind by the write through the original.
+    assert_eq!(dup.seek(SeekFrom::Current(0)).await?, 11);
+    dup.seek(SeekFrom::Start(6)).await?;
+    assert_eq!(f.seek(SeekFrom::Current(0)).await?, 6);
+
+    // Closing the original leaves the dup usable.
+    drop(f);
+    let mut buf = [0; 16];
+    let n = dup.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+    assert_eq!(&buf[..n as usize], b"world");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_reads_share_the_offset() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    let words = (0..4096u32).flat_map(u32::to_be_bytes).collect::<Vec<_>>();
+    f.write_vectored(&[IoSlice::new(&words)])
+        .await
+        .context("write to f")?;
+    f.seek(SeekFrom::Start(0)).await?;
+
+    let f = std::sync::Arc::new(f);
+    let readers = (0..2)
+        .map(|_| {
+            let f = f.clone();
+            tokio::spawn(async move {
+                let mut seen = Vec::new();
+                loop {
+                    let mut buf = [0; 4];
+                    let n = f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+                    if n == 0 {
+                        return Ok::<_, wasi_common::Error>(seen);
+                    }
+                    assert_eq!(n, 4);
+                    seen.push(u32::from_be_bytes(buf));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut seen = Vec::new();
+    for reader in readers {
+        seen.extend(reader.await??);
+    }
+
+    // Every word was read exactly once, by one reader or the other.
+    seen.sort_unstable();
+    assert_eq!(seen, (0..4096).collect::<Vec<_>>());
+
+    Ok(())
+}
+
+// The first `stream_position` has to ask the kernel, which mustn't race
+// with reads moving the offset at the same time.
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_position_during_concurrent_reads() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    let words = (0..4096u32).flat_map(u32::to_be_bytes).collect::<Vec<_>>();
+    // Positional, so the offset is still unknown when the reads start.
+    f.write_vectored_at(&[IoSlice::new(&words)], 0)
+        .await
+        .context("write to f")?;
+
+    let f = std::sync::Arc::new(f);
+    let reader = tokio::spawn({
+        let f = f.clone();
+        async move {
+            loop {
+                let mut buf = [0; 4];
+                if f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await? == 0 {
+                    return Ok::<_, wasi_common::Error>(());
+                }
+            }
+        }
+    });
+    let mut last = 0;
+    for _ in 0..100 {
+        let position = f.stream_position().await?;
+        assert!(position >= last, "offset went backwards");
+        last = position;
+        tokio::task::yield_now().await;
+    }
+    reader.await??;
+
+    assert_eq!(f.stream_position().await?, words.len() as u64);
+    assert_eq!(f.seek(SeekFrom::Current(0)).await?, words.len() as u64);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_until_delimiter() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    f.write_vectored(&[IoSlice::new(b"first\nsecond line\nrest")])
+        .await
+        .context("write to f")?;
+    f.seek(SeekFrom::Start(0)).await?;
+
+    assert_eq!(f.read_until(b'\n', 64).await?, b"first\n");
+    // `max` bounds the read even when no delimiter has been seen yet.
+    assert_eq!(f.read_until(b'\n', 3).await?, b"sec");
+    assert_eq!(f.read_until(b'\n', 64).await?, b"ond line\n");
+    assert_eq!(f.read_until(b'\n', 64).await?, b"rest");
+    assert_eq!(f.read_until(b'\n', 64).await?, b"");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_vectored_filled_per_buffer() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    f.write_vectored(&[IoSlice::new(b"0123456789abcde")])
+        .await?;
+    f.seek(SeekFrom::Start(0)).await?;
+
+    // Only a buffer and a half's worth of data is there to read.
+    let (mut a, mut b, mut c) = ([0; 10], [0; 10], [0; 10]);
+    let filled = f
+        .read_vectored_filled(&mut [
+            IoSliceMut::new(&mut a),
+            IoSliceMut::new(&mut b),
+            IoSliceMut::new(&mut c),
+        ])
+        .await?;
+    assert_eq!(filled, [10, 5, 0]);
+    assert_eq!(&a, b"0123456789");
+    assert_eq!(&b[..5], b"abcde");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lock_range_round_trip() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+
+    f.lock_range(0, 10, true).await.context("exclusive lock")?;
+    // Locks are owned by the process, so our own lock never shows up as a
+    // conflict.
+    #[cfg(unix)]
+    assert_eq!(f.test_lock(0, 10).await?, None);
+    f.unlock_range(0, 10).await.context("unlock")?;
+
+    // A zero length locks through the end of the file.
+    f.lock_range(0, 0, false).await.context("shared lock")?;
+    f.unlock_range(0, 0).await.context("unlock to end")?;
+
+    Ok(())
+}
+
+// Locking waits on a blocking thread, so it works on a current-thread
+// runtime too.
+#[tokio::test]
+async fn lock_range_on_current_thread() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    f.lock_range(0, 10, true).await.context("exclusive lock")?;
+    f.unlock_range(0, 10).await.context("unlock")?;
+
+    #[cfg(unix)]
+    {
+        use wasi_common::snapshots::preview_1::types::Errno;
+        let err = f
+            .lock_range(u64::MAX, 1, true)
+            .await
+            .expect_err("offset beyond off_t");
+        assert_eq!(err.downcast()?, Errno::Inval);
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn set_fdflags_accepts_rsync() -> Result<(), Error> {
+    use wasi_common::file::FdFlags;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let mut f = open_scratch_file(&workspace, "f")?;
+
+    // RSYNC is accepted, even though it's effectively a no-op.
+    f.set_fdflags(FdFlags::RSYNC).await.context("set RSYNC")?;
+    f.set_fdflags(FdFlags::APPEND | FdFlags::RSYNC)
+        .await
+        .context("set APPEND | RSYNC")?;
+    assert!(f.get_fdflags().await?.contains(FdFlags::APPEND));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn max_read_bytes_caps_each_read() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?.with_max_read_bytes(5);
+    f.write_vectored(&[IoSlice::new(b"hello world")]).await?;
+
+    let (mut a, mut b) = ([0; 4], [0; 16]);
+    let n = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)], 0)
+        .await?;
+    assert_eq!(n, 5);
+    assert_eq!(&a, b"hell");
+    assert_eq!(&b[..1], b"o");
+
+    f.seek(SeekFrom::Start(0)).await?;
+    let mut buf = [0; 64];
+    let n = f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+    assert_eq!(&buf[..n as usize], b"hello");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn coalesced_reads_scatter_into_each_buffer() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?.with_coalesce_threshold(2);
+    f.write_vectored(&[IoSlice::new(b"hello world")]).await?;
+
+    // Past the end of the file, the last buffers are left alone.
+    let mut storage = [[b'.'; 3]; 5];
+    let mut bufs = storage
+        .iter_mut()
+        .map(|s| IoSliceMut::new(&mut s[..]))
+        .collect::<Vec<_>>();
+    let n = f.read_vectored_at(&mut bufs, 0).await?;
+    assert_eq!(n, 11);
+    assert_eq!(storage.concat(), b"hello world....");
+
+    f.seek(SeekFrom::Start(6)).await?;
+    let (mut a, mut b, mut c) = ([0; 2], [0; 2], [0; 2]);
+    let n = f
+        .read_vectored(&mut [
+            IoSliceMut::new(&mut a),
+            IoSliceMut::new(&mut b),
+            IoSliceMut::new(&mut c),
+        ])
+        .await?;
+    assert_eq!(n, 5);
+    assert_eq!((&a, &b, &c[..1]), (b"wo", b"rl", &b"d"[..]));
+    assert_eq!(f.stream_position().await?, 11);
+
+    // Buffers too large in total to copy through are read into directly.
+    let mut storage = vec![b'.'; 3 * 64 * 1024];
+    let mut bufs = storage
+        .chunks_mut(64 * 1024)
+        .map(IoSliceMut::new)
+        .collect::<Vec<_>>();
+    let n = f.read_vectored_at(&mut bufs, 6).await?;
+    assert_eq!(n, 5);
+    assert_eq!(&storage[..6], b"world.");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_position_tracks_offset() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    assert_eq!(f.stream_position().await?, 0);
+
+    f.write_vectored(&[IoSlice::new(b"hello world")]).await?;
+    assert_eq!(f.stream_position().await?, 11);
+
+    f.seek(SeekFrom::Start(2)).await?;
+    let mut buf = [0; 3];
+    f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+    assert_eq!(f.stream_position().await?, 5);
+
+    // Positional operations leave the offset where it was.
+    f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 8)
+        .await?;
+    f.write_vectored_at(&[IoSlice::new(b"J")], 0).await?;
+    assert_eq!(f.stream_position().await?, 5);
+    assert_eq!(f.seek(SeekFrom::Current(0)).await?, 5);
+
+    // Clones share the offset, and so share its tracking.
+    let dup = f.try_clone()?;
+    dup.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+    assert_eq!(f.stream_position().await?, 8);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_position_after_append_write() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    workspace.write("f", b"hello world")?;
+    let f = workspace
+        .open_with("f", cap_std::fs::OpenOptions::new().read(true).append(true))
+        .context("open f for append")?;
+    let f = File::from_cap_std(f);
+
+    // Opened with `APPEND`, so a write after a seek still lands at the end,
+    // and leaves the offset there rather than just past the seek.
+    f.seek(SeekFrom::Start(2)).await?;
+    f.write_vectored(&[IoSlice::new(b"!")]).await?;
+    assert_eq!(f.stream_position().await?, 12);
+    assert_eq!(f.seek(SeekFrom::Current(0)).await?, 12);
+    assert_eq!(workspace.read("f")?, b"hello world!");
+
+    Ok(())
+}
+
+// Small operations run inline, so unlike those that go through
+// `block_in_place`, they work on a current-thread runtime.
+#[tokio::test(flavor = "current_thread")]
+async fn small_io_runs_inline() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?.with_inline_threshold(16);
+    f.write_vectored(&[IoSlice::new(b"hello")]).await?;
+
+    let mut buf = [0; 8];
+    let n = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(&buf[..n as usize], b"hello");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_all_reports_which_file_failed() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let a = open_scratch_file(&workspace, "a")?;
+    let c = open_scratch_file(&workspace, "c")?;
+    for f in [&a, &c] {
+        f.write_vectored(&[IoSlice::new(b"data")]).await?;
+    }
+    wasi_tokio::sync_all(&[&a, &c]).await?;
+
+    // Sockets can't be synced, so this one fails with EINVAL.
+    let (sock, _peer) = std::os::unix::net::UnixStream::pair()?;
+    let b = File::from_cap_std(cap_std::fs::File::from_std(std::fs::File::from(
+        std::os::unix::io::OwnedFd::from(sock),
+    )));
+
+    let err = wasi_tokio::sync_all(&[&a, &b, &c])
+        .await
+        .expect_err("syncing a socket fails");
+    assert!(
+        format!("{:?}", err).contains("indices [1]"),
+        "error should name the failing file: {:?}",
+        err
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn cancellation_interrupts_waits_and_reads() -> Result<(), Error> {
+    use wasi_common::snapshots::preview_1::types::Errno;
+
+    // The peer never writes, so without cancellation `readable` waits forever.
+    let (sock, _peer) = std::os::unix::net::UnixStream::pair()?;
+    let token = wasi_tokio::CancellationToken::new();
+    let f = File::from_cap_std(cap_std::fs::File::from_std(std::fs::File::from(
+        std::os::unix::io::OwnedFd::from(sock),
+    )))
+    .with_cancellation_token(token.clone());
+
+    tokio::spawn({
+        let token = token.clone();
+        async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            token.cancel();
+        }
+    });
+    let err = tokio::time::timeout(std::time::Duration::from_secs(5), f.readable())
+        .await
+        .context("readable should return once cancelled")?
+        .expect_err("cancelled");
+    assert_eq!(err.downcast()?, Errno::Intr);
+
+    let mut buf = [0; 4];
+    let err = f
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .await
+        .expect_err("cancelled");
+    assert_eq!(err.downcast()?, Errno::Intr);
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[tokio::test(flavor = "multi_thread")]
+async fn xattr_round_trip() -> Result<(), Error> {
+    use wasi_common::snapshots::preview_1::types::Errno;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    match f.setxattr("user.tag", b"blue").await {
+        Ok(()) => {}
+        // Not every filesystem a tempdir can land on supports user attributes.
+        Err(e) if e.downcast_ref() == Some(&Errno::Notsup) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+    assert_eq!(f.getxattr("user.tag").await?, b"blue");
+    assert!(f.listxattr().await?.contains(&"user.tag".to_string()));
+
+    f.removexattr("user.tag").await?;
+    f.getxattr("user.tag").await.expect_err("attribute removed");
+    assert!(!f.listxattr().await?.contains(&"user.tag".to_string()));
+
+    let read_only = File::from_cap_std(workspace.open("f")?);
+    let err = read_only
+        .setxattr("user.tag", b"red")
+        .await
+        .expect_err("read-only file");
+    assert_eq!(err.downcast()?, Errno::Badf);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn set_nonblocking_both_ways() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    assert!(!f.nonblocking()?);
+    f.set_nonblocking(true)?;
+    assert!(f.nonblocking()?);
+    f.set_nonblocking(false)?;
+    assert!(!f.nonblocking()?);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn readahead_large_file() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    let data = vec![0x5a; 4 * 1024 * 1024];
+    f.write_vectored_at(&[IoSlice::new(&data)], 0).await?;
+
+    f.readahead(0, data.len()).await?;
+    f.readahead(1024 * 1024, 64 * 1024).await?;
+    Ok(())
+}
+
+// A stand-in for a real AEAD: a keyed XOR stream, with a keyed checksum of
+// the plaintext as the tag. It shows that blocks are transformed and
+// authenticated, and is no use for anything else.
+struct ToyCipher(u64);
+
+impl ToyCipher {
+    fn seed(&self, nonce: &[u8; 12]) -> u64 {
+        let mut seed = self.0;
+        for b in nonce {
+            seed = seed.rotate_left(7) ^ u64::from(*b);
+        }
+        seed
+    }
+    fn xor(&self, nonce: &[u8; 12], data: &mut [u8]) {
+        let mut state = self.seed(nonce);
+        for b in data {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            *b ^= (state >> 56) as u8;
+        }
+    }
+    fn tag(&self, nonce: &[u8; 12], data: &[u8]) -> [u8; 8] {
+        let mut sum = !self.seed(nonce);
+        for b in data {
+            sum = sum.rotate_left(5).wrapping_mul(31) ^ u64::from(*b);
+        }
+        sum.to_le_bytes()
+    }
+}
+
+impl wasi_tokio::AeadCipher for ToyCipher {
+    fn tag_len(&self) -> usize {
+        8
+    }
+    fn seal(&self, nonce: &[u8; 12], block: &mut Vec<u8>) -> Result<(), wasi_common::Error> {
+        let tag = self.tag(nonce, block);
+        self.xor(nonce, block);
+        block.extend_from_slice(&tag);
+        Ok(())
+    }
+    fn open(&self, nonce: &[u8; 12], block: &mut Vec<u8>) -> Result<(), wasi_common::Error> {
+        use wasi_common::ErrorExt;
+        let tag = block.split_off(block.len() - 8);
+        self.xor(nonce, block);
+        if self.tag(nonce, block)[..] != tag[..] {
+            return Err(wasi_common::Error::io().context("block does not authenticate"));
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn encrypted_file_random_access() -> Result<(), Error> {
+    use wasi_common::snapshots::preview_1::types::Errno;
+    use wasi_tokio::EncryptedFile;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = EncryptedFile::new(
+        open_scratch_file(&workspace, "f")?,
+        Box::new(ToyCipher(0x5eed)),
+    )
+    .with_block_size(16);
+
+    // Mirror every write into a plain buffer to check reads against.
+    let mut model = Vec::new();
+    for (offset, data) in [
+        (0, &b"the quick brown fox"[..]),
+        (50, b"jumps over"),
+        (10, b"BROWN FOX JUMPS OVER THE"),
+        (3, b"!"),
+    ] {
+        let n = f.write_vectored_at(&[IoSlice::new(data)], offset).await?;
+        assert_eq!(n, data.len() as u64);
+        let end = offset as usize + data.len();
+        if model.len() < end {
+            model.resize(end, 0);
+        }
+        model[offset as usize..end].copy_from_slice(data);
+    }
+    assert_eq!(f.get_filestat().await?.size, model.len() as u64);
+
+    for (offset, len) in [(0, 60), (5, 20), (15, 2), (31, 1), (47, 100), (60, 4)] {
+        let (mut a, mut b) = (vec![0; len / 2], vec![0; len - len / 2]);
+        let n = f
+            .read_vectored_at(
+                &mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)],
+                offset as u64,
+            )
+            .await?;
+        a.extend_from_slice(&b);
+        let expected = &model[offset.min(model.len())..(offset + len).min(model.len())];
+        assert_eq!(&a[..n as usize], expected, "read {} at {}", len, offset);
+    }
+
+    // Streaming reads and writes go through the plaintext offset.
+    assert_eq!(f.seek(SeekFrom::End(-4)).await?, model.len() as u64 - 4);
+    let mut buf = [0; 8];
+    let n = f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+    assert_eq!(&buf[..n as usize], b"over");
+
+    // Truncate into the middle of a block, then extend again with zeros.
+    f.set_filestat_size(21).await?;
+    f.set_filestat_size(40).await?;
+    model.truncate(21);
+    model.resize(40, 0);
+    let mut buf = vec![0; 64];
+    let n = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(&buf[..n as usize], &model[..]);
+
+    // Nothing is stored in the clear, and tampering is caught.
+    let mut sealed = vec![0; 256];
+    let inner = f.get_ref();
+    let n = inner
+        .read_vectored_at(&mut [IoSliceMut::new(&mut sealed)], 0)
+        .await?;
+    sealed.truncate(n as usize);
+    assert!(!sealed.windows(5).any(|w| w == b"quick"));
+    let flipped = [sealed[20] ^ 1];
+    inner
+        .write_vectored_at(&[IoSlice::new(&flipped)], 20)
+        .await?;
+    let err = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await
+        .expect_err("tampered block");
+    assert_eq!(err.downcast()?, Errno::Io);
+
+    Ok(())
+}
+
+// Checks that no nonce is ever sealed with twice, on top of `ToyCipher`.
+struct NonceCheck(
+    ToyCipher,
+    std::sync::Mutex<std::collections::HashSet<[u8; 12]>>,
+);
+
+impl wasi_tokio::AeadCipher for NonceCheck {
+    fn tag_len(&self) -> usize {
+        wasi_tokio::AeadCipher::tag_len(&self.0)
+    }
+    fn seal(&self, nonce: &[u8; 12], block: &mut Vec<u8>) -> Result<(), wasi_common::Error> {
+        assert!(self.1.lock().unwrap().insert(*nonce), "nonce reused");
+        wasi_tokio::AeadCipher::seal(&self.0, nonce, block)
+    }
+    fn open(&self, nonce: &[u8; 12], block: &mut Vec<u8>) -> Result<(), wasi_common::Error> {
+        wasi_tokio::AeadCipher::open(&self.0, nonce, block)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn encrypted_file_rewrite_after_truncate() -> Result<(), Error> {
+    use wasi_tokio::EncryptedFile;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let cipher = NonceCheck(ToyCipher(0x5eed), Default::default());
+    let f = EncryptedFile::new(open_scratch_file(&workspace, "f")?, Box::new(cipher))
+        .with_block_size(16);
+
+    // Blocks dropped by a truncation, whole or in part, and then written
+    // again must be sealed under fresh nonces.
+    for round in 0..3u8 {
+        let data = [b'a' + round; 40];
+        f.write_vectored_at(&[IoSlice::new(&data)], 0).await?;
+        f.write_vectored_at(&[IoSlice::new(&data[..4])], 20).await?;
+        f.set_filestat_size(if round == 1 { 0 } else { 10 }).await?;
+    }
+    f.write_vectored_at(&[IoSlice::new(b"again")], 30).await?;
+    let mut buf = [0; 40];
+    let n = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(n, 35);
+    assert_eq!(&buf[..10], &[b'c'; 10]);
+    assert_eq!(&buf[10..30], &[0; 20]);
+    assert_eq!(&buf[30..35], b"again");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn publish_on_close() -> Result<(), Error> {
+    use wasi_tokio::PublishOnClose;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let entries = || -> Result<usize, Error> { Ok(workspace.entries()?.count()) };
+
+    let f = PublishOnClose::create(workspace.try_clone()?, "out.txt")?;
+    f.write_vectored(&[IoSlice::new(b"complete")]).await?;
+    f.sync().await?;
+    assert!(!workspace.exists("out.txt"));
+    drop(f);
+    assert_eq!(workspace.read("out.txt")?, b"complete");
+    assert_eq!(entries()?, 1);
+
+    // Aborted, or written to after the last sync, the old contents stay.
+    let f = PublishOnClose::create(workspace.try_clone()?, "out.txt")?;
+    f.write_vectored(&[IoSlice::new(b"aborted")]).await?;
+    f.sync().await?;
+    f.abort()?;
+    let f = PublishOnClose::create(workspace.try_clone()?, "out.txt")?;
+    f.write_vectored(&[IoSlice::new(b"unsynced")]).await?;
+    drop(f);
+    assert_eq!(workspace.read("out.txt")?, b"complete");
+    assert_eq!(entries()?, 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hardlinks_share_device_and_inode() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let a = open_scratch_file(&workspace, "a")?;
+    workspace.hard_link("a", &workspace, "b")?;
+    let b = open_scratch_file(&workspace, "b")?;
+    let c = open_scratch_file(&workspace, "c")?;
+
+    let (a, b, c) = (
+        a.get_filestat().await?,
+        b.get_filestat().await?,
+        c.get_filestat().await?,
+    );
+    assert_eq!((a.device_id, a.inode), (b.device_id, b.inode));
+    assert_eq!(a.nlink, 2);
+    assert_eq!(a.device_id, c.device_id);
+    assert_ne!(a.inode, c.inode);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sub_file_writes_are_clamped() -> Result<(), Error> {
+    use wasi_common::snapshots::preview_1::types::Errno;
+    use wasi_tokio::SubFile;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    f.write_vectored(&[IoSlice::new(b"aaaa....bbbb")]).await?;
+
+    let window = SubFile::new(f, 4, 4).with_writes(true);
+    let n = window
+        .write_vectored_at(&[IoSlice::new(b"12"), IoSlice::new(b"3456")], 1)
+        .await?;
+    assert_eq!(n, 3);
+    let err = window
+        .write_vectored_at(&[IoSlice::new(b"x")], 4)
+        .await
+        .expect_err("past the window");
+    assert_eq!(err.downcast()?, Errno::Fbig);
+
+    let mut buf = [0; 16];
+    let n = window
+        .into_inner()
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(&buf[..n as usize], b"aaaa.123bbbb");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tmpfile_round_trip() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = File::open_tmpfile(&workspace)
+        .await
+        .context("open tmpfile")?;
+    // The file has no name, whichever way it was made.
+    assert_eq!(workspace.entries()?.count(), 0);
+
+    f.write_vectored(&[IoSlice::new(b"transient")]).await?;
+    let mut buf = [0; 16];
+    let n = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(&buf[..n as usize], b"transient");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn audit_log_appends_and_mirrors() -> Result<(), Error> {
+    use std::sync::{Arc, Mutex};
+    use wasi_common::snapshots::preview_1::types::Errno;
+    use wasi_tokio::AuditLog;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "audit")?;
+    f.write_vectored(&[IoSlice::new(b"earlier\n")]).await?;
+    f.seek(SeekFrom::Start(0)).await?;
+
+    let mirrored = Arc::new(Mutex::new(Vec::new()));
+    let log = AuditLog::new(f, {
+        let mirrored = mirrored.clone();
+        move |line: &[u8]| mirrored.lock().unwrap().push(line.to_vec())
+    });
+
+    let err = log.seek(SeekFrom::Start(0)).await.expect_err("seek");
+    assert_eq!(err.downcast()?, Errno::Spipe);
+    let err = log
+        .write_vectored_at(&[IoSlice::new(b"x")], 0)
+        .await
+        .expect_err("positional write");
+    assert_eq!(err.downcast()?, Errno::Perm);
+
+    log.write_vectored(&[IoSlice::new(b"login "), IoSlice::new(b"alice\nlog")])
+        .await?;
+    log.write_vectored(&[IoSlice::new(b"out\npartial")]).await?;
+    assert_eq!(
+        *mirrored.lock().unwrap(),
+        [b"login alice\n".to_vec(), b"logout\n".to_vec()]
+    );
+    log.sync().await?;
+    assert_eq!(mirrored.lock().unwrap()[2], b"partial");
+
+    // The earlier contents weren't overwritten, though the offset was at 0.
+    let mut buf = [0; 64];
+    let n = log
+        .get_ref()
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(&buf[..n as usize], b"earlier\nlogin alice\nlogout\npartial");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn statat_with_and_without_following() -> Result<(), Error> {
+    use wasi_common::{file::FileType, snapshots::preview_1::types::Errno};
+    use wasi_tokio::statat;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    workspace.write("f", b"hello")?;
+    workspace.symlink("f", "link")?;
+
+    for follow in [false, true] {
+        let stat = statat(&workspace, "f", follow).await?;
+        assert_eq!((stat.filetype, stat.size), (FileType::RegularFile, 5));
+    }
+    let stat = statat(&workspace, "link", true).await?;
+    assert_eq!((stat.filetype, stat.size), (FileType::RegularFile, 5));
+    let stat = statat(&workspace, "link", false).await?;
+    assert_eq!(stat.filetype, FileType::SymbolicLink);
+
+    let err = statat(&workspace, "../f", true)
+        .await
+        .expect_err("outside the directory");
+    assert_eq!(err.downcast()?, Errno::Perm);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn create_and_remove_dirs() -> Result<(), Error> {
+    use wasi_common::snapshots::preview_1::types::Errno;
+    use wasi_tokio::{mkdir_at, rmdir_at, unlink_at};
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    mkdir_at(&workspace, "d").await?;
+    mkdir_at(&workspace, "d/e").await?;
+    assert!(workspace.metadata("d/e")?.is_dir());
+    let err = mkdir_at(&workspace, "d").await.expect_err("already exists");
+    assert_eq!(err.downcast()?, Errno::Exist);
+
+    workspace.write("d/f", b"hello")?;
+    let err = rmdir_at(&workspace, "d").await.expect_err("not empty");
+    assert_eq!(err.downcast()?, Errno::Notempty);
+    unlink_at(&workspace, "d/f").await?;
+    rmdir_at(&workspace, "d/e").await?;
+    rmdir_at(&workspace, "d").await?;
+    assert!(!workspace.exists("d"));
+
+    let err = mkdir_at(&workspace, "../d")
+        .await
+        .expect_err("outside the directory");
+    assert_eq!(err.downcast()?, Errno::Perm);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn full_disk_errno_reaches_the_guest() -> Result<(), Error> {
+    use wasi_common::snapshots::preview_1::types::Errno;
+
+    // `/dev/full` fails every write with `ENOSPC`, as a full filesystem does.
+    let full = std::fs::OpenOptions::new().write(true).open("/dev/full")?;
+    let f = File::from_cap_std(cap_std::fs::File::from_std(full));
+    let err = f
+        .write_vectored(&[IoSlice::new(b"hello")])
+        .await
+        .expect_err("device is full");
+    assert_eq!(err.downcast()?, Errno::Nospc);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_range() -> Result<(), Error> {
+    use wasi_tokio::SyncRangeFlags;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    f.write_vectored(&[IoSlice::new(&[1; 8192])]).await?;
+
+    // Only the data is written back: this makes none of it durable without a
+    // later fdatasync, so all that can be checked is that it goes through.
+    f.sync_range(4096, 4096, SyncRangeFlags::all()).await?;
+    f.sync_range(0, 0, SyncRangeFlags::WRITE).await?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn io_priority() -> Result<(), Error> {
+    use wasi_common::snapshots::preview_1::types::Errno;
+    use wasi_tokio::IoPriorityClass;
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let mut f = open_scratch_file(&workspace, "f")?.with_inline_threshold(0);
+    f.set_io_priority(IoPriorityClass::BestEffort, 7)?;
+    f.set_io_priority(IoPriorityClass::Idle, 0)?;
+    f.write_vectored_at(&[IoSlice::new(b"hello")], 0).await?;
+    f.datasync().await?;
+    let mut buf = [0; 5];
+    f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(&buf, b"hello");
+
+    let err = f
+        .set_io_priority(IoPriorityClass::BestEffort, 8)
+        .expect_err("no level 8");
+    assert_eq!(err.downcast()?, Errno::Inval);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_through_tokio_io() -> Result<(), Error> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    f.write_vectored(&[IoSlice::new(b"hello world")]).await?;
+
+    let mut reader = f.as_async_read();
+    assert_eq!(reader.seek(SeekFrom::Start(6)).await?, 6);
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).await?;
+    assert_eq!(rest, b"world");
+    assert_eq!(reader.stream_position().await?, 11);
+
+    // The reader moves the file's own offset.
+    drop(reader);
+    assert_eq!(f.stream_position().await?, 11);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zero_length_io() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    f.write_vectored(&[IoSlice::new(b"abc")]).await?;
+    f.seek(SeekFrom::Start(1)).await?;
+
+    assert_eq!(f.write_vectored(&[IoSlice::new(&[])]).await?, 0);
+    assert_eq!(f.write_vectored_at(&[], 0).await?, 0);
+    assert_eq!(f.read_vectored(&mut [IoSliceMut::new(&mut [])]).await?, 0);
+    assert_eq!(f.read_vectored_at(&mut [], 0).await?, 0);
+    assert_eq!(f.peek(&mut []).await?, 0);
+
+    // None of which moved the offset or touched the contents.
+    assert_eq!(f.stream_position().await?, 1);
+    let mut buf = [0; 3];
+    let n = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(&buf[..n as usize], b"abc");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn files_are_cloexec_by_default() -> Result<(), Error> {
+    let workspace =
+        cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let f = open_scratch_file(&workspace, "f")?;
+    assert!(f.is_cloexec()?);
+    f.set_cloexec(false)?;
+    assert!(!f.is_cloexec()?);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn fifo_is_not_seekable() -> Result<(), Error> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use wasi_common::snapshots::preview_1::types::Errno;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("fifo");
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+    // Opening both ends at once keeps the open from waiting for a peer.
+    let fifo = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)?;
+    let f = File::from_cap_std(cap_std::fs::File::from_std(fifo));
+
+    let err = f.seek(SeekFrom::Start(0)).await.expect_err("fifo seek");
+    assert_eq!(err.downcast()?, Errno::Spipe);
+    let mut buf = [0; 8];
+    let err = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await
+        .expect_err("fifo pread");
+    assert_eq!(err.downcast()?, Errno::Spipe);
+    let err = f
+        .write_vectored_at(&[IoSlice::new(b"x")], 0)
+        .await
+        .expect_err("fifo pwrite");
+    assert_eq!(err.downcast()?, Errno::Spipe);
+
+    // Sequential I/O still works.
+    f.write_vectored(&[IoSlice::new(b"piped")]).await?;
+    let n = f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+    assert_eq!(&buf[..n as usize], b"piped");
+    assert_eq!(f.get_filestat().await?.size, 0);
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use anyhow::Error;
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use wasi_common::{file::FileType, WasiFile};
+use wasi_tokio::TokioFsFile;
+
+fn scratch_file() -> Result<TokioFsFile, Error> {
+    let f = tempfile::tempfile()?;
+    Ok(TokioFsFile::new(tokio::fs::File::from_std(f)))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_seek_read() -> Result<(), Error> {
+    let f = scratch_file()?;
+    assert_eq!(f.get_filetype().await?, FileType::RegularFile);
+
+    let n = f.write_vectored(&[IoSlice::new(b"hello world")]).await?;
+    assert_eq!(n, 11);
+    assert_eq!(f.seek(SeekFrom::Current(0)).await?, 11);
+
+    f.seek(SeekFrom::Start(6)).await?;
+    let mut buf = [0; 16];
+    let n = f.read_vectored(&mut [IoSliceMut::new(&mut buf)]).await?;
+    assert_eq!(&buf[..n as usize], b"world");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn positional_io_keeps_offset() -> Result<(), Error> {
+    let f = scratch_file()?;
+    f.write_vectored(&[IoSlice::new(b"hello world")]).await?;
+    f.seek(SeekFrom::Start(2)).await?;
+
+    let mut buf = [0; 5];
+    let n = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 6)
+        .await?;
+    assert_eq!(&buf[..n as usize], b"world");
+    f.write_vectored_at(&[IoSlice::new(b"J")], 0).await?;
+    assert_eq!(f.seek(SeekFrom::Current(0)).await?, 2);
+
+    let mut buf = [0; 3];
+    let n = f.peek(&mut buf).await?;
+    assert_eq!(&buf[..n as usize], b"llo");
+    let mut buf = [0; 16];
+    let n = f
+        .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)
+        .await?;
+    assert_eq!(&buf[..n as usize], b"Jello world");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn set_size_and_stat() -> Result<(), Error> {
+    let f = scratch_file()?;
+    f.write_vectored(&[IoSlice::new(b"hello world")]).await?;
+    assert_eq!(f.get_filestat().await?.size, 11);
+
+    f.set_filestat_size(5).await?;
+    f.datasync().await?;
+    let stat = f.get_filestat().await?;
+    assert_eq!(stat.size, 5);
+    assert_eq!(stat.filetype, FileType::RegularFile);
+    Ok(())
+}
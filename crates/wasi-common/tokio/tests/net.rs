@@ -0,0 +1,1008 @@
+#![cfg(unix)]
+
+use anyhow::Error;
+use std::io::{IoSlice, IoSliceMut, Write};
+use std::time::Duration;
+use wasi_common::{file::FdFlags, snapshots::preview_1::types::Errno, WasiFile};
+use wasi_tokio::{SocketControl, TcpListener, TcpStream, UnixStream};
+
+fn unix_pair() -> Result<(UnixStream, std::os::unix::net::UnixStream), Error> {
+    let (a, b) = std::os::unix::net::UnixStream::pair()?;
+    let a = UnixStream::from_cap_std(cap_std::os::unix::net::UnixStream::from_std(a));
+    Ok((a, b))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_read_then_repoll_sees_the_rest() -> Result<(), Error> {
+    let (stream, mut peer) = unix_pair()?;
+    peer.write_all(b"0123456789")?;
+
+    let mut buf = [0; 4];
+    for expected in [&b"0123"[..], b"4567", b"89"] {
+        tokio::time::timeout(Duration::from_secs(5), stream.readable())
+            .await
+            .expect("data is still queued")?;
+        let n = stream
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await?;
+        assert_eq!(&buf[..n as usize], expected);
+    }
+
+    // Once drained, there's no stale readiness to wake a poll early.
+    let waited = tokio::time::timeout(Duration::from_millis(50), stream.readable()).await;
+    assert!(waited.is_err(), "readable with nothing queued");
+    peer.write_all(b"more")?;
+    tokio::time::timeout(Duration::from_secs(5), stream.readable())
+        .await
+        .expect("new data")?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zero_length_io_returns_at_once() -> Result<(), Error> {
+    let (stream, mut peer) = unix_pair()?;
+
+    // Nothing is queued, so a read which waited for data would time out.
+    let n = tokio::time::timeout(
+        Duration::from_secs(5),
+        stream.read_vectored(&mut [IoSliceMut::new(&mut [])]),
+    )
+    .await
+    .expect("empty read doesn't wait")?;
+    assert_eq!(n, 0);
+    assert_eq!(stream.write_vectored(&[]).await?, 0);
+    assert_eq!(stream.peek(&mut []).await?, 0);
+
+    // Nor does an empty read take anything which is queued.
+    peer.write_all(b"x")?;
+    assert_eq!(stream.read_vectored(&mut []).await?, 0);
+    let mut buf = [0; 1];
+    let n = stream
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .await?;
+    assert_eq!(&buf[..n as usize], b"x");
+
+    Ok(())
+}
+
+#[test]
+fn sockets_are_cloexec_by_default() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let listener = TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(listener));
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+    let (unix, _peer) = unix_pair()?;
+
+    assert!(listener.is_cloexec()?);
+    assert!(stream.is_cloexec()?);
+    assert!(unix.is_cloexec()?);
+
+    stream.set_cloexec(false)?;
+    assert!(!stream.is_cloexec()?);
+    stream.set_cloexec(true)?;
+    assert!(stream.is_cloexec()?);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn recv_with_creds() -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+    use wasi_tokio::UCred;
+
+    let (stream, peer) = unix_pair()?;
+    let sent = UCred {
+        pid: std::process::id() as i32,
+        uid: unsafe { libc::getuid() },
+        gid: unsafe { libc::getgid() },
+    };
+
+    // Send a message with the peer's credentials attached explicitly.
+    let mut data = *b"hello";
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen =
+        unsafe { libc::CMSG_SPACE(std::mem::size_of::<libc::ucred>() as u32) } as _;
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_CREDENTIALS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<libc::ucred>() as u32) as _;
+        let ucred = libc::ucred {
+            pid: sent.pid,
+            uid: sent.uid,
+            gid: sent.gid,
+        };
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast(), ucred);
+    }
+    let n = unsafe { libc::sendmsg(peer.as_raw_fd(), &msg, 0) };
+    assert_eq!(
+        n,
+        data.len() as isize,
+        "{}",
+        std::io::Error::last_os_error()
+    );
+
+    let mut buf = [0; 16];
+    let (n, creds) = tokio::time::timeout(Duration::from_secs(5), stream.recv_with_creds(&mut buf))
+        .await
+        .expect("message was sent")?;
+    assert_eq!(&buf[..n as usize], b"hello");
+    assert_eq!(creds, Some(sent));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_until_leaves_trailing_bytes_queued() -> Result<(), Error> {
+    let (stream, mut peer) = unix_pair()?;
+    peer.write_all(b"HELLO\r\nbody")?;
+
+    assert_eq!(stream.read_until(b'\n', 1024).await?, b"HELLO\r\n");
+
+    let mut buf = [0; 16];
+    let n = stream
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .await?;
+    assert_eq!(&buf[..n as usize], b"body");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_exact_vectored_spans_segments() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    peer.set_nodelay(true)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    let writer = std::thread::spawn(move || -> std::io::Result<()> {
+        for segment in [&b"hea"[..], b"der:pay", b"load", b"!extra"] {
+            peer.write_all(segment)?;
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        Ok(())
+    });
+    let (mut header, mut payload) = ([0; 7], [0; 8]);
+    stream
+        .read_exact_vectored(&mut [IoSliceMut::new(&mut header), IoSliceMut::new(&mut payload)])
+        .await?;
+    assert_eq!(&header, b"header:");
+    assert_eq!(&payload, b"payload!");
+    writer.join().unwrap()?;
+
+    // The peer closes with fewer bytes sent than asked for.
+    let mut rest = [0; 16];
+    let err = stream
+        .read_exact_vectored(&mut [IoSliceMut::new(&mut rest)])
+        .await
+        .unwrap_err();
+    assert_eq!(err.downcast()?, Errno::Io);
+    assert_eq!(&rest[..5], b"extra");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_timeout_expires() -> Result<(), Error> {
+    let (stream, _peer) = unix_pair()?;
+    stream.set_read_timeout(Some(Duration::from_millis(50)))?;
+
+    let mut buf = [0; 16];
+    let err = stream
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .await
+        .expect_err("nothing to read");
+    assert_eq!(err.downcast()?, Errno::Timedout);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_timeout_expires() -> Result<(), Error> {
+    let (mut stream, _peer) = unix_pair()?;
+
+    // Fill the send buffer without blocking, so that the socket stays
+    // unwritable while the peer isn't reading.
+    stream.set_fdflags(FdFlags::NONBLOCK).await?;
+    let chunk = vec![0; 64 * 1024];
+    loop {
+        match stream.write_vectored(&[IoSlice::new(&chunk)]).await {
+            Ok(_) => continue,
+            Err(e) if e.downcast_ref() == Some(&Errno::Again) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    stream.set_write_timeout(Some(Duration::from_millis(50)))?;
+    let err = stream
+        .write_vectored(&[IoSlice::new(&chunk)])
+        .await
+        .expect_err("send buffer is full");
+    assert_eq!(err.downcast()?, Errno::Timedout);
+
+    Ok(())
+}
+
+#[test]
+fn sockopt_round_trip() -> Result<(), Error> {
+    let (stream, _peer) = unix_pair()?;
+
+    let mut buf = [0; 4];
+    let n = stream.get_sockopt(libc::SOL_SOCKET, libc::SO_TYPE, &mut buf)?;
+    assert_eq!(n, 4);
+    assert_eq!(i32::from_ne_bytes(buf), libc::SOCK_STREAM);
+
+    stream.set_sockopt(libc::SOL_SOCKET, libc::SO_KEEPALIVE, &1i32.to_ne_bytes())?;
+    stream.get_sockopt(libc::SOL_SOCKET, libc::SO_KEEPALIVE, &mut buf)?;
+    assert_ne!(i32::from_ne_bytes(buf), 0);
+
+    let err = stream
+        .get_sockopt(libc::SOL_SOCKET, libc::SO_TYPE, &mut [])
+        .expect_err("empty buffer");
+    assert_eq!(err.downcast()?, Errno::Inval);
+
+    let err = stream
+        .set_sockopt(libc::SOL_SOCKET, libc::SO_KEEPALIVE, &[1])
+        .expect_err("not an int");
+    assert_eq!(err.downcast()?, Errno::Inval);
+
+    #[cfg(target_os = "linux")]
+    {
+        let err = stream
+            .set_sockopt(libc::SOL_SOCKET, libc::SO_BINDTODEVICE, b"lo\0")
+            .expect_err("denied option");
+        assert_eq!(err.downcast()?, Errno::Perm);
+        // Only listed options get through, even with an int value, and
+        // even to read.
+        for (level, name) in [
+            (libc::SOL_SOCKET, libc::SO_RCVBUFFORCE),
+            (libc::SOL_SOCKET, libc::SO_ATTACH_REUSEPORT_CBPF),
+            (libc::SOL_SOCKET, libc::SO_ATTACH_BPF),
+            (libc::SOL_IP, libc::IP_TRANSPARENT),
+        ] {
+            let err = stream
+                .set_sockopt(level, name, &1i32.to_ne_bytes())
+                .expect_err("unlisted option");
+            assert_eq!(err.downcast()?, Errno::Perm);
+        }
+        let err = stream
+            .get_sockopt(libc::SOL_SOCKET, libc::SO_PEERCRED, &mut [0; 12])
+            .expect_err("unlisted option");
+        assert_eq!(err.downcast()?, Errno::Perm);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tcp_stream_addresses() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (_server, client_addr) = listener.accept()?;
+
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+    assert_eq!(stream.peer_addr()?, listener.local_addr()?);
+    assert_eq!(stream.local_addr()?, client_addr);
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+#[test]
+fn cork_and_uncork() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    stream.set_cork(true)?;
+    #[cfg(target_os = "linux")]
+    {
+        let mut buf = [0; 4];
+        stream.get_sockopt(libc::IPPROTO_TCP, libc::TCP_CORK, &mut buf)?;
+        assert_eq!(i32::from_ne_bytes(buf), 1);
+    }
+    stream.set_cork(false)?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn draining_listener_stops_accepting() -> Result<(), Error> {
+    use std::io::Read;
+    use wasi_tokio::DrainingListener;
+
+    let std_listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = std_listener.local_addr()?;
+    let listener = DrainingListener::new(TcpListener::from_cap_std(
+        cap_std::net::TcpListener::from_std(std_listener),
+    ));
+    let handle = listener.handle();
+
+    let mut client = std::net::TcpStream::connect(addr)?;
+    let accepted = listener.sock_accept(FdFlags::empty()).await?;
+
+    // An accept waiting for a connection is cut off when draining begins.
+    let (result, ()) = tokio::join!(listener.sock_accept(FdFlags::empty()), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        handle.begin_drain();
+    });
+    let err = result.err().expect("accept fails once draining");
+    assert_eq!(err.downcast()?, Errno::Canceled);
+    // So does one with a connection queued.
+    let _late = std::net::TcpStream::connect(addr)?;
+    let err = listener
+        .sock_accept(FdFlags::empty())
+        .await
+        .err()
+        .expect("accept fails once draining");
+    assert_eq!(err.downcast()?, Errno::Canceled);
+
+    // The connection accepted before still works both ways.
+    client.write_all(b"ping")?;
+    let mut buf = [0; 4];
+    tokio::time::timeout(Duration::from_secs(5), accepted.readable())
+        .await
+        .expect("data arrives")?;
+    let n = accepted
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .await?;
+    assert_eq!(&buf[..n as usize], b"ping");
+    accepted.write_vectored(&[IoSlice::new(b"pong")]).await?;
+    client.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"pong");
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn recv_lowat_delays_readiness() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    peer.set_nodelay(true)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    stream.set_recv_lowat(8)?;
+    assert_eq!(stream.recv_lowat()?, 8);
+
+    peer.write_all(b"half")?;
+    let waited = tokio::time::timeout(Duration::from_millis(100), stream.readable()).await;
+    assert!(waited.is_err(), "readable below the low-water mark");
+
+    peer.write_all(b"full")?;
+    tokio::time::timeout(Duration::from_secs(5), stream.readable())
+        .await
+        .expect("readable at the low-water mark")?;
+    let mut buf = [0; 16];
+    let n = stream
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .await?;
+    assert_eq!(&buf[..n as usize], b"halffull");
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn attach_and_detach_filter() -> Result<(), Error> {
+    use wasi_tokio::SockFilter;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    // `BPF_RET | BPF_K`, keeping the whole of every packet.
+    let accept_all = [SockFilter {
+        code: 0x06,
+        jt: 0,
+        jf: 0,
+        k: u32::MAX,
+    }];
+    stream.attach_filter(&accept_all)?;
+    peer.write_all(b"through")?;
+    tokio::time::timeout(Duration::from_secs(5), stream.readable())
+        .await
+        .expect("filtered packets still arrive")?;
+    let mut buf = [0; 16];
+    let n = stream
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .await?;
+    assert_eq!(&buf[..n as usize], b"through");
+
+    stream.detach_filter()?;
+    let err = stream.detach_filter().expect_err("already detached");
+    assert_eq!(err.downcast()?, Errno::Noent);
+    let err = stream.attach_filter(&[]).expect_err("empty program");
+    assert_eq!(err.downcast()?, Errno::Inval);
+
+    // Guests can't attach one through the raw option.
+    let err = stream
+        .set_sockopt(libc::SOL_SOCKET, libc::SO_ATTACH_FILTER, &[0; 16])
+        .expect_err("denied");
+    assert_eq!(err.downcast()?, Errno::Perm);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn set_quickack() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    stream.set_quickack(true)?;
+    stream.set_quickack(false)?;
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn congestion_control() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    // Reno is always built in, and always allowed.
+    stream.set_congestion_control("reno")?;
+    assert_eq!(stream.congestion_control()?, "reno");
+
+    let err = stream
+        .set_congestion_control("no-such-algo")
+        .expect_err("unknown algorithm");
+    assert_eq!(err.downcast()?, Errno::Noent);
+    let err = stream
+        .set_congestion_control("reno\0cubic")
+        .expect_err("embedded NUL");
+    assert_eq!(err.downcast()?, Errno::Inval);
+    assert_eq!(stream.congestion_control()?, "reno");
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn set_priority() -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    // Priorities up to 6 don't need any privileges.
+    stream.set_priority(5)?;
+    // Guests can't read it back through `SocketControl`, so go around it.
+    let fd = stream.pollable().expect("sockets are pollable").as_raw_fd();
+    let mut priority: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PRIORITY,
+            (&mut priority as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+    assert_eq!(ret, 0);
+    assert_eq!(priority, 5);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn user_timeout() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    stream.set_user_timeout(Duration::from_millis(1500))?;
+    assert_eq!(stream.user_timeout()?, Duration::from_millis(1500));
+    stream.set_user_timeout(Duration::ZERO)?;
+    assert_eq!(stream.user_timeout()?, Duration::ZERO);
+
+    let err = stream
+        .set_user_timeout(Duration::from_secs(u64::MAX))
+        .expect_err("too long");
+    assert_eq!(err.downcast()?, Errno::Inval);
+
+    Ok(())
+}
+
+#[test]
+fn incoming_cpu() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let _client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (server, _) = listener.accept()?;
+    let server = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(server));
+    let listener = TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(listener));
+
+    #[cfg(target_os = "linux")]
+    {
+        listener.set_incoming_cpu(0)?;
+        // Which CPU the handshake landed on is up to the host.
+        if let Some(cpu) = server.incoming_cpu()? {
+            assert!(cpu >= 0);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let err = server.incoming_cpu().expect_err("only on Linux");
+        assert_eq!(err.downcast()?, Errno::Notsup);
+        let err = listener.set_incoming_cpu(0).expect_err("only on Linux");
+        assert_eq!(err.downcast()?, Errno::Notsup);
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn send_buffer_available_shrinks_as_it_fills() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    // The peer never reads, so once its receive buffer is full, writes stay
+    // queued in ours.
+    let _server = listener.accept()?;
+    client.set_nonblocking(true)?;
+    let mut writer = client.try_clone()?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    let empty = stream.send_buffer_available()?;
+    assert!(empty > 0);
+    let chunk = [0; 4096];
+    loop {
+        match writer.write(&chunk) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    assert!(stream.send_buffer_available()? < empty);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn path_mtu_of_connected_socket() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    stream.set_mtu_discover(wasi_tokio::MtuDiscover::Do)?;
+    let mut buf = [0; 4];
+    stream.get_sockopt(libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, &mut buf)?;
+    assert_eq!(i32::from_ne_bytes(buf), libc::IP_PMTUDISC_DO);
+    // Loopback's MTU is large, and at least IPv4's minimum.
+    assert!(stream.path_mtu()? >= 576);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn accept_queue_len_counts_pending_connections() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let std_listener = listener.try_clone()?;
+    let listener = TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(listener));
+    assert_eq!(listener.accept_queue_len()?, 0);
+
+    // The kernel completes the handshakes, and queues the connections, with
+    // nobody calling accept.
+    let _clients = (0..3)
+        .map(|_| std::net::TcpStream::connect(addr))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(listener.accept_queue_len()?, 3);
+
+    let _accepted = std_listener.accept()?;
+    assert_eq!(listener.accept_queue_len()?, 2);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn send_more_holds_data_for_the_final_write() -> Result<(), Error> {
+    use std::io::Read;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    assert_eq!(stream.send_more(b"hello")?, 5);
+    assert_eq!(stream.send_more(b", ")?, 2);
+    // Nothing has been pushed to the peer yet.
+    peer.set_nonblocking(true)?;
+    let mut buf = [0; 32];
+    let err = peer.read(&mut buf).expect_err("held back");
+    assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+
+    peer.set_nonblocking(false)?;
+    stream.write_vectored(&[IoSlice::new(b"world")]).await?;
+    let mut received = [0; 12];
+    peer.read_exact(&mut received)?;
+    assert_eq!(&received, b"hello, world");
+
+    Ok(())
+}
+
+#[test]
+fn sockets_cannot_be_made_blocking() -> Result<(), Error> {
+    let (stream, _peer) = unix_pair()?;
+    stream.set_nonblocking(true)?;
+    assert!(stream.nonblocking()?);
+
+    let err = stream
+        .set_nonblocking(false)
+        .expect_err("blocking is rejected");
+    assert_eq!(err.downcast()?, Errno::Notsup);
+    assert!(stream.nonblocking()?);
+
+    Ok(())
+}
+
+#[test]
+fn tcp_stream_is_connected() -> Result<(), Error> {
+    use std::os::unix::io::FromRawFd;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+    assert!(stream.is_connected());
+
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_STREAM, 0) };
+    assert!(fd >= 0);
+    let unconnected = unsafe { std::net::TcpStream::from_raw_fd(fd) };
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(unconnected));
+    assert!(!stream.is_connected());
+
+    Ok(())
+}
+
+#[test]
+fn tcp_stream_probe_detects_closed_peer() -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+
+    fn connect(
+        listener: &std::net::TcpListener,
+    ) -> Result<(TcpStream, std::net::TcpStream), Error> {
+        let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+        let (peer, _) = listener.accept()?;
+        let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+        Ok((stream, peer))
+    }
+
+    // The peer's FIN or RST arrives asynchronously, so give it a moment.
+    fn probe_until_dead(stream: &TcpStream) -> Result<bool, Error> {
+        for _ in 0..100 {
+            if !stream.probe()? {
+                return Ok(false);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Ok(true)
+    }
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+
+    // An idle connection is alive, and so is one with data pending, which
+    // the probe leaves in place.
+    let (stream, mut peer) = connect(&listener)?;
+    assert!(stream.probe()?);
+    peer.write_all(b"x")?;
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(stream.probe()?);
+    assert_eq!(stream.num_ready_bytes()?, 1);
+
+    // A clean close.
+    let (stream, peer) = connect(&listener)?;
+    drop(peer);
+    assert!(!probe_until_dead(&stream)?);
+
+    // A reset, from closing with a zero linger time.
+    let (stream, peer) = connect(&listener)?;
+    let linger = libc::linger {
+        l_onoff: 1,
+        l_linger: 0,
+    };
+    let ret = unsafe {
+        libc::setsockopt(
+            peer.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_LINGER,
+            (&linger as *const libc::linger).cast(),
+            std::mem::size_of::<libc::linger>() as libc::socklen_t,
+        )
+    };
+    assert_eq!(ret, 0);
+    drop(peer);
+    assert!(!probe_until_dead(&stream)?);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flush_to_peer_drains_send_queue() -> Result<(), Error> {
+    use std::io::Read;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    #[cfg(target_os = "linux")]
+    let client_fd = std::os::unix::io::AsRawFd::as_raw_fd(&client);
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    // Small enough for the peer's receive buffer to take it all unread.
+    let data = vec![0x5a; 32 * 1024];
+    let mut written = 0;
+    while written < data.len() {
+        written += stream
+            .write_vectored(&[IoSlice::new(&data[written..])])
+            .await? as usize;
+    }
+    stream.flush_to_peer().await?;
+
+    #[cfg(target_os = "linux")]
+    {
+        let mut queued: libc::c_int = -1;
+        assert_eq!(
+            unsafe { libc::ioctl(client_fd, libc::TIOCOUTQ, &mut queued) },
+            0
+        );
+        assert_eq!(queued, 0);
+    }
+
+    let mut received = vec![0; data.len()];
+    peer.read_exact(&mut received)?;
+    assert_eq!(received, data);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shutdown_and_drain() -> Result<(), Error> {
+    use std::io::Read;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+
+    // The peer sends its trailing data once it sees EOF, then closes.
+    let peer = std::thread::spawn(move || -> std::io::Result<()> {
+        let mut rest = Vec::new();
+        peer.read_to_end(&mut rest)?;
+        assert!(rest.is_empty());
+        peer.write_all(b"last words")?;
+        Ok(())
+    });
+    let drained = stream.shutdown_and_drain(Duration::from_secs(5)).await?;
+    assert_eq!(drained, b"last words");
+    peer.join().unwrap()?;
+
+    // A peer which never closes is given up on at the timeout.
+    let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    let stream = TcpStream::from_cap_std(cap_std::net::TcpStream::from_std(client));
+    peer.write_all(b"partial")?;
+    let drained = stream
+        .shutdown_and_drain(Duration::from_millis(100))
+        .await?;
+    assert_eq!(drained, b"partial");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn accept_reports_peer_addr() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let listener = TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(listener));
+
+    let client = std::net::TcpStream::connect(addr)?;
+    let (stream, peer_addr) = listener.accept(FdFlags::empty()).await?;
+    assert_eq!(peer_addr, client.local_addr()?);
+    assert_eq!(stream.peer_addr()?, peer_addr);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn accept_timeout() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let listener = TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(listener));
+
+    let accepted = listener
+        .accept_timeout(FdFlags::empty(), Duration::from_millis(50))
+        .await?;
+    assert!(accepted.is_none());
+
+    let client = std::net::TcpStream::connect(addr)?;
+    let (_stream, peer_addr) = listener
+        .accept_timeout(FdFlags::empty(), Duration::from_secs(5))
+        .await?
+        .expect("a connection is waiting");
+    assert_eq!(peer_addr, client.local_addr()?);
+
+    Ok(())
+}
+
+// With `NONBLOCK` set, an accept which loses the race for a connection
+// still waits out its timeout, rather than failing with `EAGAIN`.
+#[tokio::test(flavor = "multi_thread")]
+async fn nonblocking_accept_timeout_losing_race() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let mut listener = TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(listener));
+    listener.set_fdflags(FdFlags::NONBLOCK).await?;
+
+    let timeout = Duration::from_millis(200);
+    let (a, b, client) = tokio::join!(
+        listener.accept_timeout(FdFlags::empty(), timeout),
+        listener.accept_timeout(FdFlags::empty(), timeout),
+        async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            std::net::TcpStream::connect(addr)
+        }
+    );
+    let (a, b, _client) = (a?, b?, client?);
+    assert!(a.is_some() != b.is_some(), "exactly one accept wins");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nonblocking_accept_does_not_wait() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let mut listener = TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(listener));
+    assert_eq!(listener.get_fdflags().await?, FdFlags::empty());
+
+    listener.set_fdflags(FdFlags::NONBLOCK).await?;
+    assert_eq!(listener.get_fdflags().await?, FdFlags::NONBLOCK);
+    let err = tokio::time::timeout(
+        Duration::from_secs(5),
+        listener.sock_accept(FdFlags::empty()),
+    )
+    .await
+    .expect("a nonblocking accept returns at once")
+    .err()
+    .expect("nothing is queued");
+    assert_eq!(err.downcast()?, Errno::Again);
+
+    let _client = std::net::TcpStream::connect(addr)?;
+    let stream = listener.sock_accept(FdFlags::empty()).await?;
+    assert_eq!(stream.get_fdflags().await?, FdFlags::empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unsupported_socket_fdflags() -> Result<(), Error> {
+    let (mut stream, _peer) = unix_pair()?;
+    let err = stream
+        .set_fdflags(FdFlags::DSYNC)
+        .await
+        .expect_err("DSYNC on a socket");
+    assert_eq!(err.downcast()?, Errno::Notsup);
+
+    stream.set_fdflags(FdFlags::NONBLOCK).await?;
+    assert_eq!(stream.get_fdflags().await?, FdFlags::NONBLOCK);
+    Ok(())
+}
+
+#[cfg(all(feature = "sctp", target_os = "linux"))]
+#[tokio::test(flavor = "multi_thread")]
+async fn sctp_round_trip() -> Result<(), Error> {
+    use wasi_tokio::{SctpListener, SctpStream};
+
+    // Kernels without the SCTP module loaded can't run this test.
+    let listener = match SctpListener::bind("127.0.0.1:0".parse()?) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("skipping: SCTP is unavailable: {}", e);
+            return Ok(());
+        }
+    };
+    let client = SctpStream::connect(listener.local_addr()?).await?;
+    let (server, _peer_addr) = listener.accept(FdFlags::empty()).await?;
+
+    client.write_vectored(&[IoSlice::new(b"hello")]).await?;
+    let mut buf = [0; 16];
+    let n = server
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .await?;
+    assert_eq!(&buf[..n as usize], b"hello");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn reuseport_group_shares_one_port() -> Result<(), Error> {
+    let group =
+        TcpListener::reuseport_group("127.0.0.1:0".parse()?, 3, cap_std::ambient_authority())?;
+    assert_eq!(group.len(), 3);
+    let addr = group[0].local_addr()?;
+    assert_ne!(addr.port(), 0);
+    for listener in &group {
+        assert_eq!(listener.local_addr()?, addr);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "interfaces")]
+#[test]
+fn local_interfaces_include_loopback() -> Result<(), Error> {
+    use wasi_tokio::net::{local_interfaces, InterfaceFlags};
+
+    let interfaces = local_interfaces()?;
+    let loopback = interfaces
+        .iter()
+        .find(|i| i.addr == std::net::Ipv4Addr::LOCALHOST)
+        .expect("127.0.0.1 is listed");
+    assert!(loopback
+        .flags
+        .contains(InterfaceFlags::UP | InterfaceFlags::LOOPBACK));
+    assert_eq!(
+        loopback.netmask,
+        Some(std::net::Ipv4Addr::new(255, 0, 0, 0).into())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn accept_is_canceled_by_runtime_shutdown() -> Result<(), Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let listener = TcpListener::from_cap_std(cap_std::net::TcpListener::from_std(listener));
+    let mut accept = Box::pin(listener.accept(FdFlags::empty()));
+
+    // Leave the accept waiting on the first runtime's reactor, then shut it
+    // down.
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let waited = rt.block_on(tokio::time::timeout(Duration::from_millis(50), &mut accept));
+    assert!(waited.is_err(), "nothing connected, so the accept waits");
+    drop(rt);
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let err = rt
+        .block_on(accept)
+        .err()
+        .expect("accept fails once its reactor is gone");
+    assert_eq!(err.downcast::<Errno>()?, Errno::Canceled);
+
+    // An accept which only starts waiting once the runtime has gone fails to
+    // register with its reactor, and is canceled likewise.
+    let handle = rt.handle().clone();
+    drop(rt);
+    let _enter = handle.enter();
+    let err = wiggle::run_in_dummy_executor(listener.accept(FdFlags::empty()))
+        .expect("fails without waiting")
+        .err()
+        .expect("accept fails once its reactor is gone");
+    assert_eq!(err.downcast::<Errno>()?, Errno::Canceled);
+    Ok(())
+}
@@ -161,3 +161,62 @@ async fn stdio_readable() -> Result<(), Error> {
 
     Ok(())
 }
+
+// Always ready, noting down every time it's checked.
+struct AlwaysReady {
+    id: u64,
+    checked: std::sync::Arc<std::sync::Mutex<Vec<u64>>>,
+}
+
+#[wiggle::async_trait]
+impl WasiFile for AlwaysReady {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    async fn get_filetype(&self) -> Result<wasi_common::file::FileType, wasi_common::Error> {
+        Ok(wasi_common::file::FileType::Unknown)
+    }
+    async fn readable(&self) -> Result<(), wasi_common::Error> {
+        self.checked.lock().unwrap().push(self.id);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn fair_sched_rotates_order() -> Result<(), Error> {
+    let checked: std::sync::Arc<std::sync::Mutex<Vec<u64>>> = std::sync::Arc::default();
+    let files = (0..3)
+        .map(|id| AlwaysReady {
+            id,
+            checked: std::sync::Arc::clone(&checked),
+        })
+        .collect::<Vec<_>>();
+    let sched = wasi_tokio::sched::fair_sched_ctx();
+
+    let mut firsts = Vec::new();
+    for _ in 0..4 {
+        let mut poll = Poll::new();
+        for f in &files {
+            poll.subscribe_read(f, Userdata::from(f.id));
+        }
+        sched.poll_oneoff(&mut poll).await?;
+        // Everything ready is still reported, in subscription order.
+        let ready = poll
+            .results()
+            .into_iter()
+            .map(|(r, ud)| match r {
+                SubscriptionResult::Read(Ok(_)) => u64::from(ud),
+                _ => panic!("expected a read result, got: {:?}", r),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ready, [0, 1, 2]);
+
+        let mut checked = checked.lock().unwrap();
+        firsts.push(checked[0]);
+        checked.clear();
+    }
+    assert_eq!(firsts, [0, 1, 2, 0]);
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+#![cfg(unix)]
+
+// This leaves `SIGPIPE` to kill the process, so it lives in its own test
+// binary.
+
+use anyhow::Error;
+use std::io::IoSlice;
+use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+use wasi_tokio::UnixStream;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn socket_to_closed_peer_is_epipe() -> Result<(), Error> {
+    // Put `SIGPIPE` back to killing the process, as it is for a host which
+    // isn't a Rust binary. Sockets shouldn't need it ignored.
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+
+    let (a, b) = std::os::unix::net::UnixStream::pair()?;
+    let a = UnixStream::from_cap_std(cap_std::os::unix::net::UnixStream::from_std(a));
+    drop(b);
+
+    let err = a
+        .write_vectored(&[IoSlice::new(b"hello")])
+        .await
+        .expect_err("the peer is gone");
+    assert_eq!(err.downcast()?, Errno::Pipe);
+    Ok(())
+}
@@ -0,0 +1,32 @@
+#![cfg(unix)]
+
+// This redirects the process's standard output and changes how it handles
+// `SIGPIPE`, so it lives in its own test binary.
+
+use anyhow::Error;
+use std::io::IoSlice;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use wasi_common::{snapshots::preview_1::types::Errno, WasiFile};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stdout_to_closed_pipe_is_epipe() -> Result<(), Error> {
+    // Put `SIGPIPE` back to killing the process, as it is for a host which
+    // isn't a Rust binary, and then opt in to ignoring it.
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+    wasi_tokio::stdio::ignore_sigpipe();
+    let stdout = wasi_tokio::stdout();
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let (reader, writer) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    drop(reader);
+
+    let saved = unsafe { OwnedFd::from_raw_fd(libc::dup(1)) };
+    assert_eq!(unsafe { libc::dup2(writer.as_raw_fd(), 1) }, 1);
+    let result = stdout.write_vectored(&[IoSlice::new(b"hello")]).await;
+    assert_eq!(unsafe { libc::dup2(saved.as_raw_fd(), 1) }, 1);
+
+    let err = result.expect_err("the reader is gone");
+    assert_eq!(err.downcast()?, Errno::Pipe);
+    Ok(())
+}